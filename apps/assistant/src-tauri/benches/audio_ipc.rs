@@ -0,0 +1,61 @@
+// Compares the old JSON number-array path for audio payloads with raw binary IPC.
+//
+// Run with `cargo bench --bench audio_ipc`. The sizes correspond to 16kHz mono 16-bit
+// recordings of 5 and 30 seconds.
+use assistant_lib::audio_ipc;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tauri::ipc::{InvokeBody, IpcResponse};
+
+const RECORDING_SECONDS: [usize; 2] = [5, 30];
+const BYTES_PER_SECOND: usize = 16_000 * 2;
+
+fn sample_audio(seconds: usize) -> Vec<u8> {
+    (0..seconds * BYTES_PER_SECOND).map(|i| (i % 251) as u8).collect()
+}
+
+// Frontend -> backend: `invoke_stt_transcription` arguments
+fn bench_upload(c: &mut Criterion) {
+    let mut group = c.benchmark_group("upload_audio");
+    for seconds in RECORDING_SECONDS {
+        let audio = sample_audio(seconds);
+        group.throughput(Throughput::Bytes(audio.len() as u64));
+
+        // What the webview sends for `{ audioData: Array.from(uint8Array) }`
+        let json_payload = serde_json::to_string(&serde_json::json!({ "audioData": audio })).unwrap();
+        group.bench_with_input(BenchmarkId::new("json_number_array", seconds), &json_payload, |b, payload| {
+            b.iter(|| {
+                let body = InvokeBody::Json(serde_json::from_str(black_box(payload)).unwrap());
+                audio_ipc::bytes_from_body(&body, "audioData").unwrap()
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("raw_binary", seconds), &audio, |b, audio| {
+            b.iter(|| {
+                let body = InvokeBody::Raw(black_box(audio).clone());
+                audio_ipc::bytes_from_body(&body, "audioData").unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+// Backend -> frontend: `synthesize_speech` return value
+fn bench_download(c: &mut Criterion) {
+    let mut group = c.benchmark_group("download_audio");
+    for seconds in RECORDING_SECONDS {
+        let audio = sample_audio(seconds);
+        group.throughput(Throughput::Bytes(audio.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("json_number_array", seconds), &audio, |b, audio| {
+            b.iter(|| serde_json::to_string(black_box(audio)).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("raw_binary", seconds), &audio, |b, audio| {
+            b.iter(|| tauri::ipc::Response::new(black_box(audio).clone()).body().unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_upload, bench_download);
+criterion_main!(benches);
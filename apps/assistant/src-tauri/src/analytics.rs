@@ -0,0 +1,145 @@
+// analytics.rs
+use chrono::{DateTime, FixedOffset};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::workflow_logger::{self, RecordStatus, WorkflowEventType, WorkflowLogRecord};
+
+/// Name under which whole-workflow (END event) durations are reported.
+pub const END_TO_END_STAGE: &str = "END_TO_END";
+
+/// Latency distribution of one stage over the report window.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StageLatencyStats {
+    pub stage: String,
+    pub count: usize,
+    pub error_count: usize,
+    pub error_rate: f64,
+    pub min_us: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct LatencyReport {
+    pub since: Option<DateTime<FixedOffset>>,
+    pub until: DateTime<FixedOffset>,
+    pub workflows: usize,
+    pub stages: Vec<StageLatencyStats>, // Sorted by stage name
+    pub end_to_end: Option<StageLatencyStats>,
+}
+
+/// Computes per-stage latency statistics from workflow log records.
+///
+/// # Arguments
+/// * `records` - Records as returned by `workflow_logger::read_log_file`.
+/// * `since` - Only records at or after this time are included (`None` for all history).
+/// * `until` - Only records before this time are included.
+pub fn compute_latency_report(records: &[WorkflowLogRecord], since: Option<DateTime<FixedOffset>>, until: DateTime<FixedOffset>) -> LatencyReport {
+    // (durations, error count) per stage
+    let mut per_stage: BTreeMap<String, (Vec<u64>, usize)> = BTreeMap::new();
+    let mut end_to_end: (Vec<u64>, usize) = (Vec::new(), 0);
+    let mut workflows = std::collections::HashSet::new();
+
+    let in_window = |record: &&WorkflowLogRecord| {
+        since.is_none_or(|since| record.timestamp >= since) && record.timestamp < until
+    };
+
+    for record in records.iter().filter(in_window) {
+        let is_error = record.status == Some(RecordStatus::Error);
+        match record.event_type {
+            WorkflowEventType::Stage => {
+                let (Some(stage), Some(duration_us)) = (&record.stage, record.duration_us) else { continue };
+                let entry = per_stage.entry(stage.clone()).or_default();
+                entry.0.push(duration_us);
+                if is_error {
+                    entry.1 += 1;
+                }
+                workflows.insert(record.workflow_id.as_str());
+            }
+            WorkflowEventType::End => {
+                if let Some(duration_us) = record.duration_us {
+                    end_to_end.0.push(duration_us);
+                    if is_error {
+                        end_to_end.1 += 1;
+                    }
+                }
+                workflows.insert(record.workflow_id.as_str());
+            }
+            WorkflowEventType::Start => {}
+        }
+    }
+
+    LatencyReport {
+        since,
+        until,
+        workflows: workflows.len(),
+        stages: per_stage.into_iter()
+            .filter_map(|(stage, (durations, errors))| summarize(&stage, durations, errors))
+            .collect(),
+        end_to_end: summarize(END_TO_END_STAGE, end_to_end.0, end_to_end.1),
+    }
+}
+
+/// Reads a workflow log file and computes the latency report for the records from
+/// `since` (or the whole file if `since` is `None`) until `until`.
+pub fn latency_report_from_file(log_file: &Path, since: Option<DateTime<FixedOffset>>, until: DateTime<FixedOffset>) -> Result<LatencyReport, String> {
+    // Includes the rotated segments; empty if no workflows have been logged yet
+    let records = workflow_logger::read_log_history(log_file)?;
+    Ok(compute_latency_report(&records, since, until))
+}
+
+fn summarize(stage: &str, mut durations: Vec<u64>, error_count: usize) -> Option<StageLatencyStats> {
+    if durations.is_empty() {
+        return None;
+    }
+    durations.sort_unstable();
+    Some(StageLatencyStats {
+        stage: stage.to_string(),
+        count: durations.len(),
+        error_count,
+        error_rate: error_count as f64 / durations.len() as f64,
+        min_us: durations[0],
+        p50_us: percentile(&durations, 50.0),
+        p90_us: percentile(&durations, 90.0),
+        p99_us: percentile(&durations, 99.0),
+        max_us: durations[durations.len() - 1],
+    })
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[u64], percent: f64) -> u64 {
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Formats the report as a plain-text table (milliseconds) for the command line.
+pub fn format_table(report: &LatencyReport) -> String {
+    let mut out = format!(
+        "Workflows: {} | Window: {} .. {}\n",
+        report.workflows,
+        report.since.map(|s| s.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| "all".to_string()),
+        report.until.format("%Y-%m-%d %H:%M")
+    );
+    out.push_str(&format!(
+        "{:<24} {:>7} {:>7} {:>10} {:>10} {:>10} {:>10} {:>10}\n",
+        "STAGE", "COUNT", "ERR%", "MIN ms", "P50 ms", "P90 ms", "P99 ms", "MAX ms"
+    ));
+    for stats in report.stages.iter().chain(report.end_to_end.iter()) {
+        out.push_str(&format!(
+            "{:<24} {:>7} {:>6.1}% {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}\n",
+            stats.stage,
+            stats.count,
+            stats.error_rate * 100.0,
+            stats.min_us as f64 / 1000.0,
+            stats.p50_us as f64 / 1000.0,
+            stats.p90_us as f64 / 1000.0,
+            stats.p99_us as f64 / 1000.0,
+            stats.max_us as f64 / 1000.0
+        ));
+    }
+    out
+}
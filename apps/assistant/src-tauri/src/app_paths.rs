@@ -0,0 +1,32 @@
+// app_paths.rs
+//
+// Resolves the same per-app directories as Tauri's path resolver, for code that runs
+// without an AppHandle (e.g. command-line subcommands).
+use std::path::PathBuf;
+
+/// Must match `identifier` in tauri.conf.json.
+pub const APP_IDENTIFIER: &str = "ai.mivis.assistant";
+
+/// Equivalent of `app_handle.path().app_log_dir()`.
+pub fn app_log_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "macos") {
+        dirs::home_dir().map(|dir| dir.join("Library/Logs").join(APP_IDENTIFIER))
+    } else {
+        dirs::data_local_dir().map(|dir| dir.join(APP_IDENTIFIER).join("logs"))
+    }
+}
+
+/// Equivalent of `app_handle.path().app_config_dir()`.
+pub fn app_config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+/// Equivalent of `app_handle.path().app_data_dir()`.
+pub fn app_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+/// Equivalent of `app_handle.path().app_cache_dir()`.
+pub fn app_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
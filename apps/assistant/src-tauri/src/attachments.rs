@@ -0,0 +1,101 @@
+// attachments.rs
+//
+// Turns a local file into a content part of a chat message. Only files inside the app
+// data directory can be attached: it is the `$APPDATA` scope the frontend may write to,
+// so a picked file is copied there first. Images are downscaled and sent inline as a
+// base64 data URL; text documents are sent as an excerpt.
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader};
+
+use crate::chathandle::ContentPart;
+use crate::config::AttachmentConfig;
+use crate::error::AssistantError;
+
+/// Reads an attachment.
+///
+/// # Arguments
+/// * `path` - The file, relative to `scope` or absolute.
+/// * `scope` - The directory the file must be in (the app data directory).
+/// * `config` - The size limits of attachments.
+///
+/// # Returns
+/// An image part for an image and a text part for a text document, or `InvalidInput`
+/// for a file outside of `scope`, too large, or of another kind.
+pub fn attach(path: &Path, scope: &Path, config: &AttachmentConfig) -> Result<ContentPart, AssistantError> {
+    // Resolve `..` and symbolic links before checking the scope
+    let scope = scope.canonicalize()
+        .map_err(|e| AssistantError::io(format!("Failed to open the attachment directory {}", scope.display()), e))?;
+    let path = scope.join(path).canonicalize()
+        .map_err(|e| AssistantError::io(format!("Failed to open {}", path.display()), e))?;
+    if !path.starts_with(&scope) {
+        return Err(AssistantError::InvalidInput(format!("{} is outside of the app data directory", path.display())));
+    }
+
+    let size = fs::metadata(&path).map_err(|e| AssistantError::io(format!("Failed to read {}", path.display()), e))?.len();
+    if size > config.max_file_bytes {
+        return Err(AssistantError::InvalidInput(format!(
+            "{} is {} bytes, more than the {} bytes an attachment may have", path.display(), size, config.max_file_bytes
+        )));
+    }
+
+    // Images are recognized by their content, whatever their extension
+    let reader = ImageReader::open(&path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| AssistantError::io(format!("Failed to read {}", path.display()), e))?;
+    if reader.format().is_some() {
+        let image = reader.decode()
+            .map_err(|e| AssistantError::InvalidInput(format!("{} is not a readable image: {}", path.display(), e)))?;
+        let (mime_type, data) = encode_image(image, config)?;
+        tracing::debug!(bytes = data.len(), "Attached image {}", path.display());
+        return Ok(ContentPart::base64_image(mime_type, &BASE64.encode(data)));
+    }
+
+    let bytes = fs::read(&path).map_err(|e| AssistantError::io(format!("Failed to read {}", path.display()), e))?;
+    let text = String::from_utf8(bytes)
+        .map_err(|_| AssistantError::InvalidInput(format!("{} is neither an image nor a text document", path.display())))?;
+    let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    Ok(ContentPart::text(excerpt(&name, text.trim_start_matches('\u{feff}'), config.max_excerpt_chars)))
+}
+
+// Downscales the image to `max_image_side` and re-encodes it: as PNG if it has
+// transparency, otherwise as a (much smaller) JPEG
+fn encode_image(image: DynamicImage, config: &AttachmentConfig) -> Result<(&'static str, Vec<u8>), AssistantError> {
+    let side = config.max_image_side.max(1);
+    let image = if image.width() > side || image.height() > side {
+        image.resize(side, side, FilterType::Triangle) // Keeps the aspect ratio
+    } else {
+        image
+    };
+
+    let mut encoded = Vec::new();
+    let mime_type = if image.color().has_alpha() {
+        image.write_to(&mut Cursor::new(&mut encoded), ImageFormat::Png)
+            .map_err(|e| AssistantError::Internal(format!("Failed to encode the image: {}", e)))?;
+        "image/png"
+    } else {
+        JpegEncoder::new_with_quality(&mut encoded, config.jpeg_quality.clamp(1, 100))
+            .encode_image(&image.to_rgb8())
+            .map_err(|e| AssistantError::Internal(format!("Failed to encode the image: {}", e)))?;
+        "image/jpeg"
+    };
+    Ok((mime_type, encoded))
+}
+
+// The start of a document, labelled with its name so the model can refer to it
+fn excerpt(name: &str, text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    let mut excerpt = format!("File {}:\n```\n", name);
+    excerpt.extend(text.chars().take(max_chars));
+    excerpt.push_str("\n```");
+    if total > max_chars {
+        excerpt.push_str(&format!("\n(Cut off after {} of {} characters)", max_chars, total));
+    }
+    excerpt
+}
@@ -0,0 +1,275 @@
+// audio_format.rs
+use std::process::Stdio;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+/// Sample rate assumed for headerless PCM returned by OpenAI-compatible TTS services.
+pub const DEFAULT_PCM_SAMPLE_RATE: u32 = 24000;
+
+/// Audio formats accepted as `response_format` by `synthesize_speech`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Mp3,
+    Wav,
+    Opus,
+    Pcm, // Raw 16-bit little-endian mono samples
+}
+
+impl AudioFormat {
+    /// Parses a `response_format` value (case-insensitive).
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "mp3" => Ok(AudioFormat::Mp3),
+            "wav" => Ok(AudioFormat::Wav),
+            "opus" => Ok(AudioFormat::Opus),
+            "pcm" => Ok(AudioFormat::Pcm),
+            other => Err(format!("Unsupported response_format '{}', expected mp3, wav, opus or pcm", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Pcm => "pcm",
+        }
+    }
+
+    // Arguments describing this format to ffmpeg, for input or output
+    fn ffmpeg_args(&self, sample_rate: u32) -> Vec<String> {
+        match self {
+            AudioFormat::Mp3 => vec!["-f".into(), "mp3".into()],
+            AudioFormat::Wav => vec!["-f".into(), "wav".into()],
+            AudioFormat::Opus => vec!["-c:a".into(), "libopus".into(), "-f".into(), "ogg".into()],
+            AudioFormat::Pcm => vec![
+                "-f".into(), "s16le".into(),
+                "-ar".into(), sample_rate.to_string(),
+                "-ac".into(), "1".into(),
+            ],
+        }
+    }
+}
+
+/// Detects the container format of `audio_data` from its magic bytes.
+/// Headerless PCM cannot be recognized and yields `None`.
+pub fn detect_format(audio_data: &[u8]) -> Option<AudioFormat> {
+    if audio_data.len() >= 12 && &audio_data[0..4] == b"RIFF" && &audio_data[8..12] == b"WAVE" {
+        return Some(AudioFormat::Wav);
+    }
+    if audio_data.starts_with(b"OggS") {
+        let header_len = audio_data.len().min(128);
+        if audio_data[..header_len].windows(8).any(|w| w == b"OpusHead") {
+            return Some(AudioFormat::Opus);
+        }
+        return None;
+    }
+    if audio_data.starts_with(b"ID3") || (audio_data.len() >= 2 && audio_data[0] == 0xFF && audio_data[1] & 0xE0 == 0xE0) {
+        return Some(AudioFormat::Mp3);
+    }
+    None
+}
+
+/// Basic properties read from a WAV `fmt ` chunk.
+#[derive(Debug, Clone, Copy)]
+pub struct WavInfo {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub audio_format: u16, // 1 = integer PCM
+}
+
+/// Locates the `fmt ` and `data` chunks of a WAV file.
+/// Returns the format info and the byte range of the sample data.
+pub fn parse_wav(audio_data: &[u8]) -> Option<(WavInfo, std::ops::Range<usize>)> {
+    if detect_format(audio_data) != Some(AudioFormat::Wav) {
+        return None;
+    }
+
+    let mut info = None;
+    let mut offset = 12;
+    while offset + 8 <= audio_data.len() {
+        let chunk_id = &audio_data[offset..offset + 4];
+        let chunk_len = u32::from_le_bytes(audio_data[offset + 4..offset + 8].try_into().ok()?) as usize;
+        let body_start = offset + 8;
+
+        if chunk_id == b"fmt " && body_start + 16 <= audio_data.len() {
+            let body = &audio_data[body_start..body_start + 16];
+            info = Some(WavInfo {
+                audio_format: u16::from_le_bytes([body[0], body[1]]),
+                channels: u16::from_le_bytes([body[2], body[3]]),
+                sample_rate: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+                bits_per_sample: u16::from_le_bytes([body[14], body[15]]),
+            });
+        } else if chunk_id == b"data" {
+            // Streaming encoders may write a placeholder length, so clamp to what we have
+            let body_end = body_start.saturating_add(chunk_len).min(audio_data.len());
+            return info.map(|info| (info, body_start..body_end));
+        }
+
+        // Chunks are padded to an even number of bytes
+        offset = body_start.saturating_add(chunk_len).saturating_add(chunk_len % 2);
+    }
+    None
+}
+
+/// Returns the sample rate of the audio if it can be determined without decoding.
+pub fn sample_rate_of(audio_data: &[u8], format: AudioFormat) -> Option<u32> {
+    match format {
+        AudioFormat::Wav => parse_wav(audio_data).map(|(info, _)| info.sample_rate),
+        AudioFormat::Pcm => Some(DEFAULT_PCM_SAMPLE_RATE),
+        AudioFormat::Mp3 | AudioFormat::Opus => None,
+    }
+}
+
+/// Checks whether `audio_data` already matches the requested format and sample rate.
+pub fn matches_request(audio_data: &[u8], detected: Option<AudioFormat>, requested: AudioFormat, sample_rate: Option<u32>) -> bool {
+    // Raw PCM has no header, so an unrecognized payload is taken to be the PCM we asked for
+    let actual = detected.or(if requested == AudioFormat::Pcm { Some(AudioFormat::Pcm) } else { None });
+    if actual != Some(requested) {
+        return false;
+    }
+    match sample_rate {
+        Some(target) => sample_rate_of(audio_data, requested) == Some(target),
+        None => true,
+    }
+}
+
+/// Converts audio to the requested format and, optionally, sample rate.
+///
+/// 16-bit WAV and PCM are converted natively. Everything else goes through `ffmpeg`,
+/// which must be available on the PATH (the STT service already depends on it).
+///
+/// # Arguments
+/// * `audio_data` - The audio returned by the TTS service.
+/// * `detected` - The format detected with `detect_format`, if any.
+/// * `target` - The format requested by the caller.
+/// * `sample_rate` - The requested sample rate, if any.
+pub async fn convert_audio(audio_data: Vec<u8>, detected: Option<AudioFormat>, target: AudioFormat, sample_rate: Option<u32>) -> Result<Vec<u8>, String> {
+    if let Some(converted) = convert_natively(&audio_data, detected, target, sample_rate) {
+        return Ok(converted);
+    }
+    convert_with_ffmpeg(audio_data, detected, target, sample_rate).await
+}
+
+fn convert_natively(audio_data: &[u8], detected: Option<AudioFormat>, target: AudioFormat, sample_rate: Option<u32>) -> Option<Vec<u8>> {
+    let (samples, source_rate) = match detected {
+        Some(AudioFormat::Wav) => {
+            let (info, data) = parse_wav(audio_data)?;
+            if info.audio_format != 1 || info.bits_per_sample != 16 || info.channels != 1 {
+                return None;
+            }
+            (pcm_bytes_to_samples(&audio_data[data]), info.sample_rate)
+        }
+        None if target != AudioFormat::Pcm => return None,
+        None | Some(AudioFormat::Pcm) => (pcm_bytes_to_samples(audio_data), DEFAULT_PCM_SAMPLE_RATE),
+        Some(AudioFormat::Mp3) | Some(AudioFormat::Opus) => return None,
+    };
+
+    let target_rate = sample_rate.unwrap_or(source_rate);
+    let samples = resample_linear(&samples, source_rate, target_rate);
+    let pcm_bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+    match target {
+        AudioFormat::Pcm => Some(pcm_bytes),
+        AudioFormat::Wav => Some(encode_wav(&pcm_bytes, target_rate)),
+        AudioFormat::Mp3 | AudioFormat::Opus => None,
+    }
+}
+
+fn pcm_bytes_to_samples(bytes: &[u8]) -> Vec<i16> {
+    bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect()
+}
+
+/// Resamples mono 16-bit audio with linear interpolation.
+pub fn resample_linear(samples: &[i16], source_rate: u32, target_rate: u32) -> Vec<i16> {
+    if source_rate == target_rate || samples.is_empty() || source_rate == 0 || target_rate == 0 {
+        return samples.to_vec();
+    }
+    let output_len = (samples.len() as u64 * target_rate as u64 / source_rate as u64) as usize;
+    let step = source_rate as f64 / target_rate as f64;
+    (0..output_len)
+        .map(|i| {
+            let position = i as f64 * step;
+            let index = position as usize;
+            let fraction = position - index as f64;
+            let current = samples[index.min(samples.len() - 1)] as f64;
+            let next = samples[(index + 1).min(samples.len() - 1)] as f64;
+            (current + (next - current) * fraction).round() as i16
+        })
+        .collect()
+}
+
+/// Wraps mono 16-bit PCM in a canonical 44-byte WAV header.
+pub fn encode_wav(pcm_bytes: &[u8], sample_rate: u32) -> Vec<u8> {
+    let data_len = pcm_bytes.len() as u32;
+    let mut wav = Vec::with_capacity(44 + pcm_bytes.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Integer PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.saturating_mul(2).to_le_bytes()); // Byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // Block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.extend_from_slice(pcm_bytes);
+    wav
+}
+
+async fn convert_with_ffmpeg(audio_data: Vec<u8>, detected: Option<AudioFormat>, target: AudioFormat, sample_rate: Option<u32>) -> Result<Vec<u8>, String> {
+    let mut args: Vec<String> = vec!["-hide_banner".into(), "-loglevel".into(), "error".into()];
+    // Let ffmpeg probe containers; only raw PCM needs to be described explicitly
+    if let Some(AudioFormat::Pcm) = detected {
+        args.extend(AudioFormat::Pcm.ffmpeg_args(DEFAULT_PCM_SAMPLE_RATE));
+    }
+    args.extend(["-i".into(), "pipe:0".into()]);
+    if let Some(rate) = sample_rate {
+        args.extend(["-ar".into(), rate.to_string()]);
+    }
+    args.extend(target.ffmpeg_args(sample_rate.unwrap_or(DEFAULT_PCM_SAMPLE_RATE)));
+    args.push("pipe:1".into());
+
+    let mut child = Command::new("ffmpeg")
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // A cancelled synthesis drops this future; ffmpeg must not outlive it
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start ffmpeg for audio conversion: {}", e))?;
+
+    // Feed stdin from a separate task so a full stdout pipe cannot deadlock us
+    let mut stdin = child.stdin.take().ok_or_else(|| "Failed to open ffmpeg stdin".to_string())?;
+    let writer = tokio::spawn(async move {
+        let result = stdin.write_all(&audio_data).await;
+        drop(stdin);
+        result
+    });
+
+    // Drain stdout and stderr together, so ffmpeg never blocks on a full stderr pipe
+    let mut stdout = child.stdout.take().ok_or_else(|| "Failed to open ffmpeg stdout".to_string())?;
+    let mut stderr = child.stderr.take().ok_or_else(|| "Failed to open ffmpeg stderr".to_string())?;
+    let (mut converted, mut errors) = (Vec::new(), Vec::new());
+    let (read_stdout, _) = tokio::join!(stdout.read_to_end(&mut converted), stderr.read_to_end(&mut errors));
+    read_stdout.map_err(|e| format!("Failed to read converted audio from ffmpeg: {}", e))?;
+
+    let status = child.wait().await
+        .map_err(|e| format!("Failed to wait for ffmpeg: {}", e))?;
+    if let Ok(Err(e)) = writer.await {
+        tracing::warn!("Failed to write audio to ffmpeg stdin: {}", e);
+    }
+
+    if !status.success() {
+        return Err(format!(
+            "ffmpeg failed to convert audio to {}: {}",
+            target.as_str(),
+            String::from_utf8_lossy(&errors).trim()
+        ));
+    }
+    Ok(converted)
+}
@@ -0,0 +1,44 @@
+// audio_ipc.rs
+//
+// Helpers for moving audio over Tauri's raw binary IPC. The frontend passes a Uint8Array
+// (or ArrayBuffer) directly as the invoke payload instead of `Array.from(uint8Array)`,
+// which would otherwise be serialized as a JSON array with one number per byte.
+use tauri::ipc::InvokeBody;
+
+/// Extracts audio bytes from an invoke body.
+///
+/// # Arguments
+/// * `body` - The body of the invoke request.
+/// * `json_field` - The argument name used by callers that still send a JSON number array.
+///
+/// # Returns
+/// The audio bytes, or an error message if the body holds neither raw bytes nor the JSON field.
+pub fn bytes_from_body(body: &InvokeBody, json_field: &str) -> Result<Vec<u8>, String> {
+    match body {
+        InvokeBody::Raw(bytes) => Ok(bytes.clone()),
+        InvokeBody::Json(value) => {
+            let field = value.get(json_field)
+                .ok_or_else(|| format!("Expected a binary payload or a '{}' argument", json_field))?;
+            serde_json::from_value(field.clone())
+                .map_err(|e| format!("Invalid '{}' argument: {}", json_field, e))
+        }
+    }
+}
+
+/// Extracts mono 16-bit little-endian samples from an invoke body.
+pub fn samples_from_body(body: &InvokeBody, json_field: &str) -> Result<Vec<i16>, String> {
+    match body {
+        InvokeBody::Raw(bytes) => {
+            if bytes.len() % 2 != 0 {
+                return Err("Binary sample payload must contain 16-bit samples".to_string());
+            }
+            Ok(bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+        }
+        InvokeBody::Json(value) => {
+            let field = value.get(json_field)
+                .ok_or_else(|| format!("Expected a binary payload or a '{}' argument", json_field))?;
+            serde_json::from_value(field.clone())
+                .map_err(|e| format!("Invalid '{}' argument: {}", json_field, e))
+        }
+    }
+}
@@ -0,0 +1,514 @@
+// mivis-cli: drives the assistant pipeline from the terminal, without the GUI.
+//
+// Uses the same config.json, log directory, TTS backends and cache as the app, so it can
+// be used to script, benchmark and debug the backend on its own.
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use assistant_lib::attachments;
+use assistant_lib::events::{AssistantEvent, EventSink};
+use assistant_lib::pipeline::{self, AppDirs, Services, SpeechRequest};
+use assistant_lib::usage::UsageReport;
+use assistant_lib::{
+    chat, AssistantError, CancellationToken, ChatRequest, ChatResult, ContentPart, FinishReason, GenerationParams, Message,
+    MessageContent, ResponseFormat,
+};
+
+const USAGE: &str = "Usage:
+  mivis-cli chat [--persona <name>] [--temperature <t>] [--max-tokens <n>] [--reasoning-effort low|medium|high] [--json]
+  mivis-cli transcribe <audio.wav>
+  mivis-cli speak <text> -o <out.wav> [--voice <voice>] [--speed <speed>] [--format <format>] [--sample-rate <hz>]
+  mivis-cli voice-turn <audio.wav> [-o <reply.wav>] [--voice <voice>] [--speed <speed>]
+  mivis-cli stats [--since-hours <hours>] [--json]
+  mivis-cli usage [--since-hours <hours>] [--json]
+  mivis-cli secrets list | set <name> | delete <name>   (set reads the value from stdin)
+
+Set MIVIS_APP_DIR to use its config, cache, logs and data subdirectories instead of the app's.";
+
+/// Overrides the app directories, e.g. to run against a copy of the app's files.
+const APP_DIR_ENV: &str = "MIVIS_APP_DIR";
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(command) = args.first().cloned() else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+    if command == "-h" || command == "--help" {
+        println!("{}", USAGE);
+        return;
+    }
+
+    // Same environment and services as the GUI
+    dotenv::from_filename(".env").ok();
+    let mut services = Services::start(&app_dirs());
+    services.install_tracing();
+    services.import_env_file(Path::new(".env"));
+
+    let code = match command.as_str() {
+        "chat" => run_chat(&services, &args[1..]).await,
+        "transcribe" => run_transcribe(&services, &args[1..]).await,
+        "speak" => run_speak(&services, &args[1..]).await,
+        "voice-turn" => run_voice_turn(&services, &args[1..]).await,
+        "stats" => run_stats(&services, &args[1..]).await,
+        "usage" => run_usage(&services, &args[1..]),
+        "secrets" => run_secrets(&services, &args[1..]),
+        other => {
+            eprintln!("Unknown command: {}\n{}", other, USAGE);
+            2
+        }
+    };
+
+    // Flush the workflow log and the app log before the process ends
+    services.workflow_log.flush();
+    if let Some(log_guard) = services.log_guard.take() {
+        drop(log_guard.0.lock().unwrap().take());
+    }
+    std::process::exit(code);
+}
+
+// The directories of the app, or those under $MIVIS_APP_DIR
+fn app_dirs() -> AppDirs {
+    match std::env::var_os(APP_DIR_ENV) {
+        Some(root) => {
+            let root = PathBuf::from(root);
+            AppDirs {
+                config_dir: Some(root.join("config")),
+                cache_dir: Some(root.join("cache")),
+                log_dir: Some(root.join("logs")),
+                data_dir: Some(root.join("data")),
+            }
+        }
+        None => AppDirs::from_platform(),
+    }
+}
+
+// Interactive chat; the conversation is kept until /reset or the end of input
+async fn run_chat(services: &Services, args: &[String]) -> i32 {
+    let (persona, params) = match parse_chat_options(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    println!("Chatting with the assistant. Type /attach <file> to send a file of the app data directory with the next message, /reset to start over, /exit to quit.");
+    let mut messages: Vec<Message> = Vec::new();
+    // Files attached with /attach, sent with the next message
+    let mut attached: Vec<ContentPart> = Vec::new();
+    // The usage ledger counts each conversation separately; /reset starts a new one
+    let mut conversation_id = uuid::Uuid::new_v4().to_string();
+
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let line = match read_line().await {
+            Ok(line) if line.is_empty() => return 0, // End of input
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Failed to read input: {}", e);
+                return 1;
+            }
+        };
+
+        let input = line.trim();
+        match input {
+            "" => continue,
+            "/exit" | "/quit" => return 0,
+            "/reset" => {
+                messages.clear();
+                attached.clear();
+                conversation_id = uuid::Uuid::new_v4().to_string();
+                println!("(conversation cleared)");
+                continue;
+            }
+            _ => {}
+        }
+        if let Some(path) = input.strip_prefix("/attach ") {
+            match attach(services, Path::new(path.trim())) {
+                Ok(part) => {
+                    attached.push(part);
+                    println!("(attached {}, sent with the next message)", path.trim());
+                }
+                Err(e) => eprintln!("Error: {}", e),
+            }
+            continue;
+        }
+
+        let content = if attached.is_empty() {
+            MessageContent::Text(input.to_string())
+        } else {
+            let mut parts = vec![ContentPart::text(input)];
+            parts.append(&mut attached);
+            MessageContent::Parts(parts)
+        };
+        messages.push(Message { role: "user".to_string(), content, source: Some("text".to_string()) });
+        let request = ChatRequest {
+            messages: messages.clone(),
+            conversation_id: Some(conversation_id.clone()),
+            persona: persona.clone(),
+            params: params.clone(),
+        };
+        match chat(request, services.chat_context(&ConsoleEvents, &CancellationToken::new())).await {
+            Ok(reply) => {
+                print_notes(&reply);
+                println!("{}", reply.content);
+                messages.push(Message { role: "assistant".to_string(), content: reply.content.into(), source: None });
+            }
+            Err(e) => {
+                // Drop the unanswered message so it can be retried; its attachments are not kept
+                messages.pop();
+                eprintln!("Error: {}", e);
+            }
+        }
+    }
+}
+
+// Reads a line of stdin on a blocking thread, so the runtime's workers are never blocked
+// while waiting for the user; empty at the end of input
+async fn read_line() -> io::Result<String> {
+    tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line).map(|_| line)
+    })
+    .await
+    .map_err(io::Error::other)?
+}
+
+// Reads a file of the app data directory, like the attach_file command of the app
+fn attach(services: &Services, path: &Path) -> Result<ContentPart, AssistantError> {
+    let data_dir = app_dirs().data_dir
+        .ok_or_else(|| AssistantError::Internal("Failed to resolve app data directory".to_string()))?;
+    attachments::attach(path, &data_dir, &services.config.attachments)
+}
+
+// The reasoning and a cut-off warning go to stderr, so stdout only carries the answer
+fn print_notes(reply: &ChatResult) {
+    if let Some(reasoning) = &reply.reasoning_content {
+        eprintln!("[THINKING] {}", reasoning);
+    }
+    if reply.finish_reason == Some(FinishReason::Length) {
+        eprintln!("[TRUNCATED] The answer was cut off by the max_tokens limit");
+    }
+}
+
+// Usage: mivis-cli chat [--persona <name>] [--temperature <t>] [--max-tokens <n>] [--reasoning-effort <effort>] [--json]
+fn parse_chat_options(args: &[String]) -> Result<(Option<String>, GenerationParams), String> {
+    let mut persona = None;
+    let mut params = GenerationParams::default();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or_else(|| format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--persona" => persona = Some(value()?),
+            "--temperature" => params.temperature = Some(value()?.parse().map_err(|e| format!("Invalid temperature: {}", e))?),
+            "--max-tokens" => params.max_tokens = Some(value()?.parse().map_err(|e| format!("Invalid max tokens: {}", e))?),
+            "--reasoning-effort" => {
+                let effort = value()?;
+                params.reasoning_effort = Some(serde_json::from_value(serde_json::Value::String(effort.clone()))
+                    .map_err(|_| format!("Invalid reasoning effort: {}", effort))?);
+            }
+            "--json" => params.response_format = Some(ResponseFormat::JsonObject),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    Ok((persona, params))
+}
+
+// Usage: mivis-cli transcribe <audio.wav>
+async fn run_transcribe(services: &Services, args: &[String]) -> i32 {
+    let [audio_file] = args else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+    match transcribe_file(services, Path::new(audio_file)).await {
+        Ok(transcription) => {
+            println!("{}", transcription);
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Usage: mivis-cli speak <text> -o <out.wav> [--voice <voice>] [--speed <speed>] [--format <format>] [--sample-rate <hz>]
+async fn run_speak(services: &Services, args: &[String]) -> i32 {
+    let options = match SpeakOptions::parse(args, true) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    let (Some(text), Some(output)) = (options.positional, options.output) else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    match speak_to_file(services, SpeechRequest { text, ..options.request }, &output).await {
+        Ok(()) => {
+            println!("Wrote {}", output.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Usage: mivis-cli voice-turn <audio.wav> [-o <reply.wav>] [--voice <voice>] [--speed <speed>]
+// One full turn: transcribe the recording, ask the LLM, and speak the reply
+async fn run_voice_turn(services: &Services, args: &[String]) -> i32 {
+    let options = match SpeakOptions::parse(args, false) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+    let Some(audio_file) = options.positional else {
+        eprintln!("{}", USAGE);
+        return 2;
+    };
+
+    let result = async {
+        let transcription = transcribe_file(services, Path::new(&audio_file)).await?;
+        println!("You: {}", transcription);
+
+        let messages = vec![Message { role: "user".to_string(), content: transcription.into(), source: Some("voice".to_string()) }];
+        let reply = chat(ChatRequest::new(messages), services.chat_context(&ConsoleEvents, &CancellationToken::new())).await?;
+        print_notes(&reply);
+        println!("Assistant: {}", reply.content);
+
+        if let Some(output) = &options.output {
+            // Only the answer is spoken, not the reasoning
+            speak_to_file(services, SpeechRequest { text: reply.content, ..options.request.clone() }, output).await?;
+            println!("Wrote {}", output.display());
+        }
+        Ok::<(), AssistantError>(())
+    }.await;
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Usage: mivis-cli stats [--since-hours <hours>] [--json]
+async fn run_stats(services: &Services, args: &[String]) -> i32 {
+    let (window_hours, as_json) = match parse_report_options(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    let report = match pipeline::latency_report(&services.log_dir, window_hours) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    let cache_stats = pipeline::tts_cache_stats(&services.tts_cache).await.ok();
+
+    if as_json {
+        let stats = serde_json::json!({ "latency": report, "tts_cache": cache_stats });
+        println!("{}", serde_json::to_string_pretty(&stats).unwrap_or_default());
+    } else {
+        print!("{}", assistant_lib::analytics::format_table(&report));
+        match cache_stats {
+            Some(stats) => println!(
+                "\nTTS cache: {} entries, {} of {} bytes",
+                stats.entries, stats.total_bytes, stats.max_bytes
+            ),
+            None => println!("\nTTS cache: unavailable"),
+        }
+    }
+    0
+}
+
+// Options of the stats and usage reports: [--since-hours <hours>] [--json]
+fn parse_report_options(args: &[String]) -> Result<(Option<f64>, bool), String> {
+    let mut window_hours = None;
+    let mut as_json = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--since-hours" => {
+                let value = iter.next().ok_or_else(|| format!("Missing value for {}", arg))?;
+                let hours: f64 = value.parse().map_err(|_| format!("Invalid number of hours: {}", value))?;
+                if !hours.is_finite() || hours <= 0.0 {
+                    return Err(format!("--since-hours must be greater than zero, got {}", value));
+                }
+                window_hours = Some(hours);
+            }
+            "--json" => as_json = true,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    Ok((window_hours, as_json))
+}
+
+// Usage: mivis-cli usage [--since-hours <hours>] [--json]
+fn run_usage(services: &Services, args: &[String]) -> i32 {
+    let (window_hours, as_json) = match parse_report_options(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    let report = match pipeline::usage_report(&services.usage, window_hours) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    } else {
+        print_usage(&report);
+    }
+    0
+}
+
+fn print_usage(report: &UsageReport) {
+    println!("{:<24} {:>6} {:>12} {:>12} {:>10}", "MODEL", "CALLS", "PROMPT", "COMPLETION", "COST USD");
+    for model in &report.by_model {
+        let usage = &model.usage;
+        println!("{:<24} {:>6} {:>12} {:>12} {:>10.4}", model.model, usage.calls, usage.prompt_tokens, usage.completion_tokens, usage.cost_usd);
+    }
+    let total = &report.total;
+    println!("{:<24} {:>6} {:>12} {:>12} {:>10.4}", "TOTAL", total.calls, total.prompt_tokens, total.completion_tokens, total.cost_usd);
+    if total.unpriced_calls > 0 {
+        println!("({} calls of models without a price are not in the cost)", total.unpriced_calls);
+    }
+    println!("{} conversations", report.by_conversation.len());
+
+    for budget in &report.budgets {
+        println!("\n{} budget: ${:.4} of ${:.2} spent ({:?})", budget.period, budget.spent_usd, budget.limit_usd, budget.state);
+    }
+}
+
+// Manages the encrypted secret store; values are read from stdin so they stay out of the
+// shell history, and are never printed nor echoed
+fn run_secrets(services: &Services, args: &[String]) -> i32 {
+    let secrets = &services.secrets;
+    let result = match (args.first().map(String::as_str), args.get(1)) {
+        (Some("list"), None) => secrets.names().map(|names| names.iter().for_each(|name| println!("{}", name))),
+        (Some("set"), Some(name)) => read_secret(name).and_then(|value| secrets.set(name, &value)),
+        (Some("delete"), Some(name)) => secrets.delete(name).map(|deleted| {
+            if !deleted {
+                eprintln!("{} is not in the secret store", name);
+            }
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+// Reads the value of a secret without echo from the terminal, or a line of piped input
+fn read_secret(name: &str) -> Result<String, AssistantError> {
+    let value = if io::stdin().is_terminal() {
+        rpassword::prompt_password(format!("Value of {}: ", name))
+    } else {
+        let mut value = String::new();
+        io::stdin().lock().read_line(&mut value).map(|_| value)
+    };
+    value
+        .map(|value| value.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|e| AssistantError::Io { context: "Failed to read the value from stdin".to_string(), source: e })
+}
+
+async fn transcribe_file(services: &Services, audio_file: &Path) -> Result<String, AssistantError> {
+    let audio_data = tokio::fs::read(audio_file).await
+        .map_err(|e| AssistantError::Io { context: format!("Failed to read {}", audio_file.display()), source: e })?;
+    pipeline::transcribe(audio_data, &services.config.stt, &services.http.stt, services.workflow_log.clone(), &services.redactor, &ConsoleEvents, &CancellationToken::new()).await
+}
+
+async fn speak_to_file(services: &Services, request: SpeechRequest, output: &Path) -> Result<(), AssistantError> {
+    let audio_data = pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &ConsoleEvents, &CancellationToken::new()).await?;
+    tokio::fs::write(output, audio_data).await
+        .map_err(|e| AssistantError::Io { context: format!("Failed to write {}", output.display()), source: e })
+}
+
+// Shows progress on stderr, so stdout only carries the results
+struct ConsoleEvents;
+
+impl EventSink for ConsoleEvents {
+    fn emit(&self, event: AssistantEvent) {
+        match event {
+            AssistantEvent::ProcessingStageUpdate(update) => match update.message {
+                Some(message) => eprintln!("[{}] {}", update.stage, message),
+                None => eprintln!("[{}]", update.stage),
+            },
+            AssistantEvent::ServiceHealthChanged(change) => {
+                eprintln!("[HEALTH] {} is now {:?}", change.service, change.state);
+            }
+            AssistantEvent::BudgetWarning(budget) => {
+                eprintln!("[BUDGET] {} LLM budget: ${:.4} of ${:.2} spent", budget.period, budget.spent_usd, budget.limit_usd);
+            }
+        }
+    }
+}
+
+// Arguments shared by `speak` and `voice-turn`
+struct SpeakOptions {
+    positional: Option<String>,
+    output: Option<PathBuf>,
+    request: SpeechRequest,
+}
+
+impl SpeakOptions {
+    fn parse(args: &[String], allow_format: bool) -> Result<Self, String> {
+        let mut options = SpeakOptions { positional: None, output: None, request: SpeechRequest::default() };
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().cloned().ok_or_else(|| format!("Missing value for {}", arg));
+            match arg.as_str() {
+                "-o" | "--output" => options.output = Some(PathBuf::from(value()?)),
+                "--voice" => options.request.voice = Some(value()?),
+                "--speed" => options.request.speed = Some(value()?.parse().map_err(|e| format!("Invalid speed: {}", e))?),
+                "--format" if allow_format => options.request.response_format = Some(value()?),
+                "--sample-rate" if allow_format => {
+                    options.request.sample_rate = Some(value()?.parse().map_err(|e| format!("Invalid sample rate: {}", e))?)
+                }
+                other if other.starts_with('-') => return Err(format!("Unknown argument: {}", other)),
+                other if options.positional.is_none() => options.positional = Some(other.to_string()),
+                other => return Err(format!("Unexpected argument: {}", other)),
+            }
+        }
+
+        // Without --format the format follows the output file extension (e.g. reply.mp3)
+        if options.request.response_format.is_none() {
+            options.request.response_format = options.output.as_ref()
+                .and_then(|output| output.extension())
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        }
+        Ok(options)
+    }
+}
@@ -0,0 +1,93 @@
+// The content of a chat message: plain text, or a list of OpenAI-style content parts
+// mixing text with images (as URLs or inline base64 data URLs). Attached documents are
+// sent as text parts holding an excerpt of the file, which every provider accepts.
+use serde::{Deserialize, Serialize};
+
+/// What a message says. Plain text serializes as a string, so text-only conversations
+/// are sent exactly as before.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageUrl {
+    /// An `https://` URL, or a `data:image/...;base64,` URL of an attached image.
+    pub url: String,
+    /// How closely the model looks at the image; `None` leaves the provider's default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<ImageDetail>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageDetail {
+    Auto,
+    Low,
+    High,
+}
+
+impl MessageContent {
+    /// The text of the message, with the text parts joined by blank lines.
+    pub fn text(&self) -> String {
+        match self {
+            MessageContent::Text(text) => text.clone(),
+            MessageContent::Parts(parts) => parts.iter()
+                .filter_map(|part| match part {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        }
+    }
+
+    /// How many images the message carries.
+    pub fn image_count(&self) -> usize {
+        match self {
+            MessageContent::Text(_) => 0,
+            MessageContent::Parts(parts) => parts.iter().filter(|part| matches!(part, ContentPart::ImageUrl { .. })).count(),
+        }
+    }
+}
+
+impl ContentPart {
+    /// A text part.
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+
+    /// An image part for a base64-encoded image of the given MIME type.
+    pub fn base64_image(mime_type: &str, data: &str) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl { url: format!("data:{};base64,{}", mime_type, data), detail: None },
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        MessageContent::Parts(parts)
+    }
+}
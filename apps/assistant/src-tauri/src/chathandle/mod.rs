@@ -1,109 +1,293 @@
 // Chat handling module for Mivis Desktop Assistant
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::env;
-use std::error::Error; // Import the Error trait
-use tokio::time;
-use tauri::{AppHandle, Emitter}; // Added Emitter
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio_util::sync::CancellationToken;
 
-// WorkflowTimings will be managed by the orchestrating function
+use crate::config::{AppConfig, LlmConfig};
+use crate::error::AssistantError;
+use crate::events::{AssistantEvent, EventSink};
+use crate::http::{HttpClients, ServiceClient};
+use crate::secrets::SecretStore;
+use crate::usage::{TokenUsage, UsageLedger};
+use crate::pipeline::finish_workflow;
+use crate::workflow_logger::{WorkflowLogSink, WorkflowTimings};
+use tracing::Instrument;
 
-use super::ProcessingStageUpdatePayload; // Import from lib.rs
+mod content;
+mod params;
+pub use content::{ContentPart, ImageDetail, ImageUrl, MessageContent};
+pub use params::{GenerationParams, ReasoningEffort, ResponseFormat};
+
+use super::{CancelState, SecretsState, TauriEventSink, UsageState, WorkflowLogState}; // Import from lib.rs
+
+// Name of the chat completion API in errors
+pub(crate) const LLM_SERVICE: &str = "LLM API";
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    /// Text, or text and images as content parts.
+    pub content: MessageContent,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>, // For Task 2 (text or voice)
 }
 
+/// A chat completion request.
+#[derive(Clone, Debug, Default)]
+pub struct ChatRequest {
+    /// The conversation so far, oldest first.
+    pub messages: Vec<Message>,
+    /// Identifies the conversation in the usage ledger.
+    pub conversation_id: Option<String>,
+    /// One of `llm.personas`, whose generation parameters apply to the call.
+    pub persona: Option<String>,
+    /// Generation parameters of this call, over those of the config and the persona.
+    pub params: GenerationParams,
+}
+
+/// The reply of a chat completion, returned by `invoke_llm_chat`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChatResult {
+    /// The answer, without the reasoning; this is what gets spoken.
+    pub content: String,
+    /// What a reasoning model thought before answering (`reasoning_content`, or a leading
+    /// `<think>` block of the content on servers that inline it).
+    pub reasoning_content: Option<String>,
+    /// Why the model stopped; `length` means the answer was cut off by `max_tokens`.
+    pub finish_reason: Option<FinishReason>,
+    pub usage: Option<TokenUsage>,
+    /// The model that answered, as reported by the provider.
+    pub model: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    Stop,
+    Length,
+    ToolCalls,
+    ContentFilter,
+    /// A reason this version does not know.
+    #[serde(other)]
+    Other,
+}
+
+impl ChatRequest {
+    /// A request outside of any conversation, with the configured parameters.
+    pub fn new(messages: Vec<Message>) -> Self {
+        ChatRequest { messages, ..ChatRequest::default() }
+    }
+}
+
 #[tauri::command]
-pub async fn invoke_llm_chat(app_handle: AppHandle, messages: Vec<Message>) -> Result<String, String> {
-    // Correcting the emit call to use emit_all, assuming Emitter trait is correctly in scope
-    if let Err(e) = app_handle.emit("processing_stage_update", ProcessingStageUpdatePayload {
-        stage: "PROCESSING_API".to_string(),
-        message: Some("Processing request...".to_string()),
-    }) {
-        eprintln!("Failed to emit processing_stage_update event: {}", e);
+pub async fn invoke_llm_chat(
+    app_handle: AppHandle,
+    config: tauri::State<'_, AppConfig>,
+    http: tauri::State<'_, HttpClients>,
+    secrets: tauri::State<'_, SecretsState>,
+    usage: tauri::State<'_, UsageState>,
+    workflow_log: tauri::State<'_, WorkflowLogState>,
+    cancel_state: tauri::State<'_, CancelState>,
+    messages: Vec<Message>,
+    conversation_id: Option<String>,
+    persona: Option<String>,
+    params: Option<GenerationParams>,
+) -> Result<ChatResult, AssistantError> {
+    let request = ChatRequest { messages, conversation_id, persona, params: params.unwrap_or_default() };
+    let events = TauriEventSink(app_handle);
+    let cancel = cancel_state.token();
+    let context = ChatContext {
+        llm: &config.llm,
+        client: &http.llm,
+        secrets: &secrets.0,
+        usage: &usage.0,
+        workflow_log: workflow_log.0.clone(),
+        events: &events,
+        cancel: &cancel,
+    };
+    chat(request, context).await
+}
+
+/// What a `chat` call works with besides the conversation.
+pub struct ChatContext<'a> {
+    /// The endpoint, model, API key variable and default parameters of the provider.
+    pub llm: &'a LlmConfig,
+    /// The HTTP client of the provider.
+    pub client: &'a ServiceClient,
+    /// Where the API key is read from (the store, then the environment).
+    pub secrets: &'a SecretStore,
+    /// Where the tokens of the completion are recorded; a spent budget blocks the call.
+    pub usage: &'a UsageLedger,
+    /// Where the timing of the completion is recorded.
+    pub workflow_log: Arc<dyn WorkflowLogSink>,
+    /// Receives the PROCESSING_API stage update.
+    pub events: &'a dyn EventSink,
+    /// Cancelling it abandons the request and its retries.
+    pub cancel: &'a CancellationToken,
+}
+
+/// Sends the conversation (with the system prompt prepended) to the chat completion API.
+///
+/// # Arguments
+/// * `request` - The conversation so far, its id and the generation parameters.
+/// * `context` - The provider, its client and key, and where the call is recorded.
+///
+/// # Returns
+/// The reply with its reasoning, finish reason and usage, or why the completion failed.
+pub async fn chat(request: ChatRequest, context: ChatContext<'_>) -> Result<ChatResult, AssistantError> {
+    let ChatContext { llm, client, secrets, usage, workflow_log, events, cancel } = context;
+    events.emit(AssistantEvent::stage("PROCESSING_API", Some("Processing request...")));
+
+    // Time the completion; each attempt is recorded as a child span of the LLM stage
+    let timings = WorkflowTimings::with_sink("llm", workflow_log);
+    let mut stage = timings.stage("LLM");
+    let ChatRequest { messages, conversation_id, persona, params } = request;
+    stage.set_attribute("messages", messages.len());
+
+    // Conversations with images go to the vision model, for as long as the images are in
+    // the history since a text model would reject them
+    let images: usize = messages.iter().map(|message| message.content.image_count()).sum();
+    let model = match &llm.vision_model {
+        Some(vision_model) if images > 0 => vision_model.as_str(),
+        _ => llm.model.as_str(),
+    };
+    if images > 0 {
+        stage.set_attribute("images", images);
+        tracing::debug!(images, "Sending the conversation to {}", model);
     }
 
-    let client = Client::new();
-    let api_key = env::var("XAI_API_KEY").map_err(|e| format!("Missing API key: {}", e))?;
-
-    // Create a mutable copy of messages to prepend the system prompt
-    let mut messages_with_system_prompt = messages.clone(); // Assuming Message is Cloneable, or manually clone
-
-    // Prepend the system prompt
-    messages_with_system_prompt.insert(0, Message {
-        role: "system".to_string(),
-        content: 
-        "Mày là Arisu có tính cách ngổ ngáo và tinh nghịch, 
-        khi mày trả lời thì mày phải sử dụng ngôn ngữ tự nhiên 
-        và bằng tiếng Việt, theo phong cách hội thoại. 
-        Mày rất hỗn láo và không sợ làm mất lòng người khác.
-        Vì thế nên câu trả lời của mày cụt lủn, 
-        không cần có chủ ngũ vị ngữ, thể hiện sự mất dạy của mày.".to_string(),
-        source: None,
-    });
+    let stage_span = stage.span().clone();
+    let result: Result<ChatResult, AssistantError> = async {
+        let params = GenerationParams::resolve(llm, model, persona.as_deref(), &params)?;
+        usage.check_budget()?;
+        let api_key = secrets.get(&llm.api_key_env).ok_or_else(|| AssistantError::Auth {
+            service: LLM_SERVICE.to_string(),
+            reason: format!("Missing API key {}: add it in the settings or to .env", llm.api_key_env),
+        })?;
+
+        // Create a mutable copy of messages to prepend the system prompt
+        let mut messages_with_system_prompt = messages.clone(); // Assuming Message is Cloneable, or manually clone
+
+        // Prepend the system prompt
+        messages_with_system_prompt.insert(0, Message {
+            role: "system".to_string(),
+            content: 
+            "Mày là Arisu có tính cách ngổ ngáo và tinh nghịch, 
+            khi mày trả lời thì mày phải sử dụng ngôn ngữ tự nhiên 
+            và bằng tiếng Việt, theo phong cách hội thoại. 
+            Mày rất hỗn láo và không sợ làm mất lòng người khác.
+            Vì thế nên câu trả lời của mày cụt lủn, 
+            không cần có chủ ngũ vị ngữ, thể hiện sự mất dạy của mày.".into(),
+            source: None,
+        });
     
-    // Log input source for debugging if available
-    if let Some(last_msg) = messages_with_system_prompt.last() { // Use the modified list for logging if appropriate
-        if let Some(source) = &last_msg.source {
-            println!("Input source: {}", source);
+        // Log input source for debugging if available
+        if let Some(last_msg) = messages_with_system_prompt.last() { // Use the modified list for logging if appropriate
+            if let Some(source) = &last_msg.source {
+                tracing::debug!(source = %source, "Chat input received");
+            }
         }
-    }
-    
-    // Direct request to get completion response
-    let mut attempts = 0;
-    let max_attempts = 3;
-    let mut delay = time::Duration::from_secs(2);
     
-    let response = loop {
-        attempts += 1;
-        match client
-            .post("https://api.x.ai/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .json(&serde_json::json!({
-                "model": "grok-3-mini-beta",
-                "messages": &messages_with_system_prompt // Use the messages with the system prompt
-            }))
-            .send()
-            .await
-        {
-            Ok(response) => break response,
-            Err(e) => {
-                if attempts >= max_attempts {
-                    let mut error_details = format!("API call failed after {} attempts: {}", attempts, e);
-                    if let Some(source) = e.source() {
-                        error_details.push_str(&format!("\nSource error: {}", source));
-                        let mut current_source = source;
-                        while let Some(next_source) = current_source.source() {
-                            error_details.push_str(&format!("\nCaused by: {}", next_source));
-                            current_source = next_source;
-                        }
-                    }
-                    return Err(error_details);
+        // The messages with the system prompt, and the generation parameters that are set
+        let mut body = serde_json::json!({
+            "model": model,
+            "messages": &messages_with_system_prompt
+        });
+        params.write_to(llm.provider, &mut body);
+
+        // Request the completion, retrying transient failures, unless the circuit breaker
+        // says the provider is down; each attempt is a child span
+        let retries = llm.retry.policy();
+        let request = retries.run(LLM_SERVICE, cancel, |attempt| {
+            let (stage, api_key, body) = (&stage, &api_key, &body);
+            async move {
+                let mut attempt_span = stage.child("llm:request");
+                attempt_span.set_attribute("attempt", attempt);
+                let send_result = client
+                    .post(&llm.url)
+                    .header("Authorization", format!("Bearer {}", api_key))
+                    .header("Content-Type", "application/json")
+                    .json(body)
+                    .send()
+                    .await;
+                attempt_span.complete(&send_result);
+                let response = send_result.map_err(|e| client.send_error(LLM_SERVICE, &e))?;
+
+                if !response.status().is_success() {
+                    return Err(AssistantError::from_response(LLM_SERVICE, response).await);
                 }
-                time::sleep(delay).await;
-                delay *= 2;
+                Ok(response)
             }
-        }
-    };
+        });
+        let response = client.breaker().call(events, request).await?;
     
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_else(|_| "No response body".to_string());
-        return Err(format!("xAI API returned error status {}: {}", status, text));
+        // Parse the response to extract the content
+        let completion_data: serde_json::Value = response.json().await
+            .map_err(|e| AssistantError::invalid_response(LLM_SERVICE, format!("Failed to parse response: {}", e)))?;
+
+        // Record the tokens against the conversation and the model that answered
+        let model = completion_data["model"].as_str().unwrap_or(model).to_string();
+        let tokens = TokenUsage::from_completion(&completion_data);
+        match &tokens {
+            Some(tokens) => {
+                stage.set_attribute("prompt_tokens", tokens.prompt_tokens);
+                stage.set_attribute("completion_tokens", tokens.completion_tokens);
+                usage.record(conversation_id.as_deref(), &model, tokens.clone(), events);
+            }
+            None => tracing::warn!("The completion has no usage block, its tokens are not recorded"),
+        }
+
+        let reply = ChatResult::from_completion(&completion_data, model, tokens)?;
+        if let Some(finish_reason) = reply.finish_reason {
+            stage.set_attribute("finish_reason", serde_json::to_value(finish_reason).unwrap_or_default());
+        }
+        if reply.finish_reason == Some(FinishReason::Length) {
+            tracing::warn!("The reply was cut off by the max_tokens limit");
+        }
+        Ok(reply)
+    }.instrument(stage_span).await;
+
+    stage.complete(&result);
+    finish_workflow(&timings, &result);
+    result
+}
+
+impl ChatResult {
+    // Reads the first choice of a completion
+    fn from_completion(completion: &serde_json::Value, model: String, usage: Option<TokenUsage>) -> Result<Self, AssistantError> {
+        let choice = &completion["choices"][0];
+        let message = choice["message"].as_object()
+            .ok_or_else(|| AssistantError::invalid_response(LLM_SERVICE, "Content field not found in response"))?;
+        // A reply that only calls tools, or that was filtered, has no content
+        let content = match message.get("content") {
+            Some(serde_json::Value::String(content)) => content.clone(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(_) => return Err(AssistantError::invalid_response(LLM_SERVICE, "The content of the reply is not text")),
+        };
+
+        let reasoning_content = message.get("reasoning_content").and_then(|reasoning| reasoning.as_str()).map(str::to_string);
+        let (reasoning_content, content) = match reasoning_content {
+            Some(reasoning) => (Some(reasoning), content),
+            None => split_think_block(&content),
+        };
+
+        Ok(ChatResult {
+            content,
+            reasoning_content: reasoning_content.filter(|reasoning| !reasoning.trim().is_empty()),
+            finish_reason: serde_json::from_value(choice["finish_reason"].clone()).ok(),
+            usage,
+            model,
+        })
+    }
+}
+
+// Some OpenAI-compatible servers put the reasoning in the content, as "<think>...</think>answer"
+fn split_think_block(content: &str) -> (Option<String>, String) {
+    let Some(rest) = content.trim_start().strip_prefix("<think>") else { return (None, content.to_string()) };
+    match rest.split_once("</think>") {
+        Some((reasoning, answer)) => (Some(reasoning.trim().to_string()), answer.trim_start().to_string()),
+        // Cut off while still thinking
+        None => (Some(rest.trim().to_string()), String::new()),
     }
-    
-    // Parse the response to extract the content
-    let completion_data: serde_json::Value = response.json().await
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
-    let content = completion_data["choices"][0]["message"]["content"].as_str()
-        .ok_or_else(|| "Content field not found in response".to_string())?
-        .to_string();
-    
-    return Ok(content);
 }
@@ -0,0 +1,191 @@
+// Generation parameters of a chat completion and the checks made before they are sent.
+//
+// Parameters come from three places, each overriding the one before: the `llm.params`
+// defaults of the config, the persona picked for the call (`llm.personas`), and the call
+// itself. Providers reject parameters their models do not support, so the merged set is
+// checked against the configured provider first and fails as `InvalidInput` naming every
+// problem, rather than as an upstream 400 after the retries.
+use serde::{Deserialize, Serialize};
+
+use crate::config::{LlmConfig, LlmProvider};
+use crate::error::AssistantError;
+
+/// Optional sampling and output parameters; `None` leaves the provider's default.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GenerationParams {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Upper bound of the reply length, reasoning tokens included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Sequences at which the reply stops (at most 4).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Makes sampling repeatable, as far as the provider allows.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// How long a reasoning model thinks before answering.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// `{"type": "json_object"}` makes the model answer with a JSON object.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningEffort {
+    Low,
+    /// OpenAI only; xAI accepts low and high.
+    Medium,
+    High,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    /// JSON mode: the reply is a valid JSON object.
+    JsonObject,
+}
+
+// The limits of the OpenAI API, which xAI follows
+const MAX_STOP_SEQUENCES: usize = 4;
+
+impl GenerationParams {
+    /// These parameters with the ones set in `overrides` taking precedence.
+    pub fn merged_with(&self, overrides: &GenerationParams) -> GenerationParams {
+        GenerationParams {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            seed: overrides.seed.or(self.seed),
+            presence_penalty: overrides.presence_penalty.or(self.presence_penalty),
+            frequency_penalty: overrides.frequency_penalty.or(self.frequency_penalty),
+            reasoning_effort: overrides.reasoning_effort.or(self.reasoning_effort),
+            response_format: overrides.response_format.or(self.response_format),
+        }
+    }
+
+    /// The parameters of a call: the config defaults, then the persona, then the call's own.
+    /// They are checked against `model`, which is the vision model for calls with images.
+    ///
+    /// # Returns
+    /// The merged parameters, or `InvalidInput` for an unknown persona or for parameters
+    /// out of range or not supported by the provider and model.
+    pub fn resolve(llm: &LlmConfig, model: &str, persona: Option<&str>, params: &GenerationParams) -> Result<GenerationParams, AssistantError> {
+        let mut resolved = llm.params.clone();
+        if let Some(name) = persona {
+            let preset = llm.personas.get(name).ok_or_else(|| {
+                let known: Vec<&str> = llm.personas.keys().map(String::as_str).collect();
+                AssistantError::InvalidInput(format!("Unknown persona '{}' (configured: {})", name, known.join(", ")))
+            })?;
+            resolved = resolved.merged_with(preset);
+        }
+        let resolved = resolved.merged_with(params);
+
+        let problems = resolved.problems(llm.provider, model);
+        if !problems.is_empty() {
+            return Err(AssistantError::InvalidInput(format!("Invalid generation parameters: {}", problems.join("; "))));
+        }
+        Ok(resolved)
+    }
+
+    /// Adds the parameters that are set to a chat completion request body.
+    pub fn write_to(&self, provider: LlmProvider, body: &mut serde_json::Value) {
+        let (Ok(serde_json::Value::Object(params)), Some(body)) = (serde_json::to_value(self), body.as_object_mut()) else {
+            return;
+        };
+        for (name, value) in params {
+            // OpenAI replaced max_tokens, which its reasoning models reject
+            let name = match (provider, name.as_str()) {
+                (LlmProvider::OpenAi, "max_tokens") => "max_completion_tokens".to_string(),
+                _ => name,
+            };
+            body.insert(name, value);
+        }
+    }
+
+    // Everything wrong with the parameters for `model` of `provider`
+    fn problems(&self, provider: LlmProvider, model: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check_range = |name: &str, value: Option<f32>, min: f32, max: f32| {
+            if let Some(value) = value {
+                if !(min..=max).contains(&value) {
+                    problems.push(format!("{} must be between {} and {}, got {}", name, min, max, value));
+                }
+            }
+        };
+        check_range("temperature", self.temperature, 0.0, 2.0);
+        check_range("top_p", self.top_p, 0.0, 1.0);
+        check_range("presence_penalty", self.presence_penalty, -2.0, 2.0);
+        check_range("frequency_penalty", self.frequency_penalty, -2.0, 2.0);
+        if self.max_tokens == Some(0) {
+            problems.push("max_tokens must be at least 1".to_string());
+        }
+        if let Some(stop) = &self.stop {
+            if stop.len() > MAX_STOP_SEQUENCES || stop.iter().any(String::is_empty) {
+                problems.push(format!("stop takes 1 to {} non-empty sequences", MAX_STOP_SEQUENCES));
+            }
+        }
+
+        let unsupported = self.unsupported(provider, model);
+        if !unsupported.is_empty() {
+            problems.push(format!("{} not supported by {} on {}", unsupported.join(", "), model, provider));
+        }
+        problems
+    }
+
+    // The parameters that are set but that `model` of `provider` rejects
+    fn unsupported(&self, provider: LlmProvider, model: &str) -> Vec<&'static str> {
+        let set = |name: &'static str, is_set: bool| is_set.then_some(name);
+        let penalties_and_stop = [
+            set("presence_penalty", self.presence_penalty.is_some()),
+            set("frequency_penalty", self.frequency_penalty.is_some()),
+            set("stop", self.stop.is_some()),
+        ];
+
+        let unsupported: Vec<Option<&'static str>> = match provider {
+            LlmProvider::Xai => {
+                // Reasoning effort is a setting of grok-3-mini only, with low and high levels;
+                // the reasoning models do not take penalties or stop sequences
+                let mini = model.starts_with("grok-3-mini");
+                let reasoning = mini || model.starts_with("grok-4");
+                let mut unsupported = vec![
+                    set("reasoning_effort", !mini && self.reasoning_effort.is_some()),
+                    set("reasoning_effort \"medium\"", mini && self.reasoning_effort == Some(ReasoningEffort::Medium)),
+                ];
+                if reasoning {
+                    unsupported.extend(penalties_and_stop);
+                }
+                unsupported
+            }
+            LlmProvider::OpenAi => {
+                // The o-series and gpt-5 reason, and leave sampling and penalties to the model
+                let reasoning = model.starts_with("gpt-5")
+                    || (model.starts_with('o') && model[1..].starts_with(|c: char| c.is_ascii_digit()));
+                if reasoning {
+                    vec![
+                        set("temperature", self.temperature.is_some()),
+                        set("top_p", self.top_p.is_some()),
+                        set("presence_penalty", self.presence_penalty.is_some()),
+                        set("frequency_penalty", self.frequency_penalty.is_some()),
+                    ]
+                } else {
+                    vec![set("reasoning_effort", self.reasoning_effort.is_some())]
+                }
+            }
+            // Other OpenAI-compatible servers get every parameter as is
+            LlmProvider::Compatible => Vec::new(),
+        };
+        unsupported.into_iter().flatten().collect()
+    }
+}
@@ -0,0 +1,206 @@
+// circuit_breaker.rs
+//
+// A circuit breaker per upstream service. After `failure_threshold` failed calls in a row
+// the circuit opens and calls fail at once, instead of each voice turn waiting out the
+// timeouts and retries of a service that is down. Once `open_secs` have passed, a single
+// probe call is let through (half-open): its success closes the circuit, its failure opens
+// it again. Every change is sent to the frontend as a `service_health_changed` event.
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::config::CircuitBreakerConfig;
+use crate::error::AssistantError;
+use crate::events::{AssistantEvent, EventSink};
+
+/// Health of a service as seen by its circuit breaker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// The service failed repeatedly; calls fail without being sent.
+    Open,
+    /// One probe call is checking whether the service has recovered.
+    HalfOpen,
+}
+
+/// The current state of a service, returned by `get_service_health`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ServiceHealth {
+    pub service: String,
+    pub state: CircuitState,
+}
+
+/// The circuit breaker of one service. Clones share the same circuit.
+#[derive(Clone, Debug)]
+pub struct CircuitBreaker {
+    service: String,
+    failure_threshold: u32,
+    open_duration: Duration,
+    circuit: Arc<Mutex<Circuit>>,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    /// A closed circuit for `service`. A `failure_threshold` of 0 never opens it.
+    pub fn new(service: &str, config: &CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            service: service.to_string(),
+            failure_threshold: config.failure_threshold,
+            open_duration: Duration::from_secs(config.open_secs),
+            circuit: Arc::new(Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            })),
+        }
+    }
+
+    /// A separate circuit with the same settings, for another service.
+    pub fn for_service(&self, service: &str) -> Self {
+        let config = CircuitBreakerConfig { failure_threshold: self.failure_threshold, open_secs: self.open_duration.as_secs() };
+        CircuitBreaker::new(service, &config)
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.circuit.lock().unwrap().state
+    }
+
+    pub fn health(&self) -> ServiceHealth {
+        ServiceHealth { service: self.service.clone(), state: self.state() }
+    }
+
+    /// Runs `call` unless the circuit is open, and records its outcome.
+    ///
+    /// # Arguments
+    /// * `events` - Receives a `service_health_changed` event when the state changes.
+    /// * `call` - The request to the service, retries included.
+    ///
+    /// # Returns
+    /// The result of `call`, or `ServiceUnavailable` without calling it while the circuit is open.
+    pub async fn call<T>(
+        &self,
+        events: &dyn EventSink,
+        call: impl std::future::Future<Output = Result<T, AssistantError>>,
+    ) -> Result<T, AssistantError> {
+        let permit = self.acquire(events)?;
+        let result = call.await;
+        permit.record(&result, events);
+        result
+    }
+
+    /// Lets a call through if the circuit is closed, or if it is the probe of a circuit that
+    /// has been open long enough. The outcome of the call is recorded through the permit.
+    pub fn acquire(&self, events: &dyn EventSink) -> Result<CircuitPermit<'_>, AssistantError> {
+        let mut circuit = self.circuit.lock().unwrap();
+        match circuit.state {
+            CircuitState::Closed => Ok(CircuitPermit { breaker: self, probe: false }),
+            CircuitState::Open => {
+                let opened_at = circuit.opened_at.unwrap_or_else(Instant::now);
+                let remaining = self.open_duration.saturating_sub(opened_at.elapsed());
+                if !remaining.is_zero() {
+                    return Err(self.open_error(circuit.consecutive_failures, remaining));
+                }
+                circuit.probe_in_flight = true;
+                self.transition(&mut circuit, CircuitState::HalfOpen, None, events);
+                Ok(CircuitPermit { breaker: self, probe: true })
+            }
+            CircuitState::HalfOpen if circuit.probe_in_flight => {
+                Err(self.open_error(circuit.consecutive_failures, Duration::ZERO))
+            }
+            CircuitState::HalfOpen => {
+                circuit.probe_in_flight = true;
+                Ok(CircuitPermit { breaker: self, probe: true })
+            }
+        }
+    }
+
+    // Records the outcome of a call; only the probe frees the probe slot
+    fn record<T>(&self, probe: bool, result: &Result<T, AssistantError>, events: &dyn EventSink) {
+        let mut circuit = self.circuit.lock().unwrap();
+        if probe {
+            circuit.probe_in_flight = false;
+        }
+        match result {
+            Err(AssistantError::Cancelled) => {}
+            Err(e) if is_health_failure(e) => {
+                circuit.consecutive_failures += 1;
+                let tripped = self.failure_threshold > 0 && circuit.consecutive_failures >= self.failure_threshold;
+                if circuit.state == CircuitState::HalfOpen || (circuit.state == CircuitState::Closed && tripped) {
+                    circuit.opened_at = Some(Instant::now());
+                    self.transition(&mut circuit, CircuitState::Open, Some(e.to_string()), events);
+                }
+            }
+            _ => {
+                circuit.consecutive_failures = 0;
+                if circuit.state != CircuitState::Closed {
+                    circuit.opened_at = None;
+                    self.transition(&mut circuit, CircuitState::Closed, None, events);
+                }
+            }
+        }
+    }
+
+    fn transition(&self, circuit: &mut Circuit, state: CircuitState, reason: Option<String>, events: &dyn EventSink) {
+        let previous = std::mem::replace(&mut circuit.state, state);
+        match state {
+            CircuitState::Open => tracing::warn!("Circuit of {} opened after {} failures: {}", self.service, circuit.consecutive_failures, reason.as_deref().unwrap_or_default()),
+            CircuitState::HalfOpen => tracing::info!("Circuit of {} half-open, probing the service", self.service),
+            CircuitState::Closed => tracing::info!("Circuit of {} closed, the service has recovered", self.service),
+        }
+        events.emit(AssistantEvent::service_health(&self.service, state, previous, reason));
+    }
+
+    fn open_error(&self, failures: u32, retry_in: Duration) -> AssistantError {
+        AssistantError::ServiceUnavailable {
+            service: self.service.clone(),
+            reason: format!("circuit open after {} failed calls, next probe in {}s", failures, retry_in.as_secs()),
+        }
+    }
+}
+
+/// A call let through by `CircuitBreaker::acquire`. If it is dropped without `record`,
+/// because the caller gave up on the call, a probe frees its slot so the next call can
+/// probe the service instead of failing fast forever.
+#[must_use = "record the outcome of the call"]
+pub struct CircuitPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+}
+
+impl CircuitPermit<'_> {
+    /// Records the outcome of the call. Only failures that say something about the
+    /// service's health count: it could not be reached, timed out or answered 5xx. A
+    /// rejected key or a cancelled request does not.
+    pub fn record<T>(mut self, result: &Result<T, AssistantError>, events: &dyn EventSink) {
+        self.breaker.record(self.probe, result, events);
+        self.probe = false;
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.circuit.lock().unwrap().probe_in_flight = false;
+        }
+    }
+}
+
+fn is_health_failure(error: &AssistantError) -> bool {
+    match error {
+        AssistantError::ServiceUnavailable { .. } | AssistantError::Timeout { .. } => true,
+        AssistantError::Upstream { status, .. } => *status >= 500,
+        _ => false,
+    }
+}
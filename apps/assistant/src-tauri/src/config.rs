@@ -0,0 +1,497 @@
+// config.rs
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::chathandle::GenerationParams;
+use crate::log_writer::{self, RotationPolicy};
+use crate::metrics;
+use crate::redaction;
+use crate::retry::RetryPolicy;
+use crate::workflow_logger::LogFormat;
+
+/// File name of the configuration inside the app config directory.
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+/// Application configuration, read from `config.json` in the app config directory.
+/// Every field has a default, so a missing file or missing keys fall back to the built-in setup.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct AppConfig {
+    pub stt: SttConfig,
+    pub llm: LlmConfig,
+    pub tts: TtsConfig,
+    pub logging: LoggingConfig,
+    pub privacy: PrivacyConfig,
+    pub metrics: MetricsConfig,
+    pub http: HttpConfig,
+    pub secrets: SecretsConfig,
+    pub usage: UsageConfig,
+    pub attachments: AttachmentConfig,
+}
+
+/// The speech-to-text service (packages/stt).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SttConfig {
+    /// The `/transcribe` endpoint of the Flask service.
+    pub url: String,
+    pub retry: RetryConfig,
+    pub timeouts: HttpTimeouts,
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+impl Default for SttConfig {
+    fn default() -> Self {
+        SttConfig {
+            url: "http://127.0.0.1:5000/transcribe".to_string(),
+            retry: RetryConfig::default(),
+            // Whisper can take a while on long recordings without a GPU
+            timeouts: HttpTimeouts { connect_secs: 5, read_secs: 60 },
+            circuit_breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+/// The chat completion provider.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LlmConfig {
+    /// An OpenAI-compatible `/v1/chat/completions` endpoint.
+    pub url: String,
+    pub model: String,
+    /// Model that answers conversations with images in them; `None` sends them to `model`,
+    /// which must then accept images.
+    pub vision_model: Option<String>,
+    /// Name of the environment variable holding the API key.
+    pub api_key_env: String,
+    /// Who serves `url`; the generation parameters are checked against what it supports.
+    pub provider: LlmProvider,
+    /// Generation parameters of every call, unless the persona or the call sets them.
+    pub params: GenerationParams,
+    /// Named sets of generation parameters, one per persona, picked per call.
+    pub personas: BTreeMap<String, GenerationParams>,
+    pub retry: RetryConfig,
+    pub timeouts: HttpTimeouts,
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        LlmConfig {
+            url: "https://api.x.ai/v1/chat/completions".to_string(),
+            model: "grok-3-mini-beta".to_string(),
+            vision_model: Some("grok-2-vision-1212".to_string()),
+            api_key_env: "XAI_API_KEY".to_string(),
+            provider: LlmProvider::Xai,
+            params: GenerationParams::default(),
+            personas: BTreeMap::new(),
+            // Completions are slow and rate limited, so wait longer between attempts
+            retry: RetryConfig { initial_backoff_ms: 2000, max_backoff_ms: 20_000, deadline_secs: 120, ..RetryConfig::default() },
+            // Reasoning models think for a while before the first byte of the reply
+            timeouts: HttpTimeouts { connect_secs: 10, read_secs: 90 },
+            circuit_breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+/// The provider behind the chat completion endpoint.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LlmProvider {
+    #[default]
+    Xai,
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Any other OpenAI-compatible server (e.g. a local one); parameters are not checked.
+    Compatible,
+}
+
+impl std::fmt::Display for LlmProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LlmProvider::Xai => "xAI",
+            LlmProvider::OpenAi => "OpenAI",
+            LlmProvider::Compatible => "an OpenAI-compatible server",
+        })
+    }
+}
+
+/// How failed requests to a provider are retried (see retry.rs).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Attempts in total, including the first; 1 disables retries.
+    pub max_attempts: u32,
+    /// Backoff before the second attempt; it doubles for each further attempt.
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Time allowed for all attempts together; 0 means no limit.
+    pub deadline_secs: u64,
+    /// Longest wait honoured when a service sends Retry-After.
+    pub max_retry_after_secs: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig { max_attempts: 3, initial_backoff_ms: 500, max_backoff_ms: 8000, deadline_secs: 60, max_retry_after_secs: 60 }
+    }
+}
+
+impl RetryConfig {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            initial_backoff: Duration::from_millis(self.initial_backoff_ms),
+            max_backoff: Duration::from_millis(self.max_backoff_ms),
+            deadline: (self.deadline_secs > 0).then(|| Duration::from_secs(self.deadline_secs)),
+            max_retry_after: Duration::from_secs(self.max_retry_after_secs),
+        }
+    }
+}
+
+/// When a service is considered down (see circuit_breaker.rs).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CircuitBreakerConfig {
+    /// Failed calls in a row, retries included, that open the circuit; 0 never opens it.
+    pub failure_threshold: u32,
+    /// How long calls fail fast before a probe call checks the service again.
+    pub open_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig { failure_threshold: 3, open_secs: 30 }
+    }
+}
+
+/// Timeouts of one service's HTTP requests.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HttpTimeouts {
+    /// Time allowed to open the connection.
+    pub connect_secs: u64,
+    /// Time allowed between two reads of the response, so a stalled service fails
+    /// while a slow but streaming one does not.
+    pub read_secs: u64,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        HttpTimeouts { connect_secs: 10, read_secs: 30 }
+    }
+}
+
+impl HttpTimeouts {
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect_secs)
+    }
+
+    pub fn read(&self) -> Duration {
+        Duration::from_secs(self.read_secs)
+    }
+}
+
+/// Settings shared by the HTTP clients of all services (see http.rs).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Proxy for `http://` URLs, e.g. "http://proxy.local:3128". Without one, the
+    /// HTTP_PROXY and HTTPS_PROXY variables are used.
+    pub http_proxy: Option<String>,
+    /// Proxy for `https://` URLs.
+    pub https_proxy: Option<String>,
+    /// Hosts reached without the proxy, comma separated (`NO_PROXY` syntax).
+    pub no_proxy: Option<String>,
+    /// PEM file of extra root certificates to trust, e.g. a company CA.
+    pub ca_certificate: Option<PathBuf>,
+    /// How long an unused keep-alive connection stays open.
+    pub pool_idle_timeout_secs: u64,
+    pub pool_max_idle_per_host: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            http_proxy: None,
+            https_proxy: None,
+            // The local STT and TTS services must not go through a proxy
+            no_proxy: Some("localhost,127.0.0.1,::1".to_string()),
+            ca_certificate: None,
+            pool_idle_timeout_secs: 90,
+            pool_max_idle_per_host: 4,
+        }
+    }
+}
+
+/// Where API keys are kept (see secrets.rs).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub key_source: KeySource,
+    /// Environment variable with the passphrase of a passphrase store, so it unlocks
+    /// without asking (e.g. for mivis-cli).
+    pub passphrase_env: String,
+    /// Variables moved from `.env` into the store at startup.
+    pub import_from_env_file: Vec<String>,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        SecretsConfig {
+            key_source: KeySource::default(),
+            passphrase_env: "MIVIS_SECRETS_PASSPHRASE".to_string(),
+            import_from_env_file: ["XAI_API_KEY", "TAVILY_API_KEY", "GMAIL_CLIENT_SECRET", "DISCORD_BOT_TOKEN", "SPOTIFY_CLIENT_SECRET"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// How the key of the secret store is obtained.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// A random key in `secrets.key` in the app data directory, readable by the user only.
+    #[default]
+    KeyFile,
+    /// A key derived from a passphrase; the store is locked until it is entered.
+    Passphrase,
+}
+
+/// Prices and budgets of the LLM calls (see usage.rs).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct UsageConfig {
+    /// Price per model id, as reported in the completions. A `prices` object in config.json
+    /// replaces the whole built-in table; calls of models missing from it cost nothing.
+    pub prices: BTreeMap<String, ModelPrice>,
+    /// Spend per calendar day (local time) after which LLM calls are blocked; no limit by default.
+    pub daily_budget_usd: Option<f64>,
+    /// Spend per calendar month after which LLM calls are blocked.
+    pub monthly_budget_usd: Option<f64>,
+    /// Share of a budget, in percent, at which a `budget_warning` event is sent.
+    pub warn_at_percent: f64,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        // xAI list prices in USD per million tokens
+        let prices = [
+            ("grok-3-mini-beta", 0.30, 0.50),
+            ("grok-3-mini", 0.30, 0.50),
+            ("grok-3-beta", 3.00, 15.00),
+            ("grok-3", 3.00, 15.00),
+            ("grok-2-vision-1212", 2.00, 10.00),
+        ];
+        UsageConfig {
+            prices: prices.iter()
+                .map(|(model, prompt, completion)| {
+                    (model.to_string(), ModelPrice { prompt_per_million_usd: *prompt, completion_per_million_usd: *completion })
+                })
+                .collect(),
+            daily_budget_usd: None,
+            monthly_budget_usd: None,
+            warn_at_percent: 80.0,
+        }
+    }
+}
+
+/// Files attached to chat messages (see attachments.rs).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AttachmentConfig {
+    /// Larger files are refused before being read.
+    pub max_file_bytes: u64,
+    /// Images are downscaled so that neither side is longer, in pixels.
+    pub max_image_side: u32,
+    /// Quality (1-100) of the JPEG images are re-encoded as.
+    pub jpeg_quality: u8,
+    /// Characters of a text document sent to the model; the rest is cut off.
+    pub max_excerpt_chars: usize,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        AttachmentConfig {
+            max_file_bytes: 20 * 1024 * 1024,
+            // Vision models tile images in 512px squares; larger ones only cost more tokens
+            max_image_side: 1024,
+            jpeg_quality: 85,
+            max_excerpt_chars: 20_000,
+        }
+    }
+}
+
+/// Price of a model in USD per million tokens.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ModelPrice {
+    pub prompt_per_million_usd: f64,
+    /// Output tokens, reasoning tokens included.
+    pub completion_per_million_usd: f64,
+}
+
+/// The Prometheus metrics endpoint, served on 127.0.0.1 only.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Off by default; when on, metrics are served at `http://127.0.0.1:<port>/metrics`.
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig { enabled: false, port: metrics::DEFAULT_METRICS_PORT }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Format of the workflow timing log: "json_lines" (default) or the older "text".
+    pub workflow_log_format: LogFormat,
+    /// Level filter of the app log, e.g. "info" or "warn,assistant_lib=debug" (`RUST_LOG` syntax).
+    pub level: String,
+    /// Number of daily app log files kept before the oldest is deleted.
+    pub max_log_files: usize,
+    /// Size at which the workflow log is rotated into a gzipped segment.
+    pub workflow_log_max_bytes: u64,
+    /// Days to keep rotated workflow log segments; 0 keeps them forever.
+    pub workflow_log_retention_days: u32,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            workflow_log_format: LogFormat::default(),
+            level: "info".to_string(),
+            max_log_files: 14,
+            workflow_log_max_bytes: log_writer::DEFAULT_MAX_FILE_BYTES,
+            workflow_log_retention_days: log_writer::DEFAULT_RETENTION_DAYS,
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn workflow_rotation(&self) -> RotationPolicy {
+        RotationPolicy {
+            max_file_bytes: self.workflow_log_max_bytes,
+            retention_days: self.workflow_log_retention_days,
+        }
+    }
+}
+
+/// What is masked before text reaches the logs or saved recordings.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    /// Built-in rules to apply, see `redaction::BUILTIN_RULES`. All of them by default.
+    pub rules: Vec<String>,
+    /// Extra patterns, applied after the built-in rules.
+    pub custom_rules: Vec<CustomRedactionRule>,
+    /// Log only timings: no messages, error text or text fields in the app log and the workflow
+    /// log, no sidecar output, no saved recordings.
+    pub no_content_logging: bool,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            rules: redaction::BUILTIN_RULES.iter().map(|rule| rule.to_string()).collect(),
+            custom_rules: Vec::new(),
+            no_content_logging: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CustomRedactionRule {
+    pub name: String,
+    /// Regular expression (`regex` crate syntax) of the text to mask.
+    pub pattern: String,
+    /// Text that replaces a match; defaults to `[REDACTED_<NAME>]`.
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TtsConfig {
+    /// Backends tried in order until one succeeds.
+    pub backends: Vec<TtsBackendConfig>,
+    /// How long a single backend may take before the next one is tried, retries included.
+    pub timeout_secs: u64,
+    /// Retries of the HTTP backends (VietTTS and OpenAI-compatible servers).
+    pub retry: RetryConfig,
+    /// Connect and read timeouts of the HTTP backends.
+    pub timeouts: HttpTimeouts,
+    /// Circuit breaker of each backend; a backend with an open circuit is skipped.
+    pub circuit_breaker: CircuitBreakerConfig,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        TtsConfig {
+            backends: vec![TtsBackendConfig::Viettts {
+                url: "http://localhost:8298/v1/audio/speech".to_string(),
+                default_voice: "diep-chi".to_string(),
+            }],
+            timeout_secs: 30,
+            retry: RetryConfig::default(),
+            timeouts: HttpTimeouts { connect_secs: 5, read_secs: 20 },
+            circuit_breaker: CircuitBreakerConfig::default(),
+        }
+    }
+}
+
+/// One entry of the TTS fallback chain.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TtsBackendConfig {
+    /// The VietTTS Docker service.
+    Viettts {
+        url: String,
+        default_voice: String,
+    },
+    /// Any server implementing OpenAI's `/v1/audio/speech`.
+    OpenaiCompatible {
+        url: String,
+        model: String,
+        default_voice: String,
+        /// Name of the environment variable holding the API key, if the server needs one.
+        #[serde(default)]
+        api_key_env: Option<String>,
+    },
+    /// A local engine such as espeak-ng or piper run as a subprocess.
+    /// `args` may contain the placeholders `{text}`, `{voice}`, `{speed}` and `{output}`.
+    Command {
+        program: String,
+        args: Vec<String>,
+        default_voice: String,
+        /// Write the text to the process's stdin instead of passing it as an argument.
+        #[serde(default)]
+        text_via_stdin: bool,
+    },
+    /// Produces silence; useful as the last resort and for testing.
+    Mock,
+}
+
+impl AppConfig {
+    /// Loads the configuration from `path`. A missing file gives the defaults.
+    ///
+    /// # Returns
+    /// The configuration, or an error message if the file cannot be parsed. Logging is set up
+    /// from the configuration, so the caller reports the error once logging is running.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Failed to parse config {}: {}", path.display(), e)),
+            Err(_) => Ok(AppConfig::default()),
+        }
+    }
+}
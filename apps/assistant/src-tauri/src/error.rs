@@ -0,0 +1,289 @@
+// error.rs
+//
+// The error type returned by the pipeline and every command. The variants tell the
+// frontend *why* a request failed (service down, bad API key, rate limited, ...), so it can
+// react without parsing messages. Errors reach the frontend as a tagged JSON object:
+//
+//   { "kind": "rate_limited", "message": "<Vietnamese text for the user>",
+//     "detail": "<technical message>", "service": "LLM API", "retry_after_secs": 20 }
+//
+// `Display` gives the technical (English) message used in logs and by the CLI.
+use std::fmt;
+use std::time::Duration;
+
+use serde::ser::{Serialize, Serializer};
+
+use crate::usage::BudgetPeriod;
+use crate::workflow_logger::StageError;
+
+/// Why a request failed.
+#[derive(Debug, thiserror::Error)]
+pub enum AssistantError {
+    /// The service could not be reached or cannot serve requests right now.
+    #[error("{service} is unavailable: {reason}")]
+    ServiceUnavailable { service: String, reason: String },
+
+    /// The API key is missing or was rejected.
+    #[error("{service} authentication failed: {reason}")]
+    Auth { service: String, reason: String },
+
+    /// The service asked us to slow down, optionally saying for how long.
+    #[error("{service} rate limited the request{}", retry_after_text(.retry_after))]
+    RateLimited { service: String, retry_after: Option<Duration> },
+
+    /// The request itself is invalid (bad argument, unsupported format, ...).
+    #[error("{0}")]
+    InvalidInput(String),
+
+    /// The service did not answer in time.
+    #[error("{service} timed out after {}s", .after.as_secs())]
+    Timeout { service: String, after: Duration },
+
+    /// The request was cancelled before it finished.
+    #[error("The request was cancelled")]
+    Cancelled,
+
+    /// The daily or monthly LLM budget is spent (see usage.rs).
+    #[error("The {period} LLM budget of ${limit_usd:.2} is spent (${spent_usd:.2})")]
+    BudgetExceeded { period: BudgetPeriod, spent_usd: f64, limit_usd: f64 },
+
+    /// The service answered with an error status. `retry_after` comes from the
+    /// Retry-After header (e.g. of a 503).
+    #[error("{service} returned error status {status}: {body}")]
+    Upstream { service: String, status: u16, body: String, retry_after: Option<Duration> },
+
+    /// The service answered, but not with what we expected.
+    #[error("{service} returned an invalid response: {reason}")]
+    InvalidResponse { service: String, reason: String },
+
+    /// Reading or writing a local file failed.
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// Anything else (a bug or an unexpected state of the app).
+    #[error("{0}")]
+    Internal(String),
+}
+
+// The Vietnamese name of a service in the user messages. The names of configured TTS
+// backends are the user's own, so they are only prefixed.
+fn service_display_name(service: &str) -> String {
+    match service {
+        "STT service" => "dịch vụ nhận dạng giọng nói".to_string(),
+        "LLM API" => "mô hình ngôn ngữ".to_string(),
+        "TTS" => "dịch vụ đọc văn bản".to_string(),
+        "TTS cache" => "bộ nhớ đệm giọng đọc".to_string(),
+        "VietTTS service" => "dịch vụ VietTTS".to_string(),
+        "Secret store" => "kho khóa bí mật".to_string(),
+        other => format!("dịch vụ {}", other),
+    }
+}
+
+// Upper-cases the first letter, for names that start a sentence
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+fn retry_after_text(retry_after: &Option<Duration>) -> String {
+    retry_after.map(|delay| format!(", retry after {}s", delay.as_secs())).unwrap_or_default()
+}
+
+impl AssistantError {
+    /// Short name of the variant, the `kind` field of the JSON form.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AssistantError::ServiceUnavailable { .. } => "service_unavailable",
+            AssistantError::Auth { .. } => "auth",
+            AssistantError::RateLimited { .. } => "rate_limited",
+            AssistantError::InvalidInput(_) => "invalid_input",
+            AssistantError::Timeout { .. } => "timeout",
+            AssistantError::Cancelled => "cancelled",
+            AssistantError::BudgetExceeded { .. } => "budget_exceeded",
+            AssistantError::Upstream { .. } => "upstream",
+            AssistantError::InvalidResponse { .. } => "invalid_response",
+            AssistantError::Io { .. } => "io",
+            AssistantError::Internal(_) => "internal",
+        }
+    }
+
+    /// The message shown to the user, in Vietnamese.
+    pub fn user_message(&self) -> String {
+        match self {
+            AssistantError::ServiceUnavailable { service, .. } => {
+                format!("Hiện không dùng được {}. Vui lòng thử lại sau.", service_display_name(service))
+            }
+            AssistantError::Auth { service, .. } => {
+                format!("Xác thực với {} thất bại. Vui lòng kiểm tra khóa API.", service_display_name(service))
+            }
+            AssistantError::RateLimited { service, retry_after: Some(delay) } => {
+                format!("Đã gửi quá nhiều yêu cầu tới {}. Vui lòng thử lại sau {} giây.", service_display_name(service), delay.as_secs().max(1))
+            }
+            AssistantError::RateLimited { service, retry_after: None } => {
+                format!("Đã gửi quá nhiều yêu cầu tới {}. Vui lòng thử lại sau ít phút.", service_display_name(service))
+            }
+            AssistantError::InvalidInput(reason) => format!("Yêu cầu không hợp lệ: {}", reason),
+            AssistantError::Timeout { service, after } => {
+                format!("Không nhận được phản hồi từ {} sau {} giây.", service_display_name(service), after.as_secs())
+            }
+            AssistantError::Cancelled => "Yêu cầu đã bị hủy.".to_string(),
+            AssistantError::BudgetExceeded { period: BudgetPeriod::Daily, .. } => {
+                "Đã dùng hết ngân sách LLM của hôm nay. Vui lòng thử lại vào ngày mai.".to_string()
+            }
+            AssistantError::BudgetExceeded { period: BudgetPeriod::Monthly, .. } => {
+                "Đã dùng hết ngân sách LLM của tháng này.".to_string()
+            }
+            AssistantError::Upstream { service, status, .. } => {
+                format!("{} trả về lỗi {}.", capitalize(&service_display_name(service)), status)
+            }
+            AssistantError::InvalidResponse { service, .. } => {
+                format!("{} trả về phản hồi không hợp lệ.", capitalize(&service_display_name(service)))
+            }
+            AssistantError::Io { .. } => "Không thể đọc hoặc ghi tệp.".to_string(),
+            AssistantError::Internal(_) => "Đã xảy ra lỗi không mong muốn.".to_string(),
+        }
+    }
+
+    /// The service the error came from, if any.
+    pub fn service(&self) -> Option<&str> {
+        match self {
+            AssistantError::ServiceUnavailable { service, .. }
+            | AssistantError::Auth { service, .. }
+            | AssistantError::RateLimited { service, .. }
+            | AssistantError::Timeout { service, .. }
+            | AssistantError::Upstream { service, .. }
+            | AssistantError::InvalidResponse { service, .. } => Some(service),
+            _ => None,
+        }
+    }
+
+    /// Whether trying the same request again may succeed: the service could not be reached
+    /// or timed out, or it answered 408, 429 or 5xx.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AssistantError::ServiceUnavailable { .. } | AssistantError::RateLimited { .. } | AssistantError::Timeout { .. } => true,
+            AssistantError::Upstream { status, .. } => *status == 408 || (500..600).contains(status),
+            _ => false,
+        }
+    }
+
+    /// How long the service asked us to wait before trying again.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AssistantError::RateLimited { retry_after, .. } | AssistantError::Upstream { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Sorts an error status of `service` into a variant: 401/403 become `Auth` and
+    /// 429 becomes `RateLimited`, everything else is `Upstream`.
+    ///
+    /// # Arguments
+    /// * `service` - Name of the service, shown to the user.
+    /// * `status` - The HTTP status code.
+    /// * `body` - The response body.
+    /// * `retry_after` - The Retry-After header, if the response had one.
+    pub fn from_status(service: &str, status: u16, body: String, retry_after: Option<Duration>) -> Self {
+        match status {
+            401 | 403 => AssistantError::Auth { service: service.to_string(), reason: format!("status {}: {}", status, body) },
+            429 => AssistantError::RateLimited { service: service.to_string(), retry_after },
+            _ => AssistantError::Upstream { service: service.to_string(), status, body, retry_after },
+        }
+    }
+
+    /// Turns an unsuccessful HTTP response into an error, reading its body.
+    pub(crate) async fn from_response(service: &str, response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        let retry_after = response.headers().get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_retry_after);
+        let body = response.text().await.unwrap_or_else(|_| "No response body".to_string());
+        Self::from_status(service, status, body, retry_after)
+    }
+
+    /// A request to `service` that could not be sent (connection refused, DNS, TLS, ...).
+    pub(crate) fn unreachable(service: &str, error: impl fmt::Display) -> Self {
+        AssistantError::ServiceUnavailable { service: service.to_string(), reason: format!("Failed to send request: {}", error) }
+    }
+
+    /// A response of `service` that could not be understood.
+    pub(crate) fn invalid_response(service: &str, reason: impl fmt::Display) -> Self {
+        AssistantError::InvalidResponse { service: service.to_string(), reason: reason.to_string() }
+    }
+
+    /// A failed file operation, described by `context` (e.g. "Failed to read x.wav").
+    pub(crate) fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        AssistantError::Io { context: context.into(), source }
+    }
+}
+
+// Failed stages and workflows are counted in the metrics by the variant
+impl StageError for AssistantError {
+    fn error_kind(&self) -> Option<&'static str> {
+        Some(self.kind())
+    }
+}
+
+// Requests that got no response, timed by the attempt stages
+impl StageError for reqwest::Error {
+    fn error_kind(&self) -> Option<&'static str> {
+        if self.is_timeout() {
+            Some("timeout")
+        } else if self.is_connect() {
+            Some("service_unavailable")
+        } else {
+            None
+        }
+    }
+}
+
+// Retry-After is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let seconds = (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds();
+    Some(Duration::from_secs(seconds.max(0) as u64))
+}
+
+// The JSON form sent to the frontend (see the top of this file)
+#[derive(serde::Serialize)]
+struct SerializedError<'a> {
+    kind: &'static str,
+    message: String,
+    detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_secs: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout_secs: Option<u64>,
+}
+
+impl Serialize for AssistantError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedError {
+            kind: self.kind(),
+            message: self.user_message(),
+            detail: self.to_string(),
+            service: self.service(),
+            status: match self {
+                AssistantError::Upstream { status, .. } => Some(*status),
+                _ => None,
+            },
+            retry_after_secs: self.retry_after().map(|delay| delay.as_secs()),
+            timeout_secs: match self {
+                AssistantError::Timeout { after, .. } => Some(after.as_secs()),
+                _ => None,
+            },
+        }
+        .serialize(serializer)
+    }
+}
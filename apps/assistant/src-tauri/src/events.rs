@@ -0,0 +1,164 @@
+// events.rs
+//
+// Progress events sent by the pipeline. The pipeline only knows the `EventSink` trait;
+// the app forwards events to the frontend (see `TauriEventSink` in lib.rs), while tests
+// and the CLI record them or read them from a channel.
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::circuit_breaker::CircuitState;
+use crate::usage::{BudgetPeriod, BudgetState, BudgetStatus};
+
+/// Payload of the `processing_stage_update` event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProcessingStageUpdatePayload {
+    pub stage: String,
+    pub message: Option<String>,
+}
+
+/// Payload of the `service_health_changed` event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ServiceHealthPayload {
+    pub service: String,
+    pub state: CircuitState,
+    pub previous: CircuitState,
+    /// The failure that opened the circuit.
+    pub reason: Option<String>,
+}
+
+/// Payload of the `budget_warning` event.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BudgetWarningPayload {
+    pub period: BudgetPeriod,
+    /// `warning`, or `exceeded` once LLM calls are blocked.
+    pub state: BudgetState,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+}
+
+/// An event sent while a request is being processed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum AssistantEvent {
+    /// The request moved to a new processing stage (e.g. TRANSCRIBING).
+    ProcessingStageUpdate(ProcessingStageUpdatePayload),
+    /// The circuit breaker of a service changed state (see circuit_breaker.rs).
+    ServiceHealthChanged(ServiceHealthPayload),
+    /// An LLM budget is nearly or fully spent (see usage.rs).
+    BudgetWarning(BudgetWarningPayload),
+}
+
+impl AssistantEvent {
+    /// A stage update with an optional message for the user.
+    pub fn stage(stage: &str, message: Option<&str>) -> Self {
+        AssistantEvent::ProcessingStageUpdate(ProcessingStageUpdatePayload {
+            stage: stage.to_string(),
+            message: message.map(str::to_string),
+        })
+    }
+
+    /// A change of a service's health.
+    pub fn service_health(service: &str, state: CircuitState, previous: CircuitState, reason: Option<String>) -> Self {
+        AssistantEvent::ServiceHealthChanged(ServiceHealthPayload { service: service.to_string(), state, previous, reason })
+    }
+
+    /// A budget that reached its warning share or was spent.
+    pub fn budget_warning(budget: &BudgetStatus) -> Self {
+        AssistantEvent::BudgetWarning(BudgetWarningPayload {
+            period: budget.period,
+            state: budget.state,
+            spent_usd: budget.spent_usd,
+            limit_usd: budget.limit_usd,
+        })
+    }
+
+    /// The name the event is emitted under to the frontend.
+    pub fn name(&self) -> &'static str {
+        match self {
+            AssistantEvent::ProcessingStageUpdate(_) => "processing_stage_update",
+            AssistantEvent::ServiceHealthChanged(_) => "service_health_changed",
+            AssistantEvent::BudgetWarning(_) => "budget_warning",
+        }
+    }
+}
+
+/// Receives the events of the pipeline. Emitting must not fail the request, so
+/// implementations log delivery problems instead of returning them.
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: AssistantEvent);
+}
+
+/// Sends events over a channel, e.g. to a task that renders progress.
+pub struct ChannelEventSink(pub mpsc::UnboundedSender<AssistantEvent>);
+
+impl ChannelEventSink {
+    /// Creates the sink and the receiving end of its channel.
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<AssistantEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (ChannelEventSink(sender), receiver)
+    }
+}
+
+impl EventSink for ChannelEventSink {
+    fn emit(&self, event: AssistantEvent) {
+        // The receiver is gone once nobody is interested in the progress any more
+        if self.0.send(event).is_err() {
+            tracing::debug!("Dropped an event, the receiver was closed");
+        }
+    }
+}
+
+/// Keeps every event in memory, so tests can check what was emitted.
+#[derive(Default)]
+pub struct RecordingEventSink {
+    events: Mutex<Vec<AssistantEvent>>,
+}
+
+impl RecordingEventSink {
+    pub fn new() -> Self {
+        RecordingEventSink::default()
+    }
+
+    /// The events emitted so far, oldest first.
+    pub fn events(&self) -> Vec<AssistantEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// The stages of the stage updates emitted so far, oldest first.
+    pub fn stages(&self) -> Vec<String> {
+        self.events.lock().unwrap().iter()
+            .filter_map(|event| match event {
+                AssistantEvent::ProcessingStageUpdate(payload) => Some(payload.stage.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The health changes emitted so far, oldest first.
+    pub fn health_changes(&self) -> Vec<ServiceHealthPayload> {
+        self.events.lock().unwrap().iter()
+            .filter_map(|event| match event {
+                AssistantEvent::ServiceHealthChanged(payload) => Some(payload.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The budget warnings emitted so far, oldest first.
+    pub fn budget_warnings(&self) -> Vec<BudgetWarningPayload> {
+        self.events.lock().unwrap().iter()
+            .filter_map(|event| match event {
+                AssistantEvent::BudgetWarning(payload) => Some(payload.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl EventSink for RecordingEventSink {
+    fn emit(&self, event: AssistantEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
@@ -0,0 +1,140 @@
+// http.rs
+//
+// The HTTP clients of the STT, LLM and TTS requests, built once at startup and shared by
+// every command so connections are kept alive and reused. reqwest sets connect and read
+// timeouts per client, so each service gets its own client; they share the pool, proxy,
+// CA and User-Agent settings of `HttpConfig`. Each client also carries the circuit breaker
+// of its service.
+use std::error::Error;
+use std::time::Duration;
+
+use reqwest::{Certificate, Client, NoProxy, Proxy};
+
+use crate::chathandle::LLM_SERVICE;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::config::{AppConfig, CircuitBreakerConfig, HttpConfig, HttpTimeouts};
+use crate::error::AssistantError;
+use crate::pipeline::STT_SERVICE;
+
+/// Sent with every request, e.g. `Mivis-Assistant/0.1.0`.
+pub const USER_AGENT: &str = concat!("Mivis-Assistant/", env!("CARGO_PKG_VERSION"));
+
+/// One client per service.
+#[derive(Clone, Debug)]
+pub struct HttpClients {
+    pub stt: ServiceClient,
+    pub llm: ServiceClient,
+    /// Shared by the HTTP TTS backends, which get a circuit of their own with `for_upstream`.
+    pub tts: ServiceClient,
+}
+
+/// The client of one service with its timeouts and circuit breaker. Clones share the
+/// connection pool and the circuit.
+#[derive(Clone, Debug)]
+pub struct ServiceClient {
+    client: Client,
+    timeouts: HttpTimeouts,
+    breaker: CircuitBreaker,
+}
+
+impl HttpClients {
+    /// Builds the clients from the `http` section and the timeouts of each service.
+    ///
+    /// # Returns
+    /// The clients, or why the proxy or CA settings could not be applied.
+    pub fn from_config(config: &AppConfig) -> Result<Self, String> {
+        Ok(HttpClients {
+            stt: ServiceClient::new(STT_SERVICE, &config.http, &config.stt.timeouts, &config.stt.circuit_breaker)?,
+            llm: ServiceClient::new(LLM_SERVICE, &config.http, &config.llm.timeouts, &config.llm.circuit_breaker)?,
+            tts: ServiceClient::new("TTS", &config.http, &config.tts.timeouts, &config.tts.circuit_breaker)?,
+        })
+    }
+
+    /// Clients with the default settings and the timeouts of each service, used when the
+    /// `http` section is invalid.
+    pub fn without_proxy(config: &AppConfig) -> Self {
+        HttpClients::from_config(&AppConfig { http: HttpConfig::default(), ..config.clone() })
+            .expect("default HTTP settings are valid")
+    }
+}
+
+impl ServiceClient {
+    pub fn new(service: &str, http: &HttpConfig, timeouts: &HttpTimeouts, breaker: &CircuitBreakerConfig) -> Result<Self, String> {
+        Ok(ServiceClient {
+            client: build_client(http, timeouts)?,
+            timeouts: timeouts.clone(),
+            breaker: CircuitBreaker::new(service, breaker),
+        })
+    }
+
+    /// The same connection pool with a circuit of its own, for another upstream such as
+    /// one of several TTS backends.
+    pub fn for_upstream(&self, service: &str) -> Self {
+        ServiceClient { breaker: self.breaker.for_service(service), ..self.clone() }
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    pub fn post(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.post(url)
+    }
+
+    /// Turns a request to `service` that got no response into an error: `Timeout` when
+    /// the connect or read timeout ran out, `ServiceUnavailable` otherwise.
+    pub(crate) fn send_error(&self, service: &str, error: &reqwest::Error) -> AssistantError {
+        if error.is_timeout() {
+            let after = if error.is_connect() { self.timeouts.connect() } else { self.timeouts.read() };
+            return AssistantError::Timeout { service: service.to_string(), after };
+        }
+        AssistantError::unreachable(service, error_chain(error))
+    }
+}
+
+fn build_client(http: &HttpConfig, timeouts: &HttpTimeouts) -> Result<Client, String> {
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(timeouts.connect())
+        .read_timeout(timeouts.read())
+        .pool_idle_timeout(Duration::from_secs(http.pool_idle_timeout_secs))
+        .pool_max_idle_per_host(http.pool_max_idle_per_host)
+        .tcp_keepalive(Duration::from_secs(60));
+
+    // An explicit proxy replaces the one reqwest would take from the HTTP(S)_PROXY variables
+    let no_proxy = http.no_proxy.as_deref().and_then(NoProxy::from_string);
+    if let Some(url) = &http.http_proxy {
+        let proxy = Proxy::http(url).map_err(|e| format!("Invalid http_proxy: {}", e))?;
+        builder = builder.proxy(proxy.no_proxy(no_proxy.clone()));
+    }
+    if let Some(url) = &http.https_proxy {
+        let proxy = Proxy::https(url).map_err(|e| format!("Invalid https_proxy: {}", e))?;
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+
+    // Extra trusted roots, e.g. a company CA that re-signs TLS traffic
+    if let Some(path) = &http.ca_certificate {
+        let pem = std::fs::read(path).map_err(|e| format!("Failed to read CA certificate {}: {}", path.display(), e))?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| format!("Invalid CA certificate {}: {}", path.display(), e))?;
+        if certificates.is_empty() {
+            return Err(format!("No certificate found in {}", path.display()));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    builder.build().map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+// The error with its chain of causes, which tell e.g. a DNS failure from a TLS one
+fn error_chain(error: &reqwest::Error) -> String {
+    let mut details = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        details.push_str(&format!("\nCaused by: {}", cause));
+        source = cause.source();
+    }
+    details
+}
@@ -1,171 +1,285 @@
+pub mod analytics;
+pub mod app_paths;
+pub mod attachments;
+pub mod audio_ipc;
+mod audio_format;
 mod chathandle;
-
-pub use chathandle::{invoke_llm_chat, Message};
+pub mod circuit_breaker;
+mod config;
+pub mod error;
+pub mod events;
+pub mod http;
+pub mod log_writer;
+pub mod metrics;
+pub mod pipeline;
+pub mod playback;
+pub mod redaction;
+pub mod retry;
+pub mod secrets;
+mod telemetry;
+mod tts;
+pub mod tts_cache;
+pub mod usage;
+pub mod workflow_logger;
+
+pub use chathandle::{
+    chat, invoke_llm_chat, ChatContext, ChatRequest, ChatResult, ContentPart, FinishReason, GenerationParams, ImageDetail, ImageUrl, Message,
+    MessageContent, ReasoningEffort, ResponseFormat,
+};
+pub use error::AssistantError;
+pub use tokio_util::sync::CancellationToken;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
-use reqwest::multipart;
-use tokio::fs::File;
 use tauri::Manager; // For app_handle.state(), app_handle.clone() etc.
 use tauri::AppHandle; // Added for emitting events
 use tauri::Emitter; // Added for emit_all
+use tauri::ipc::{Request, Response}; // Raw binary IPC for audio payloads
 use tauri_plugin_shell::ShellExt; // For app_handle.shell()
 use tauri_plugin_shell::process::CommandChild;
 // Removed Sidecar import as it's not found
 use std::sync::Mutex;
 use std::path::PathBuf; // Added for PathBuf
-use std::env; // Added for std::env::temp_dir()
+use tts_cache::{TtsCache, TtsCacheStats};
+use tts::TtsFallbackChain;
+use config::AppConfig;
+use analytics::LatencyReport;
+use playback::{BargeIn, PlaybackEngine, PlaybackStatus};
+use std::sync::Arc;
+use workflow_logger::WorkflowLogSink;
+use metrics::Metrics;
+use redaction::Redactor;
+use http::HttpClients;
+use circuit_breaker::ServiceHealth;
+use secrets::SecretStore;
+use usage::{UsageLedger, UsageReport};
+use pipeline::{AppDirs, Services, SpeechRequest};
+use events::{AssistantEvent, EventSink};
 
 // State to hold the child process handle
 struct SttServiceHandle(Mutex<Option<CommandChild>>);
 
-#[derive(Clone, serde::Serialize)]
-pub struct ProcessingStageUpdatePayload { // Made public
-    stage: String,
-    message: Option<String>,
-}
+// State to hold the TTS audio cache (None if the cache directory could not be opened)
+struct TtsCacheState(Arc<Mutex<Option<TtsCache>>>);
 
-#[tauri::command]
-async fn invoke_stt_transcription(app_handle: AppHandle, audio_data: Vec<u8>) -> Result<String, String> {
-    // Changed emit_all to emit, to align with user preference and see if it resolves method not found
-    if let Err(e) = app_handle.emit("processing_stage_update", ProcessingStageUpdatePayload {
-        stage: "TRANSCRIBING".to_string(),
-        message: Some("Transcribing voice...".to_string()),
-    }) {
-        eprintln!("Failed to emit processing_stage_update event: {}", e);
-    }
+// State to hold the destination of workflow timing records (shared by all commands)
+pub(crate) struct WorkflowLogState(pub(crate) Arc<dyn WorkflowLogSink>);
 
-    // TODO: Make the STT service URL configurable
-    let url = "http://127.0.0.1:5000/transcribe";
-
-    // Create a temporary file on the Rust side
-    // Use std::env::temp_dir() to get the system's temporary directory
-    let temp_dir_path = std::env::temp_dir();
-    // Create a unique filename within the system's temp directory
-    let temp_file_name = format!("recording_{}.wav", chrono::Utc::now().timestamp_millis());
-    let temp_file_path = temp_dir_path.join(temp_file_name);
-
-    // Write the audio data to the temporary file
-    use tokio::io::AsyncWriteExt;
-    let mut temp_file = match File::create(&temp_file_path).await {
-        Ok(f) => f,
-        Err(e) => return Err(format!("Failed to create temporary audio file: {:?} - {}", temp_file_path, e)),
-    };
-    if let Err(e) = temp_file.write_all(&audio_data).await {
-        return Err(format!("Failed to write audio data to temporary file: {:?} - {}", temp_file_path, e));
-    }
+// State to hold the redaction rules applied to logs and saved recordings
+struct PrivacyState(Arc<Redactor>);
+
+// State to hold the metrics registry (also fed by the workflow log sink)
+struct MetricsState(Arc<Metrics>);
+
+// State to hold the encrypted API key store (shared with the LLM and TTS requests)
+pub(crate) struct SecretsState(pub(crate) Arc<SecretStore>);
+
+// State to hold the LLM usage ledger (recorded by invoke_llm_chat)
+pub(crate) struct UsageState(pub(crate) Arc<UsageLedger>);
+
+// State to hold the token of the requests in flight; `cancel_pending_requests` cancels it
+// and puts a fresh one in its place for the requests that follow
+pub(crate) struct CancelState(Mutex<CancellationToken>);
 
-    // Ensure data is flushed to disk
-    if let Err(e) = temp_file.sync_all().await {
-        eprintln!("Warning: Failed to sync temporary audio file to disk: {:?} - {}",temp_file_path, e);
+impl CancelState {
+    pub(crate) fn token(&self) -> CancellationToken {
+        self.0.lock().unwrap().clone()
     }
+}
 
 
-    // Create multipart form data
-    let part = multipart::Part::bytes(audio_data) // Use the received audio_data directly
-        .file_name("audio.wav") // Use a generic filename
-        .mime_str("audio/wav") // Assuming WAV format from frontend (TODO: Frontend conversion)
-        .map_err(|e| format!("Failed to create multipart part: {}", e))?;
+// `tracing` target of the events forwarded from sidecar processes
+const SIDECAR_TRACING_TARGET: &str = "mivis::sidecar";
 
-    let form = multipart::Form::new().part("audio", part);
+/// Forwards pipeline events to the frontend.
+pub struct TauriEventSink(pub AppHandle);
 
-    // Send the request to the Python STT service
-    let client = reqwest::Client::new();
-    let response = match client.post(url).multipart(form).send().await {
-        Ok(res) => res,
-        Err(e) => {
-            // Clean up temporary file on error
-            cleanup_temp_file(&temp_file_path.to_string_lossy()).await;
-            return Err(format!("Failed to send request to STT service: {}", e));
+impl EventSink for TauriEventSink {
+    fn emit(&self, event: AssistantEvent) {
+        if let Err(e) = self.0.emit(event.name(), &event) {
+            tracing::warn!("Failed to emit {} event: {}", event.name(), e);
         }
-    };
-
-    // Check if the request was successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_else(|_| "No response body".to_string());
-        // Clean up temporary file on error
-        cleanup_temp_file(&temp_file_path.to_string_lossy()).await;
-        return Err(format!("STT service returned error status {}: {}", status, text));
     }
+}
 
-    // Parse the JSON response
-    let json_response: serde_json::Value = match response.json().await {
-        Ok(json) => json,
-        Err(e) => {
-            // Clean up temporary file on error
-            cleanup_temp_file(&temp_file_path.to_string_lossy()).await;
-            return Err(format!("Failed to parse STT service response JSON: {}", e));
-        }
-    };
+#[tauri::command]
+async fn invoke_stt_transcription(
+    app_handle: AppHandle,
+    config: tauri::State<'_, AppConfig>,
+    http: tauri::State<'_, HttpClients>,
+    workflow_log: tauri::State<'_, WorkflowLogState>,
+    privacy: tauri::State<'_, PrivacyState>,
+    cancel_state: tauri::State<'_, CancelState>,
+    request: Request<'_>,
+) -> Result<String, AssistantError> {
+    // The recording arrives as the raw invoke body (see audio_ipc)
+    let audio_data = audio_ipc::bytes_from_body(request.body(), "audioData").map_err(AssistantError::InvalidInput)?;
+
+    let events = TauriEventSink(app_handle);
+    pipeline::transcribe(audio_data, &config.stt, &http.stt, workflow_log.0.clone(), &privacy.0, &events, &cancel_state.token()).await
+}
 
-    // Extract the transcription
-    let transcription = json_response["transcription"].as_str()
-        .ok_or_else(|| "Transcription field not found in response".to_string())?
-        .to_string();
+#[tauri::command]
+async fn synthesize_speech(
+    app_handle: AppHandle,
+    cache_state: tauri::State<'_, TtsCacheState>,
+    tts_chain: tauri::State<'_, TtsFallbackChain>,
+    workflow_log: tauri::State<'_, WorkflowLogState>,
+    cancel_state: tauri::State<'_, CancelState>,
+    text: String,
+    voice: Option<String>,
+    speed: Option<f32>,
+    response_format: Option<String>,
+    sample_rate: Option<u32>,
+) -> Result<Response, AssistantError> {
+    let request = SpeechRequest { text, voice, speed, response_format, sample_rate };
+    let events = TauriEventSink(app_handle);
+    let audio_data = pipeline::synthesize(request, &tts_chain, &cache_state.0, workflow_log.0.clone(), &events, &cancel_state.token()).await?;
+    // Returned as a raw binary response, received as an ArrayBuffer by the frontend
+    Ok(Response::new(audio_data))
+}
 
-    // Clean up the temporary audio file after successful transcription
-    cleanup_temp_file(&temp_file_path.to_string_lossy()).await;
+// Abandons every STT, LLM and TTS request in flight, including their retries; they fail
+// with a `cancelled` error. Requests started afterwards are not affected.
+#[tauri::command]
+fn cancel_pending_requests(cancel_state: tauri::State<'_, CancelState>) {
+    let token = std::mem::replace(&mut *cancel_state.0.lock().unwrap(), CancellationToken::new());
+    token.cancel();
+}
 
-    Ok(transcription)
+// Whether each upstream service is up (closed), down (open) or being probed (half_open);
+// changes arrive as `service_health_changed` events
+#[tauri::command]
+fn get_service_health(http: tauri::State<'_, HttpClients>, tts_chain: tauri::State<'_, TtsFallbackChain>) -> Vec<ServiceHealth> {
+    pipeline::service_health(&http, &tts_chain)
 }
 
+// Stores an API key (e.g. XAI_API_KEY) in the encrypted secret store. Secret values are
+// never sent back to the frontend; only their names can be listed.
 #[tauri::command]
-async fn synthesize_speech(app_handle: AppHandle, text: String, voice: Option<String>) -> Result<Vec<u8>, String> {
-    // Changed emit_all to emit
-    if let Err(e) = app_handle.emit("processing_stage_update", ProcessingStageUpdatePayload {
-        stage: "SYNTHESIZING_VOICE".to_string(),
-        message: None,
-    }) {
-        eprintln!("Failed to emit processing_stage_update event: {}", e);
-    }
+fn set_secret(secrets: tauri::State<'_, SecretsState>, name: String, value: String) -> Result<(), AssistantError> {
+    secrets.0.set(&name, &value)?;
+    tracing::info!("Stored secret {}", name);
+    Ok(())
+}
 
-    let url = "http://localhost:8298/v1/audio/speech";
-    let default_voice = "diep-chi".to_string(); // Default voice if none provided
-    let selected_voice = voice.unwrap_or(default_voice);
-
-    // Create JSON payload for the request
-    let payload = serde_json::json!({
-        "model": "tts-1",
-        "input": text,
-        "voice": selected_voice
-    });
-
-    // Send the request to the VietTTS service
-    let client = reqwest::Client::new();
-    let response = match client.post(url)
-        .header("Content-Type", "application/json")
-        .header("Authorization", "Bearer viet-tts")
-        .json(&payload)
-        .send().await {
-            Ok(res) => res,
-            Err(e) => return Err(format!("Failed to send request to VietTTS service: {}", e))
-        };
-
-    // Check if the request was successful
-    if !response.status().is_success() {
-        let status = response.status();
-        let text = response.text().await.unwrap_or_else(|_| "No response body".to_string());
-        return Err(format!("VietTTS service returned error status {}: {}", status, text));
+// Returns whether the secret existed
+#[tauri::command]
+fn delete_secret(secrets: tauri::State<'_, SecretsState>, name: String) -> Result<bool, AssistantError> {
+    let deleted = secrets.0.delete(&name)?;
+    if deleted {
+        tracing::info!("Deleted secret {}", name);
     }
+    Ok(deleted)
+}
+
+#[tauri::command]
+fn list_secret_names(secrets: tauri::State<'_, SecretsState>) -> Result<Vec<String>, AssistantError> {
+    secrets.0.names()
+}
+
+// Unlocks a store whose key is derived from a passphrase; the first unlock sets it
+#[tauri::command]
+async fn unlock_secrets(secrets: tauri::State<'_, SecretsState>, passphrase: String) -> Result<(), AssistantError> {
+    // Deriving the key takes a noticeable time, so keep it off the main thread and the runtime
+    let secrets = secrets.0.clone();
+    tauri::async_runtime::spawn_blocking(move || secrets.unlock(&passphrase))
+        .await
+        .map_err(|e| AssistantError::Internal(format!("Unlock task failed: {}", e)))?
+}
+
+#[tauri::command]
+async fn get_tts_cache_stats(cache_state: tauri::State<'_, TtsCacheState>) -> Result<TtsCacheStats, AssistantError> {
+    pipeline::tts_cache_stats(&cache_state.0).await
+}
+
+#[tauri::command]
+async fn clear_tts_cache(cache_state: tauri::State<'_, TtsCacheState>) -> Result<(), AssistantError> {
+    pipeline::clear_tts_cache(&cache_state.0).await
+}
 
-    // Get the audio data as bytes
-    let audio_data = match response.bytes().await {
-        Ok(bytes) => bytes.to_vec(),
-        Err(e) => return Err(format!("Failed to retrieve audio data from VietTTS service: {}", e))
-    };
+#[tauri::command]
+fn play_audio(engine: tauri::State<'_, PlaybackEngine>, request: Request<'_>) -> Result<u64, AssistantError> {
+    let audio_data = audio_ipc::bytes_from_body(request.body(), "audioData").map_err(AssistantError::InvalidInput)?;
+    engine.enqueue(audio_data).map_err(AssistantError::Internal)
+}
+
+#[tauri::command]
+fn pause_playback(engine: tauri::State<'_, PlaybackEngine>) -> Result<(), AssistantError> {
+    engine.pause().map_err(AssistantError::Internal)
+}
+
+#[tauri::command]
+fn resume_playback(engine: tauri::State<'_, PlaybackEngine>) -> Result<(), AssistantError> {
+    engine.resume().map_err(AssistantError::Internal)
+}
+
+#[tauri::command]
+fn stop_playback(engine: tauri::State<'_, PlaybackEngine>) -> Result<(), AssistantError> {
+    engine.stop().map_err(AssistantError::Internal)
+}
 
-    Ok(audio_data)
+#[tauri::command]
+fn get_playback_status(engine: tauri::State<'_, PlaybackEngine>) -> PlaybackStatus {
+    engine.status()
 }
 
-// Helper function to clean up the temporary file
-async fn cleanup_temp_file(file_path: &str) {
-    if let Err(e) = tokio::fs::remove_file(file_path).await {
-        eprintln!("Failed to clean up temporary file {}: {}", file_path, e);
-    } else {
-        println!("Cleaned up temporary file: {}", file_path);
+#[tauri::command]
+fn set_barge_in_enabled(barge_in: tauri::State<'_, BargeIn>, enabled: bool) {
+    barge_in.set_enabled(enabled);
+}
+
+// Feeds one microphone frame (mono 16-bit little-endian samples, sent as the raw invoke body)
+// to the voice activity detector.
+// If the user starts speaking while audio is playing, playback is stopped and a
+// `barge_in` event tells the frontend to start a new voice turn.
+#[tauri::command]
+fn process_vad_frame(
+    app_handle: AppHandle,
+    engine: tauri::State<'_, PlaybackEngine>,
+    barge_in: tauri::State<'_, BargeIn>,
+    request: Request<'_>,
+) -> Result<bool, AssistantError> {
+    let samples = audio_ipc::samples_from_body(request.body(), "samples").map_err(AssistantError::InvalidInput)?;
+    let frame = barge_in.process_frame(&engine, &samples).map_err(AssistantError::Internal)?;
+
+    if frame.barged_in {
+        if let Err(e) = app_handle.emit("barge_in", ()) {
+            tracing::warn!("Failed to emit barge_in event: {}", e);
+        }
     }
+
+    Ok(frame.speech_detected)
 }
 
+// Computes per-stage latency statistics from the workflow log.
+// `window_hours` limits the report to recent history; omit it to cover the whole log.
+#[tauri::command]
+fn get_latency_report(app_handle: AppHandle, window_hours: Option<f64>) -> Result<LatencyReport, AssistantError> {
+    let log_dir = app_handle.path().app_log_dir()
+        .map_err(|e| AssistantError::Internal(format!("Failed to resolve app log directory: {}", e)))?;
+    pipeline::latency_report(&log_dir, window_hours)
+}
+
+// Tokens and spend of the LLM calls per model and conversation, with the state of the
+// daily and monthly budgets. `window_hours` limits the report to recent calls.
+#[tauri::command]
+fn get_usage_report(usage: tauri::State<'_, UsageState>, window_hours: Option<f64>) -> Result<UsageReport, AssistantError> {
+    pipeline::usage_report(&usage.0, window_hours)
+}
+
+// Reads a file of the app data directory ($APPDATA) as a content part to send in a
+// message: a downscaled base64 image or an excerpt of a text document. `path` may be
+// relative to the directory.
+#[tauri::command]
+async fn attach_file(app_handle: AppHandle, config: tauri::State<'_, AppConfig>, path: String) -> Result<ContentPart, AssistantError> {
+    let data_dir = app_handle.path().app_data_dir()
+        .map_err(|e| AssistantError::Internal(format!("Failed to resolve app data directory: {}", e)))?;
+    let attachments = config.attachments.clone();
+    // Decoding and resizing a photo takes a while, so keep it off the async runtime
+    tauri::async_runtime::spawn_blocking(move || attachments::attach(std::path::Path::new(&path), &data_dir, &attachments))
+        .await
+        .map_err(|e| AssistantError::Internal(format!("Attachment task failed: {}", e)))?
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -174,7 +288,56 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init()) // Initialize the filesystem plugin
         .plugin(tauri_plugin_shell::init()) // Initialize the shell plugin
         .manage(SttServiceHandle(Default::default())) // Add state to manage the child process
+        .manage(CancelState(Mutex::new(CancellationToken::new())))
         .setup(|app| {
+            // Load config.json and start logging, the TTS chain and the cache (shared with mivis-cli)
+            let services = Services::start(&AppDirs {
+                config_dir: app.path().app_config_dir().ok(),
+                cache_dir: app.path().app_cache_dir().ok(),
+                log_dir: app.path().app_log_dir().ok(),
+                data_dir: app.path().app_data_dir().ok(),
+            });
+            services.install_tracing();
+            // Keys still in .env (loaded by main.rs) move into the secret store
+            services.import_env_file(std::path::Path::new(".env"));
+            let Services { config, tts_chain, tts_cache, http, secrets, usage, workflow_log, redactor, metrics, log_guard, .. } = services;
+            if let Some(guard) = log_guard {
+                app.manage(guard);
+            }
+            app.manage(PrivacyState(redactor));
+            app.manage(SecretsState(secrets));
+            app.manage(UsageState(usage));
+
+            // Serve the metrics on localhost if enabled in the config
+            if config.metrics.enabled {
+                let port = config.metrics.port;
+                let served_metrics = metrics.clone();
+                tauri::async_runtime::spawn(async move {
+                    match metrics::bind(port).await {
+                        Ok(listener) => {
+                            tracing::info!("Serving metrics at http://127.0.0.1:{}/metrics", port);
+                            metrics::serve(listener, served_metrics).await;
+                        }
+                        Err(e) => tracing::warn!("{}", e),
+                    }
+                });
+            }
+            app.manage(MetricsState(metrics));
+            app.manage(WorkflowLogState(workflow_log));
+            app.manage(tts_chain);
+            app.manage(config);
+            app.manage(http);
+            app.manage(TtsCacheState(tts_cache));
+
+            // Start the native playback engine, forwarding its events to the frontend
+            let event_handle = app.handle().clone();
+            app.manage(PlaybackEngine::new(playback::sink::default_sink, move |event| {
+                if let Err(e) = event_handle.emit("playback_event", event) {
+                    tracing::warn!("Failed to emit playback_event: {}", e);
+                }
+            }));
+            app.manage(BargeIn::new());
+
             let app_handle = app.handle().clone(); // app_handle is 'static and can be moved
             
             tauri::async_runtime::spawn(async move {
@@ -195,16 +358,16 @@ pub fn run() {
                 let stt_service_working_dir = project_root.join("packages").join("stt").join("src");
 
                 if !stt_service_working_dir.exists() {
-                    eprintln!("Error: STT service working directory does not exist: {:?}", stt_service_working_dir);
+                    tracing::error!("STT service working directory does not exist: {:?}", stt_service_working_dir);
                     return; // Or handle error appropriately
                 }
                 if !stt_service_working_dir.is_dir() {
-                     eprintln!("Error: STT service working directory is not a directory: {:?}", stt_service_working_dir);
+                     tracing::error!("STT service working directory is not a directory: {:?}", stt_service_working_dir);
                     return; // Or handle error appropriately
                 }
 
 
-                println!("Launching STT service script: {:?}", script_path);
+                tracing::info!("Launching STT service script: {:?}", script_path);
                 
                 // Canonicalize and then convert to string for current_dir, attempting to avoid UNC path issues with cmd.exe
                 let canonical_working_dir = stt_service_working_dir.canonicalize()
@@ -217,7 +380,7 @@ pub fn run() {
                     working_dir_str
                 };
 
-                println!("STT service working directory (final for cmd): {:?}", final_working_dir_str);
+                tracing::info!("STT service working directory (final for cmd): {:?}", final_working_dir_str);
                 let script_path_str = script_path.to_string_lossy().to_string();
                 let final_script_path_str = if script_path_str.starts_with("\\\\?\\") {
                     script_path_str.trim_start_matches("\\\\?\\").to_string()
@@ -230,47 +393,78 @@ pub fn run() {
                     .args(["/C", &final_script_path_str]) // Use /C to execute the script
                     .current_dir(PathBuf::from(final_working_dir_str.clone())); 
 
-                println!("Attempting to spawn sidecar command: cmd.exe /C {:?} in {:?}", final_script_path_str, final_working_dir_str);
+                tracing::info!("Attempting to spawn sidecar command: cmd.exe /C {:?} in {:?}", final_script_path_str, final_working_dir_str);
 
                 match command_to_run.spawn() {
                      Ok(child_tuple) => { 
-                        println!("Sidecar command spawned. PID: {:?}", child_tuple.1.pid()); 
+                        tracing::info!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", pid = child_tuple.1.pid(), "Sidecar command spawned"); 
+                        let sidecar_metrics = app_handle.state::<MetricsState>().0.clone();
+                        sidecar_metrics.record_sidecar_start("stt");
                         
                         *stt_service_state_in_async.0.lock().unwrap() = Some(child_tuple.1); 
 
                         let mut receiver = child_tuple.0; 
+                        // Sidecar output may contain transcripts, so it is left out in no-content mode
+                        let no_content = app_handle.state::<PrivacyState>().0.no_content();
                         tauri::async_runtime::spawn(async move {
                             while let Some(event) = receiver.recv().await { 
                                 match event {
+                                    // Sidecar output becomes structured events, so it lands in the app log
+                                    tauri_plugin_shell::process::CommandEvent::Stdout(_) | tauri_plugin_shell::process::CommandEvent::Stderr(_) if no_content => {}
                                     tauri_plugin_shell::process::CommandEvent::Stdout(line_bytes) => {
-                                        println!("[Sidecar STDOUT]: {}", String::from_utf8_lossy(&line_bytes));
+                                        let line = String::from_utf8_lossy(&line_bytes);
+                                        tracing::info!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", stream = "stdout", "{}", line.trim_end());
                                     }
                                     tauri_plugin_shell::process::CommandEvent::Stderr(line_bytes) => {
-                                        eprintln!("[Sidecar STDERR]: {}", String::from_utf8_lossy(&line_bytes));
+                                        let line = String::from_utf8_lossy(&line_bytes);
+                                        tracing::warn!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", stream = "stderr", "{}", line.trim_end());
                                     }
                                     tauri_plugin_shell::process::CommandEvent::Error(message) => {
-                                        eprintln!("[Sidecar ERROR]: {}", message);
+                                        tracing::error!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", "{}", message);
                                     }
                                     tauri_plugin_shell::process::CommandEvent::Terminated(payload) => {
-                                        println!("[Sidecar Terminated]: Code: {:?}, Signal: {:?}", payload.code, payload.signal);
+                                        sidecar_metrics.record_sidecar_exit("stt");
+                                        tracing::info!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", code = ?payload.code, signal = ?payload.signal, "Sidecar terminated");
                                     }
                                     _ => {} 
                                 }
                             }
-                            println!("[Sidecar] Event stream ended.");
+                            tracing::info!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", "Event stream ended");
                         });
                     }
                     Err(e) => {
-                        eprintln!("Failed to spawn STT service sidecar: {}", e);
+                        tracing::error!(target: SIDECAR_TRACING_TARGET, sidecar = "stt", "Failed to spawn STT service sidecar: {}", e);
                     }
                 }
             });
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![invoke_stt_transcription, synthesize_speech, invoke_llm_chat])
+        .invoke_handler(tauri::generate_handler![
+            invoke_stt_transcription,
+            synthesize_speech,
+            invoke_llm_chat,
+            cancel_pending_requests,
+            get_service_health,
+            set_secret,
+            delete_secret,
+            list_secret_names,
+            unlock_secrets,
+            get_tts_cache_stats,
+            clear_tts_cache,
+            play_audio,
+            pause_playback,
+            resume_playback,
+            stop_playback,
+            get_playback_status,
+            set_barge_in_enabled,
+            process_vad_frame,
+            get_latency_report,
+            get_usage_report,
+            attach_file
+        ])
         .build(tauri::generate_context!())
         .expect("error while building tauri application")
-        .run(|_app_handle, event| match event { // Handle exit
+        .run(|app_handle, event| match event { // Handle exit
             tauri::RunEvent::ExitRequested { api: _, .. } => { // Fix unused 'api'
                 // If you need to prevent exit or do cleanup before exit
                 // _api.prevent_exit(); 
@@ -280,7 +474,15 @@ pub fn run() {
                 // We need to get the handle from somewhere or have passed it
                 // This part is tricky as _app_handle here might not have the state
                 // A better way is to use the main window's on_close_requested
-                println!("Tauri app is exiting. STT service should be cleaned up if managed.");
+                tracing::info!("Tauri app is exiting. STT service should be cleaned up if managed.");
+
+                // Flush the workflow log and the app log before the process ends
+                if let Some(workflow_log) = app_handle.try_state::<WorkflowLogState>() {
+                    workflow_log.0.flush();
+                }
+                if let Some(log_guard) = app_handle.try_state::<telemetry::AppLogGuard>() {
+                    drop(log_guard.0.lock().unwrap().take());
+                }
             }
             _ => {}
         });
@@ -0,0 +1,310 @@
+// log_writer.rs
+//
+// Background writer for the workflow log. Lines are sent over a channel to a worker thread,
+// which appends them in batches, rotates the file by size and date, gzips the rotated
+// segments and deletes segments past the retention age. Callers never touch the file.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use chrono::{Local, NaiveDate};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+// Lines are written once this many are waiting...
+const BATCH_SIZE: usize = 64;
+// ...or when no new line arrived for this long
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default size at which the active log file is rotated (10 MiB).
+pub const DEFAULT_MAX_FILE_BYTES: u64 = 10 * 1024 * 1024;
+/// Default age after which rotated segments are deleted.
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// When the active file is rotated and how long rotated segments are kept.
+#[derive(Clone, Copy, Debug)]
+pub struct RotationPolicy {
+    /// Rotate once the file would grow beyond this size.
+    pub max_file_bytes: u64,
+    /// Delete rotated segments older than this many days; 0 keeps them forever.
+    pub retention_days: u32,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        RotationPolicy { max_file_bytes: DEFAULT_MAX_FILE_BYTES, retention_days: DEFAULT_RETENTION_DAYS }
+    }
+}
+
+enum WriterCommand {
+    Line(String),
+    Flush(mpsc::Sender<()>),
+    Shutdown,
+}
+
+/// Handle to the writer thread of one log file. Dropping it writes the remaining
+/// lines and stops the thread.
+pub struct LogWriter {
+    commands: mpsc::Sender<WriterCommand>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LogWriter {
+    /// Starts the writer thread for `log_file`.
+    ///
+    /// # Arguments
+    /// * `log_file` - The active log file; rotated segments are stored next to it.
+    /// * `policy` - When to rotate and how long to keep rotated segments.
+    pub fn spawn(log_file: &Path, policy: RotationPolicy) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let mut worker = RotatingFile::new(log_file.to_path_buf(), policy);
+
+        let handle = thread::Builder::new()
+            .name("mivis-log-writer".to_string())
+            .spawn(move || worker.run(receiver))
+            .map_err(|e| tracing::error!("Failed to spawn log writer thread: {}", e))
+            .ok();
+
+        LogWriter { commands: sender, worker: Mutex::new(handle) }
+    }
+
+    /// Queues a line; it is written by the background thread.
+    pub fn write_line(&self, line: String) {
+        // The thread only stops on shutdown, after which lines are dropped
+        let _ = self.commands.send(WriterCommand::Line(line));
+    }
+
+    /// Blocks until every line queued so far has been written to disk.
+    pub fn flush(&self) {
+        let (ack_sender, ack_receiver) = mpsc::channel();
+        if self.commands.send(WriterCommand::Flush(ack_sender)).is_ok() {
+            let _ = ack_receiver.recv();
+        }
+    }
+
+    /// Writes the remaining lines and stops the thread. Called when the app exits.
+    pub fn shutdown(&self) {
+        let _ = self.commands.send(WriterCommand::Shutdown);
+        if let Some(handle) = self.worker.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for LogWriter {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// State of the writer thread
+struct RotatingFile {
+    path: PathBuf,
+    policy: RotationPolicy,
+    file: Option<BufWriter<File>>,
+    size_bytes: u64,
+    segment_date: NaiveDate,
+    pending: Vec<String>,
+}
+
+impl RotatingFile {
+    fn new(path: PathBuf, policy: RotationPolicy) -> Self {
+        RotatingFile {
+            path,
+            policy,
+            file: None,
+            size_bytes: 0,
+            segment_date: Local::now().date_naive(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn run(&mut self, receiver: mpsc::Receiver<WriterCommand>) {
+        self.open_active_file();
+        self.delete_expired_segments();
+
+        loop {
+            match receiver.recv_timeout(FLUSH_INTERVAL) {
+                Ok(WriterCommand::Line(line)) => {
+                    self.pending.push(line);
+                    if self.pending.len() >= BATCH_SIZE {
+                        self.write_pending();
+                    }
+                }
+                Ok(WriterCommand::Flush(ack)) => {
+                    self.write_pending();
+                    let _ = ack.send(());
+                }
+                Ok(WriterCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                    // Lines queued before the shutdown are still written
+                    while let Ok(WriterCommand::Line(line)) = receiver.try_recv() {
+                        self.pending.push(line);
+                    }
+                    self.write_pending();
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => self.write_pending(),
+            }
+        }
+    }
+
+    fn open_active_file(&mut self) {
+        if let Some(parent_dir) = self.path.parent() {
+            if !parent_dir.as_os_str().is_empty() {
+                if let Err(e) = fs::create_dir_all(parent_dir) {
+                    tracing::error!("Failed to create logs directory {}: {}", parent_dir.display(), e);
+                }
+            }
+        }
+
+        match OpenOptions::new().create(true).append(true).open(&self.path) {
+            Ok(file) => {
+                let metadata = file.metadata().ok();
+                self.size_bytes = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+                // An existing file belongs to the day it was last written
+                self.segment_date = metadata
+                    .and_then(|m| m.modified().ok())
+                    .map(|modified| chrono::DateTime::<Local>::from(modified).date_naive())
+                    .unwrap_or_else(|| Local::now().date_naive());
+                self.file = Some(BufWriter::new(file));
+            }
+            Err(e) => {
+                tracing::error!("Failed to open workflow log file {}: {}", self.path.display(), e);
+                self.file = None;
+            }
+        }
+    }
+
+    fn write_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let batch_bytes: u64 = self.pending.iter().map(|line| line.len() as u64 + 1).sum();
+        let new_day = Local::now().date_naive() != self.segment_date;
+        let too_large = self.size_bytes + batch_bytes > self.policy.max_file_bytes;
+        if self.size_bytes > 0 && (new_day || too_large) {
+            self.rotate();
+        }
+        if self.file.is_none() {
+            self.open_active_file(); // Retry after an earlier failure
+        }
+
+        let lines = std::mem::take(&mut self.pending);
+        let Some(file) = self.file.as_mut() else {
+            tracing::error!("Dropped {} workflow log lines, the log file is not writable", lines.len());
+            return;
+        };
+        let result = lines.iter()
+            .try_for_each(|line| writeln!(file, "{}", line))
+            .and_then(|_| file.flush());
+        match result {
+            Ok(()) => self.size_bytes += batch_bytes,
+            Err(e) => tracing::error!("Failed to write to workflow log: {}", e),
+        }
+    }
+
+    // Moves the active file aside as a gzipped segment and starts a new one
+    fn rotate(&mut self) {
+        self.file = None; // Closes the active file
+
+        let segment = segment_path(&self.path, self.segment_date);
+        match fs::rename(&self.path, &segment) {
+            Ok(()) => {
+                if let Err(e) = gzip_file(&segment) {
+                    tracing::warn!("Failed to compress log segment {}: {}", segment.display(), e);
+                }
+            }
+            Err(e) => tracing::error!("Failed to rotate workflow log {}: {}", self.path.display(), e),
+        }
+
+        self.open_active_file();
+        self.segment_date = Local::now().date_naive();
+        self.delete_expired_segments();
+    }
+
+    fn delete_expired_segments(&self) {
+        if self.policy.retention_days == 0 {
+            return;
+        }
+        let max_age = Duration::from_secs(u64::from(self.policy.retention_days) * 24 * 60 * 60);
+        let now = SystemTime::now();
+
+        for segment in list_segments(&self.path) {
+            let expired = fs::metadata(&segment)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .is_some_and(|age| age > max_age);
+            if expired {
+                match fs::remove_file(&segment) {
+                    Ok(()) => tracing::info!("Deleted expired log segment {}", segment.display()),
+                    Err(e) => tracing::warn!("Failed to delete log segment {}: {}", segment.display(), e),
+                }
+            }
+        }
+    }
+}
+
+/// Lists the rotated segments of `log_file` (e.g. `workflow_timings.20250519-1.log.gz`),
+/// oldest first.
+pub fn list_segments(log_file: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (log_file.parent(), log_file.file_stem()) else {
+        return Vec::new();
+    };
+    let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let active_name = log_file.file_name().map(|name| name.to_string_lossy().to_string());
+
+    let mut segments: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                name.starts_with(&prefix) && Some(&name) != active_name.as_ref()
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    // Segment names sort chronologically: date first, then the sequence number
+    segments.sort_by_key(|path| segment_sort_key(path));
+    segments
+}
+
+// Picks a free name such as `workflow_timings.20250519-2.log` for a segment of `date`
+fn segment_path(log_file: &Path, date: NaiveDate) -> PathBuf {
+    let stem = log_file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = log_file.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_else(|| "log".to_string());
+    let mut sequence = 1;
+    loop {
+        let name = format!("{}.{}-{}.{}", stem, date.format("%Y%m%d"), sequence, extension);
+        let candidate = log_file.with_file_name(&name);
+        let gzipped = log_file.with_file_name(format!("{}.gz", name));
+        if !candidate.exists() && !gzipped.exists() {
+            return candidate;
+        }
+        sequence += 1;
+    }
+}
+
+// (date, sequence) parsed from a segment name, so that segment 10 sorts after segment 9
+fn segment_sort_key(path: &Path) -> (String, u32) {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let label = name.split('.').nth(1).unwrap_or_default();
+    let (date, sequence) = label.split_once('-').unwrap_or((label, "0"));
+    (date.to_string(), sequence.parse().unwrap_or(0))
+}
+
+// Compresses `path` into `<path>.gz` and removes the original
+fn gzip_file(path: &Path) -> io::Result<()> {
+    let gz_path = PathBuf::from(format!("{}.gz", path.display()));
+    let mut input = File::open(path)?;
+    let mut encoder = GzEncoder::new(File::create(&gz_path)?, Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::remove_file(path)
+}
@@ -1,6 +1,8 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+// The GUI only; command-line tools (e.g. the latency report) are in `mivis-cli`, which
+// keeps its console on Windows
 fn main() {
     // Initialize dotenv to load environment variables from .env file
     dotenv::from_filename(".env").ok();
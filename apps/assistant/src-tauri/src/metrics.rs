@@ -0,0 +1,235 @@
+// metrics.rs
+//
+// Counters and histograms for long-running sessions, served on an opt-in localhost
+// endpoint in the Prometheus text exposition format. The values come from the workflow
+// log records (`Metrics` is a `WorkflowLogSink`), so they match the workflow log exactly.
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::workflow_logger::{RecordStatus, WorkflowEventType, WorkflowLogRecord, WorkflowLogSink};
+
+/// Default port of the metrics endpoint.
+pub const DEFAULT_METRICS_PORT: u16 = 9464;
+
+// Upper bounds (in seconds) of the latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+// Largest request head read from a scraper
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+#[derive(Clone, Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    stage_requests: BTreeMap<(String, &'static str), u64>, // (stage, status)
+    stage_latency: BTreeMap<String, Histogram>,
+    stage_errors: BTreeMap<(String, String), u64>, // (stage, error kind)
+    workflows: BTreeMap<(String, &'static str), u64>, // (workflow type, status)
+    workflow_latency: BTreeMap<String, Histogram>,
+    cache_lookups: BTreeMap<&'static str, u64>, // hit / miss
+    sidecar_starts: BTreeMap<String, u64>,
+    sidecar_exits: BTreeMap<String, u64>,
+}
+
+/// Metrics registry. Feed it workflow records through `WorkflowLogSink` and render it
+/// with `render`.
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Counts a (re)start of a sidecar process.
+    pub fn record_sidecar_start(&self, sidecar: &str) {
+        *self.state.lock().unwrap().sidecar_starts.entry(sidecar.to_string()).or_default() += 1;
+    }
+
+    /// Counts a sidecar process that terminated.
+    pub fn record_sidecar_exit(&self, sidecar: &str) {
+        *self.state.lock().unwrap().sidecar_exits.entry(sidecar.to_string()).or_default() += 1;
+    }
+
+    /// Renders every metric in the Prometheus text exposition format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        write_header(&mut out, "mivis_stage_requests_total", "counter", "Completed workflow stages by stage and status.");
+        for ((stage, status), count) in &state.stage_requests {
+            let _ = writeln!(out, "mivis_stage_requests_total{{stage=\"{}\",status=\"{}\"}} {}", escape(stage), status, count);
+        }
+
+        write_header(&mut out, "mivis_stage_duration_seconds", "histogram", "Latency of workflow stages.");
+        for (stage, histogram) in &state.stage_latency {
+            write_histogram(&mut out, "mivis_stage_duration_seconds", &format!("stage=\"{}\"", escape(stage)), histogram);
+        }
+
+        write_header(&mut out, "mivis_stage_errors_total", "counter", "Failed workflow stages by stage and error kind.");
+        for ((stage, kind), count) in &state.stage_errors {
+            let _ = writeln!(out, "mivis_stage_errors_total{{stage=\"{}\",kind=\"{}\"}} {}", escape(stage), escape(kind), count);
+        }
+
+        write_header(&mut out, "mivis_workflows_total", "counter", "Finished workflows by type and status.");
+        for ((workflow, status), count) in &state.workflows {
+            let _ = writeln!(out, "mivis_workflows_total{{workflow=\"{}\",status=\"{}\"}} {}", escape(workflow), status, count);
+        }
+
+        write_header(&mut out, "mivis_workflow_duration_seconds", "histogram", "End-to-end latency of workflows.");
+        for (workflow, histogram) in &state.workflow_latency {
+            write_histogram(&mut out, "mivis_workflow_duration_seconds", &format!("workflow=\"{}\"", escape(workflow)), histogram);
+        }
+
+        write_header(&mut out, "mivis_tts_cache_lookups_total", "counter", "TTS cache lookups by result.");
+        for (result, count) in &state.cache_lookups {
+            let _ = writeln!(out, "mivis_tts_cache_lookups_total{{result=\"{}\"}} {}", result, count);
+        }
+
+        write_header(&mut out, "mivis_sidecar_starts_total", "counter", "Sidecar process starts.");
+        for (sidecar, count) in &state.sidecar_starts {
+            let _ = writeln!(out, "mivis_sidecar_starts_total{{sidecar=\"{}\"}} {}", escape(sidecar), count);
+        }
+
+        write_header(&mut out, "mivis_sidecar_restarts_total", "counter", "Sidecar process starts after the first one.");
+        for (sidecar, count) in &state.sidecar_starts {
+            let _ = writeln!(out, "mivis_sidecar_restarts_total{{sidecar=\"{}\"}} {}", escape(sidecar), count.saturating_sub(1));
+        }
+
+        write_header(&mut out, "mivis_sidecar_exits_total", "counter", "Sidecar processes that terminated.");
+        for (sidecar, count) in &state.sidecar_exits {
+            let _ = writeln!(out, "mivis_sidecar_exits_total{{sidecar=\"{}\"}} {}", escape(sidecar), count);
+        }
+
+        out
+    }
+}
+
+impl WorkflowLogSink for Metrics {
+    fn write_record(&self, record: &WorkflowLogRecord) {
+        let status = match record.status {
+            Some(RecordStatus::Error) => "error",
+            _ => "ok",
+        };
+        let seconds = record.duration_us.unwrap_or(0) as f64 / 1_000_000.0;
+        let mut state = self.state.lock().unwrap();
+
+        match record.event_type {
+            WorkflowEventType::Start => {}
+            WorkflowEventType::Stage => {
+                let stage = record.stage.clone().unwrap_or_default();
+                *state.stage_requests.entry((stage.clone(), status)).or_default() += 1;
+                state.stage_latency.entry(stage.clone()).or_default().observe(seconds);
+                if record.error.is_some() {
+                    let kind = record.error_kind.clone().unwrap_or_else(|| "other".to_string());
+                    *state.stage_errors.entry((stage, kind)).or_default() += 1;
+                }
+                if let Some(cache_hit) = record.attributes.get("cache_hit").and_then(|v| v.as_bool()) {
+                    *state.cache_lookups.entry(if cache_hit { "hit" } else { "miss" }).or_default() += 1;
+                }
+            }
+            WorkflowEventType::End => {
+                let workflow = record.workflow_type.clone().unwrap_or_else(|| "unknown".to_string());
+                *state.workflows.entry((workflow.clone(), status)).or_default() += 1;
+                state.workflow_latency.entry(workflow).or_default().observe(seconds);
+            }
+        }
+    }
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+        let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, bound, count);
+    }
+    let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, histogram.count);
+    let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+    let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+}
+
+// Escapes a label value as required by the exposition format
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Binds the metrics endpoint to localhost only.
+///
+/// # Arguments
+/// * `port` - The port to listen on; 0 picks a free port.
+pub async fn bind(port: u16) -> Result<TcpListener, String> {
+    TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port))).await
+        .map_err(|e| format!("Failed to bind metrics endpoint on 127.0.0.1:{}: {}", port, e))
+}
+
+/// Serves `GET /metrics` on `listener` until the task is dropped.
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let metrics = metrics.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_connection(stream, &metrics).await {
+                        tracing::debug!("Metrics request failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => tracing::warn!("Failed to accept metrics connection: {}", e),
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    // Read the request head; the body (if any) is ignored
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request_line = String::from_utf8_lossy(&request).lines().next().unwrap_or_default().to_string();
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4; charset=utf-8", metrics.render()),
+        (Some("GET"), Some(_)) => ("404 Not Found", "text/plain; charset=utf-8", "Not found, try /metrics\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", "Only GET is supported\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, content_type, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,562 @@
+// pipeline.rs
+//
+// The backend pipeline without Tauri: the services shared by every entry point (config,
+// logging, TTS chain and cache) and the STT/TTS steps. The GUI commands in lib.rs and the
+// `mivis-cli` binary are thin wrappers around these functions.
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use reqwest::multipart;
+use tokio::fs::File;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::analytics::{self, LatencyReport};
+use crate::app_paths;
+use crate::audio_format::{self, AudioFormat};
+use crate::chathandle::ChatContext;
+use crate::circuit_breaker::ServiceHealth;
+use crate::config::{self, AppConfig, SttConfig};
+use crate::error::AssistantError;
+use crate::events::{AssistantEvent, EventSink};
+use crate::http::{HttpClients, ServiceClient};
+use crate::metrics::Metrics;
+use crate::redaction::{RedactingSink, Redactor};
+use crate::secrets::SecretStore;
+use crate::telemetry::{self, AppLogGuard};
+use crate::tts::{self, SynthesisRequest, TtsFallbackChain};
+use crate::tts_cache::{self, TtsCache, TtsCacheKey, TtsCacheStats};
+use crate::usage::{UsageLedger, UsageReport};
+use crate::workflow_logger::{self, FanoutSink, WorkflowLogSink, WorkflowTimings};
+
+// Name of the STT service in errors
+pub(crate) const STT_SERVICE: &str = "STT service";
+
+// Speech rate bounds accepted by OpenAI-compatible /v1/audio/speech endpoints
+const DEFAULT_TTS_SPEED: f32 = 1.0;
+const MIN_TTS_SPEED: f32 = 0.25;
+const MAX_TTS_SPEED: f32 = 4.0;
+// Output rates from telephony to full-band speech; the audio is resampled in memory
+const MIN_TTS_SAMPLE_RATE: u32 = 8000;
+const MAX_TTS_SAMPLE_RATE: u32 = 48000;
+
+/// The per-app directories. `None` means the directory could not be resolved.
+pub struct AppDirs {
+    pub config_dir: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    pub log_dir: Option<PathBuf>,
+    pub data_dir: Option<PathBuf>,
+}
+
+impl AppDirs {
+    /// The same directories as Tauri's path resolver, for code that runs without an AppHandle.
+    pub fn from_platform() -> Self {
+        AppDirs {
+            config_dir: app_paths::app_config_dir(),
+            cache_dir: app_paths::app_cache_dir(),
+            log_dir: app_paths::app_log_dir(),
+            data_dir: app_paths::app_data_dir(),
+        }
+    }
+}
+
+/// Everything the pipeline steps need, built once from config.json.
+pub struct Services {
+    pub config: AppConfig,
+    pub tts_chain: TtsFallbackChain,
+    pub tts_cache: Arc<Mutex<Option<TtsCache>>>, // None if the cache directory could not be opened
+    /// The HTTP clients of the STT, LLM and TTS requests, shared by every call.
+    pub http: HttpClients,
+    /// API keys, read by the LLM and TTS requests.
+    pub secrets: Arc<SecretStore>,
+    /// Token usage and spend of the LLM calls.
+    pub usage: Arc<UsageLedger>,
+    pub workflow_log: Arc<dyn WorkflowLogSink>,
+    pub redactor: Arc<Redactor>,
+    pub metrics: Arc<Metrics>,
+    /// The subscriber writing the app log and the child stages of workflows; None if
+    /// logging could not be set up. See `install_tracing`.
+    pub tracing: Option<tracing::Dispatch>,
+    /// Dropping the guard flushes the app log.
+    pub log_guard: Option<AppLogGuard>,
+    /// Where the app log and the workflow log are written.
+    pub log_dir: PathBuf,
+}
+
+impl Services {
+    /// Loads config.json, sets up logging and builds the TTS chain and cache.
+    /// Problems are logged and replaced by defaults, so this always succeeds.
+    pub fn start(dirs: &AppDirs) -> Self {
+        // Problems are reported once logging is running, since its level comes from the config
+        let (config, config_error) = match &dirs.config_dir {
+            Some(config_dir) => match AppConfig::load(&config_dir.join(config::CONFIG_FILE_NAME)) {
+                Ok(config) => (config, None),
+                Err(e) => (AppConfig::default(), Some(format!("{}. Using defaults.", e))),
+            },
+            None => (AppConfig::default(), Some("Failed to resolve app config directory, using default config".to_string())),
+        };
+
+        // Workflow timing records and the app log go to the app log directory
+        let log_dir = dirs.log_dir.clone().unwrap_or_else(|| {
+            eprintln!("[WARN] Failed to resolve app log directory, logging to the temp dir");
+            env::temp_dir()
+        });
+        let workflow_log_path = log_dir.join(workflow_logger::WORKFLOW_LOG_FILE_NAME);
+
+        // Everything written to the logs passes through the redactor first
+        let (redactor, redaction_error) = match Redactor::from_config(&config.privacy) {
+            Ok(redactor) => (Arc::new(redactor), None),
+            Err(e) => (Arc::new(Redactor::builtin(config.privacy.no_content_logging)), Some(e)),
+        };
+        let workflow_log_file = config.logging.workflow_log_format
+            .file_sink(&workflow_log_path.to_string_lossy(), config.logging.workflow_rotation());
+        // Metrics are fed from the same records as the workflow log, before redaction
+        let metrics = Arc::new(Metrics::new());
+        let workflow_log: Arc<dyn WorkflowLogSink> = Arc::new(FanoutSink(vec![
+            Arc::new(RedactingSink::new(workflow_log_file, redactor.clone())),
+            metrics.clone(),
+        ]));
+
+        // Route tracing spans and events to the console, the app log and the workflow log.
+        // The subscriber is used on this thread while starting, and installed by the caller.
+        let (dispatch, log_guard) = match telemetry::build_subscriber(&log_dir, &config.logging, workflow_log.clone(), redactor.clone()) {
+            Ok((dispatch, guard)) => (Some(dispatch), Some(guard)),
+            Err(e) => {
+                eprintln!("[ERROR] {}", e);
+                (None, None)
+            }
+        };
+        let _startup_tracing = dispatch.as_ref().map(tracing::dispatcher::set_default);
+        if let Some(e) = config_error {
+            tracing::warn!("{}", e);
+        }
+        if let Some(e) = redaction_error {
+            tracing::warn!("{}. Using the built-in redaction rules.", e);
+        }
+
+        // Open the encrypted secret store in the app data directory
+        let secrets = Arc::new(match &dirs.data_dir {
+            Some(data_dir) => SecretStore::open(data_dir, &config.secrets).unwrap_or_else(|e| {
+                tracing::error!("Failed to open the secret store, API keys are read from the environment only: {}", e);
+                SecretStore::env_only()
+            }),
+            None => {
+                tracing::warn!("Failed to resolve app data directory, API keys are read from the environment only");
+                SecretStore::env_only()
+            }
+        });
+        if secrets.is_locked() {
+            tracing::info!("The secret store is locked until its passphrase is entered");
+        }
+
+        // Open the LLM usage ledger next to the secrets
+        let usage = match &dirs.data_dir {
+            Some(data_dir) => UsageLedger::open(data_dir, &config.usage).unwrap_or_else(|e| {
+                tracing::error!("Failed to open the usage ledger, LLM usage is not saved: {}", e);
+                UsageLedger::in_memory(&config.usage)
+            }),
+            None => UsageLedger::in_memory(&config.usage),
+        };
+
+        // Build the HTTP clients once so connections are reused across requests
+        let http = HttpClients::from_config(&config).unwrap_or_else(|e| {
+            tracing::warn!("{}. Using direct connections and the built-in CA roots.", e);
+            HttpClients::without_proxy(&config)
+        });
+
+        // Build the TTS fallback chain
+        let tts_chain = tts::build_chain(&config.tts, &http.tts, &secrets);
+        tracing::info!("TTS backends (in fallback order): {:?}", tts_chain.backend_names());
+
+        // Open the TTS audio cache in the app cache directory
+        let tts_cache = match &dirs.cache_dir {
+            Some(cache_dir) => TtsCache::open(&cache_dir.join("tts"), tts_cache::DEFAULT_MAX_CACHE_BYTES)
+                .map_err(|e| tracing::warn!("Failed to open TTS cache: {}", e))
+                .ok(),
+            None => {
+                tracing::warn!("Failed to resolve app cache directory for TTS cache");
+                None
+            }
+        };
+
+        Services {
+            config,
+            tts_chain,
+            tts_cache: Arc::new(Mutex::new(tts_cache)),
+            http,
+            secrets,
+            usage: Arc::new(usage),
+            workflow_log,
+            redactor,
+            metrics,
+            tracing: dispatch,
+            log_guard,
+            log_dir,
+        }
+    }
+
+    /// Makes the subscriber of these services the global one, for every thread. Called
+    /// once by the app and the CLI; a subscriber installed earlier is kept.
+    pub fn install_tracing(&self) {
+        if let Some(dispatch) = &self.tracing {
+            if let Err(e) = dispatch.clone().try_init() {
+                eprintln!("[WARN] Keeping the tracing subscriber installed earlier: {}", e);
+            }
+        }
+    }
+
+    /// The context of a `chat` call with these services.
+    pub fn chat_context<'a>(&'a self, events: &'a dyn EventSink, cancel: &'a CancellationToken) -> ChatContext<'a> {
+        ChatContext {
+            llm: &self.config.llm,
+            client: &self.http.llm,
+            secrets: &self.secrets,
+            usage: &self.usage,
+            workflow_log: self.workflow_log.clone(),
+            events,
+            cancel,
+        }
+    }
+
+    /// Moves the configured API keys from a dotenv file into the secret store. Keys left
+    /// in the file keep working, since lookups fall back to the environment.
+    pub fn import_env_file(&self, path: &Path) {
+        if self.secrets.is_locked() {
+            return;
+        }
+        match self.secrets.import_env_file(path, &self.config.secrets.import_from_env_file) {
+            Ok(imported) if !imported.is_empty() => {
+                tracing::info!("Moved {} from {} into the secret store", imported.join(", "), path.display());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Failed to import {} into the secret store: {}", path.display(), e),
+        }
+    }
+}
+
+/// Options of a speech synthesis request; `None` picks the default.
+#[derive(Clone, Debug, Default)]
+pub struct SpeechRequest {
+    pub text: String,
+    pub voice: Option<String>,
+    pub speed: Option<f32>,
+    pub response_format: Option<String>,
+    pub sample_rate: Option<u32>,
+}
+
+/// Transcribes a WAV recording with the STT service.
+///
+/// # Arguments
+/// * `audio_data` - The recording as WAV bytes.
+/// * `stt` - Where the STT service runs.
+/// * `client` - The HTTP client of the STT service.
+/// * `workflow_log` - Where the timing of the transcription is recorded.
+/// * `redactor` - In no-content mode the recording is not saved to the temp dir.
+/// * `events` - Receives the TRANSCRIBING stage update.
+/// * `cancel` - Cancelling it abandons the request and its retries.
+///
+/// # Returns
+/// The transcription, or why it failed.
+pub async fn transcribe(
+    audio_data: Vec<u8>,
+    stt: &SttConfig,
+    client: &ServiceClient,
+    workflow_log: Arc<dyn WorkflowLogSink>,
+    redactor: &Redactor,
+    events: &dyn EventSink,
+    cancel: &CancellationToken,
+) -> Result<String, AssistantError> {
+    events.emit(AssistantEvent::stage("TRANSCRIBING", Some("Transcribing voice...")));
+
+    // Time the transcription; the stage guard records it even when a step below fails
+    let timings = WorkflowTimings::with_sink("stt", workflow_log);
+    let mut stage = timings.stage("STT");
+    stage.set_attribute("audio_bytes", audio_data.len());
+
+    let stage_span = stage.span().clone();
+    let result: Result<String, AssistantError> = async {
+        // Keep a copy of the recording in the temp dir while it is transcribed, unless
+        // only timings may be logged
+        let temp_file_path = if redactor.no_content() {
+            None
+        } else {
+            Some(save_temp_recording(&audio_data).await?)
+        };
+
+        // Send the recording to the Python STT service, retrying transient failures, unless
+        // its circuit breaker says it is down
+        let retries = stt.retry.policy();
+        let request = retries.run(STT_SERVICE, cancel, |attempt| {
+            let audio_data = audio_data.clone();
+            let stage = &stage;
+            async move {
+                // Create multipart form data
+                let part = multipart::Part::bytes(audio_data)
+                    .file_name("audio.wav") // Use a generic filename
+                    .mime_str("audio/wav") // Assuming WAV format from frontend (TODO: Frontend conversion)
+                    .map_err(|e| AssistantError::Internal(format!("Failed to create multipart part: {}", e)))?;
+                let form = multipart::Form::new().part("audio", part);
+
+                let mut request_span = stage.child("stt:request");
+                request_span.set_attribute("attempt", attempt);
+                let send_result = client.post(&stt.url).multipart(form).send().await;
+                request_span.complete(&send_result);
+                let response = send_result.map_err(|e| client.send_error(STT_SERVICE, &e))?;
+
+                // Check if the request was successful
+                if !response.status().is_success() {
+                    return Err(AssistantError::from_response(STT_SERVICE, response).await);
+                }
+
+                // Parse the JSON response and extract the transcription
+                let json_response: serde_json::Value = response.json().await
+                    .map_err(|e| AssistantError::invalid_response(STT_SERVICE, format!("Failed to parse JSON: {}", e)))?;
+                json_response["transcription"].as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| AssistantError::invalid_response(STT_SERVICE, "Transcription field not found in response"))
+            }
+        });
+        let result = client.breaker().call(events, request).await;
+
+        // Clean up the temporary audio file whatever the outcome
+        cleanup_temp_file(temp_file_path.as_deref()).await;
+        result
+    }.instrument(stage_span).await;
+
+    stage.complete(&result);
+    finish_workflow(&timings, &result);
+    result
+}
+
+/// Synthesizes speech, serving repeated phrases from the cache.
+///
+/// # Arguments
+/// * `request` - The text and the optional voice, speed, format and sample rate.
+/// * `tts_chain` - The backends tried in order.
+/// * `tts_cache` - The audio cache, if it could be opened.
+/// * `workflow_log` - Where the timing of the synthesis is recorded.
+/// * `events` - Receives the SYNTHESIZING_VOICE stage update.
+/// * `cancel` - Cancelling it abandons the synthesis and its retries.
+///
+/// # Returns
+/// The audio in the requested format (WAV by default), or why it could not be synthesized.
+pub async fn synthesize(
+    request: SpeechRequest,
+    tts_chain: &TtsFallbackChain,
+    tts_cache: &Arc<Mutex<Option<TtsCache>>>,
+    workflow_log: Arc<dyn WorkflowLogSink>,
+    events: &dyn EventSink,
+    cancel: &CancellationToken,
+) -> Result<Vec<u8>, AssistantError> {
+    events.emit(AssistantEvent::stage("SYNTHESIZING_VOICE", None));
+
+    let SpeechRequest { text, voice, speed, response_format, sample_rate } = request;
+
+    // Time the synthesis; the stage guard records it even when a step below fails
+    let timings = WorkflowTimings::with_sink("tts", workflow_log);
+    let mut stage = timings.stage("TTS");
+    stage.set_attribute("text_chars", text.chars().count());
+
+    let stage_span = stage.span().clone();
+    let result: Result<Vec<u8>, AssistantError> = async {
+        // Validate the optional synthesis settings
+        let selected_speed = speed.unwrap_or(DEFAULT_TTS_SPEED);
+        if !(MIN_TTS_SPEED..=MAX_TTS_SPEED).contains(&selected_speed) {
+            return Err(AssistantError::InvalidInput(format!("Speed must be between {} and {}, got {}", MIN_TTS_SPEED, MAX_TTS_SPEED, selected_speed)));
+        }
+        let requested_format = match response_format.as_deref() {
+            Some(format) => AudioFormat::parse(format).map_err(AssistantError::InvalidInput)?,
+            None => AudioFormat::Wav, // The frontend plays the audio back as WAV
+        };
+        if let Some(rate) = sample_rate.filter(|rate| !(MIN_TTS_SAMPLE_RATE..=MAX_TTS_SAMPLE_RATE).contains(rate)) {
+            return Err(AssistantError::InvalidInput(format!(
+                "Sample rate must be between {} and {} Hz, got {}", MIN_TTS_SAMPLE_RATE, MAX_TTS_SAMPLE_RATE, rate
+            )));
+        }
+
+        let cache_key = TtsCacheKey {
+            text: &text,
+            voice: voice.as_deref().unwrap_or("default"), // None means each backend's default voice
+            speed: selected_speed,
+            format: requested_format.as_str(),
+            sample_rate,
+        };
+
+        let cache_digest = cache_key.digest();
+
+        // Serve repeated phrases from the cache instead of re-synthesizing them
+        let digest = cache_digest.clone();
+        if let Some(audio_data) = with_tts_cache(tts_cache, move |cache| cache.get(&digest)).await.flatten() {
+            stage.set_attribute("cache_hit", true);
+            return Ok(audio_data);
+        }
+
+        // Try the configured backends in order until one produces audio
+        let request = SynthesisRequest {
+            text: text.clone(),
+            voice: voice.clone(),
+            speed: selected_speed,
+            format: requested_format,
+            cancel: cancel.clone(),
+        };
+        stage.set_attribute("cache_hit", false);
+        let mut synthesis_span = stage.child("tts:synthesize");
+        let synthesis_result = tts_chain.synthesize(&request, events).instrument(synthesis_span.span().clone()).await;
+        if let Ok(synthesis) = &synthesis_result {
+            synthesis_span.set_attribute("backend", synthesis.backend.clone());
+        }
+        synthesis_span.complete(&synthesis_result);
+        let synthesis = synthesis_result?;
+        let mut audio_data = synthesis.audio_data;
+
+        // Check the returned audio against the request and convert it if the service could not comply
+        let detected_format = audio_format::detect_format(&audio_data);
+        if !audio_format::matches_request(&audio_data, detected_format, requested_format, sample_rate) {
+            tracing::info!(
+                "Converting synthesized audio from {} to {}{}",
+                detected_format.map(|f| f.as_str()).unwrap_or("unknown format"),
+                requested_format.as_str(),
+                sample_rate.map(|rate| format!(" at {} Hz", rate)).unwrap_or_default()
+            );
+            let convert_span = stage.child("tts:convert");
+            let converted = audio_format::convert_audio(audio_data, detected_format, requested_format, sample_rate).await;
+            convert_span.complete(&converted);
+            audio_data = converted.map_err(AssistantError::Internal)?;
+        }
+
+        // Only cache audio from the primary backend, so fallback voices stop being served
+        // once it has recovered
+        if synthesis.from_primary {
+            let cached_audio = audio_data.clone();
+            if let Some(Err(e)) = with_tts_cache(tts_cache, move |cache| cache.put(&cache_digest, &cached_audio)).await {
+                tracing::warn!("Failed to cache synthesized speech: {}", e);
+            }
+        }
+
+        Ok(audio_data)
+    }.instrument(stage_span).await;
+
+    stage.complete(&result);
+    finish_workflow(&timings, &result);
+    result
+}
+
+// Runs `f` on the TTS cache, if it is open. The cache reads and writes files under its
+// lock, so this happens on a blocking thread rather than on the async workers.
+async fn with_tts_cache<T: Send + 'static>(
+    tts_cache: &Arc<Mutex<Option<TtsCache>>>,
+    f: impl FnOnce(&mut TtsCache) -> T + Send + 'static,
+) -> Option<T> {
+    let tts_cache = tts_cache.clone();
+    tokio::task::spawn_blocking(move || tts_cache.lock().unwrap().as_mut().map(f))
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("TTS cache task failed: {}", e);
+            None
+        })
+}
+
+/// Hit/miss statistics of the TTS cache.
+pub async fn tts_cache_stats(tts_cache: &Arc<Mutex<Option<TtsCache>>>) -> Result<TtsCacheStats, AssistantError> {
+    with_tts_cache(tts_cache, |cache| cache.stats())
+        .await
+        .ok_or_else(cache_unavailable)
+}
+
+/// Deletes every cached phrase.
+pub async fn clear_tts_cache(tts_cache: &Arc<Mutex<Option<TtsCache>>>) -> Result<(), AssistantError> {
+    with_tts_cache(tts_cache, |cache| cache.clear().map_err(AssistantError::Internal))
+        .await
+        .unwrap_or_else(|| Err(cache_unavailable()))
+}
+
+fn cache_unavailable() -> AssistantError {
+    AssistantError::ServiceUnavailable {
+        service: "TTS cache".to_string(),
+        reason: "the cache directory could not be opened".to_string(),
+    }
+}
+
+/// The circuit state of every upstream service: STT, LLM and the HTTP TTS backends.
+pub fn service_health(http: &HttpClients, tts_chain: &TtsFallbackChain) -> Vec<ServiceHealth> {
+    let mut health = vec![http.stt.breaker().health(), http.llm.breaker().health()];
+    health.extend(tts_chain.health());
+    health
+}
+
+/// Computes per-stage latency statistics from the workflow log in `log_dir`.
+/// `window_hours` limits the report to recent history; `None` covers the whole log.
+pub fn latency_report(log_dir: &Path, window_hours: Option<f64>) -> Result<LatencyReport, AssistantError> {
+    let until = chrono::Local::now().fixed_offset();
+    let since = window_start(window_hours, until)?;
+    analytics::latency_report_from_file(&log_dir.join(workflow_logger::WORKFLOW_LOG_FILE_NAME), since, until)
+        .map_err(AssistantError::Internal)
+}
+
+/// Sums the LLM usage recorded in the ledger, with the state of the budgets.
+/// `window_hours` limits the report to recent calls; `None` covers all of them.
+pub fn usage_report(usage: &UsageLedger, window_hours: Option<f64>) -> Result<UsageReport, AssistantError> {
+    let since = window_start(window_hours, chrono::Local::now().fixed_offset())?;
+    Ok(usage.report(since))
+}
+
+// The start of a report window of `window_hours` ending at `until`; None without a window
+fn window_start(window_hours: Option<f64>, until: chrono::DateTime<chrono::FixedOffset>) -> Result<Option<chrono::DateTime<chrono::FixedOffset>>, AssistantError> {
+    let hours = match window_hours {
+        Some(hours) if hours > 0.0 => hours,
+        Some(_) => return Err(AssistantError::InvalidInput("window_hours must be greater than zero".to_string())),
+        None => return Ok(None),
+    };
+    // Casting saturates, and windows reaching past the representable dates fail below
+    chrono::TimeDelta::try_seconds((hours * 3600.0) as i64)
+        .and_then(|window| until.checked_sub_signed(window))
+        .map(Some)
+        .ok_or_else(|| AssistantError::InvalidInput(format!("window_hours is too large, got {}", hours)))
+}
+
+// Helper function to log the end of a command's workflow with its outcome
+pub(crate) fn finish_workflow<T>(timings: &WorkflowTimings, result: &Result<T, AssistantError>) {
+    match result {
+        Ok(_) => timings.finalize_and_log("Completed."),
+        Err(e) => timings.finalize_with_error("Failed.", e),
+    }
+}
+
+// Helper function to write the recording to a temporary file
+async fn save_temp_recording(audio_data: &[u8]) -> Result<PathBuf, AssistantError> {
+    // Create a temporary file on the Rust side
+    // Use std::env::temp_dir() to get the system's temporary directory
+    let temp_dir_path = std::env::temp_dir();
+    // Create a unique filename within the system's temp directory
+    let temp_file_name = format!("recording_{}.wav", chrono::Utc::now().timestamp_millis());
+    let temp_file_path = temp_dir_path.join(temp_file_name);
+
+    // Write the audio data to the temporary file
+    use tokio::io::AsyncWriteExt;
+    let mut temp_file = match File::create(&temp_file_path).await {
+        Ok(f) => f,
+        Err(e) => return Err(AssistantError::io(format!("Failed to create temporary audio file {:?}", temp_file_path), e)),
+    };
+    if let Err(e) = temp_file.write_all(audio_data).await {
+        return Err(AssistantError::io(format!("Failed to write audio data to temporary file {:?}", temp_file_path), e));
+    }
+
+    // Ensure data is flushed to disk
+    if let Err(e) = temp_file.sync_all().await {
+        tracing::warn!("Failed to sync temporary audio file to disk: {:?} - {}",temp_file_path, e);
+    }
+
+    Ok(temp_file_path)
+}
+
+// Helper function to clean up the temporary file
+async fn cleanup_temp_file(file_path: Option<&Path>) {
+    let Some(file_path) = file_path else { return };
+    if let Err(e) = tokio::fs::remove_file(file_path).await {
+        tracing::warn!("Failed to clean up temporary file {}: {}", file_path.display(), e);
+    } else {
+        tracing::debug!("Cleaned up temporary file: {}", file_path.display());
+    }
+}
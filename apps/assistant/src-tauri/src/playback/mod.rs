@@ -0,0 +1,304 @@
+// Native audio playback engine for Mivis Desktop Assistant
+//
+// The engine owns a worker thread with an audio sink and a queue of clips. Commands are
+// sent over a channel and progress is reported through the event callback, so the engine
+// itself does not depend on Tauri.
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod sink;
+pub mod vad;
+
+pub use sink::{AudioSink, NullSink};
+pub use vad::EnergyVad;
+
+// How often the worker checks the sink for completion
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+// How often progress events are emitted while a clip is playing
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaybackState {
+    #[default]
+    Idle,
+    Playing,
+    Paused,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Requested,
+    BargeIn,
+}
+
+/// Snapshot of the engine state returned by `get_playback_status`.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PlaybackStatus {
+    pub state: PlaybackState,
+    pub clip_id: Option<u64>,
+    pub position_ms: u64,
+    pub duration_ms: Option<u64>,
+    pub queued: usize,
+}
+
+/// Events reported by the engine while it works through the queue.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PlaybackEvent {
+    Started { clip_id: u64, duration_ms: Option<u64> },
+    Progress { clip_id: u64, position_ms: u64, duration_ms: Option<u64> },
+    Paused { clip_id: u64 },
+    Resumed { clip_id: u64 },
+    Finished { clip_id: u64 },
+    Stopped { clip_id: Option<u64>, reason: StopReason },
+    Error { clip_id: u64, message: String },
+}
+
+enum PlaybackCommand {
+    Enqueue(u64, Vec<u8>),
+    Pause,
+    Resume,
+    Stop(StopReason),
+    Shutdown,
+}
+
+struct CurrentClip {
+    id: u64,
+    duration: Option<Duration>,
+}
+
+/// Handle to the playback worker. Dropping it shuts the worker down.
+pub struct PlaybackEngine {
+    commands: mpsc::Sender<PlaybackCommand>,
+    status: Arc<Mutex<PlaybackStatus>>,
+    in_flight: Arc<AtomicUsize>, // Clips sent to the worker that it has not received yet
+    next_clip_id: AtomicU64,
+}
+
+impl PlaybackEngine {
+    /// Starts the playback worker.
+    ///
+    /// # Arguments
+    /// * `sink_factory` - Creates the audio sink on the worker thread (audio devices are often not `Send`).
+    /// * `on_event` - Called on the worker thread for every playback event.
+    pub fn new<F, E>(sink_factory: F, on_event: E) -> Self
+    where
+        F: FnOnce() -> Box<dyn AudioSink> + Send + 'static,
+        E: Fn(PlaybackEvent) + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let status = Arc::new(Mutex::new(PlaybackStatus::default()));
+        let worker_status = status.clone();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let worker_in_flight = in_flight.clone();
+
+        thread::Builder::new()
+            .name("mivis-playback".to_string())
+            .spawn(move || run_worker(sink_factory(), receiver, worker_status, worker_in_flight, on_event))
+            .expect("Failed to spawn playback worker thread");
+
+        PlaybackEngine { commands: sender, status, in_flight, next_clip_id: AtomicU64::new(1) }
+    }
+
+    /// Adds a clip to the end of the queue and returns its id. The clip counts as queued
+    /// right away, so `is_active` is true before the worker has picked it up.
+    pub fn enqueue(&self, audio_data: Vec<u8>) -> Result<u64, String> {
+        let clip_id = self.next_clip_id.fetch_add(1, Ordering::Relaxed);
+        // Under the status lock, so the worker never reports the queue without this clip
+        let mut status = self.status.lock().unwrap();
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.send(PlaybackCommand::Enqueue(clip_id, audio_data)) {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            return Err(e);
+        }
+        status.queued += 1;
+        Ok(clip_id)
+    }
+
+    pub fn pause(&self) -> Result<(), String> {
+        self.send(PlaybackCommand::Pause)
+    }
+
+    pub fn resume(&self) -> Result<(), String> {
+        self.send(PlaybackCommand::Resume)
+    }
+
+    /// Stops the current clip and clears the queue.
+    pub fn stop(&self) -> Result<(), String> {
+        self.send(PlaybackCommand::Stop(StopReason::Requested))
+    }
+
+    /// Stops playback because the user started speaking.
+    pub fn barge_in(&self) -> Result<(), String> {
+        self.send(PlaybackCommand::Stop(StopReason::BargeIn))
+    }
+
+    pub fn status(&self) -> PlaybackStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Whether a clip is playing or paused, or clips are waiting in the queue.
+    pub fn is_active(&self) -> bool {
+        let status = self.status.lock().unwrap();
+        status.state != PlaybackState::Idle || status.queued > 0
+    }
+
+    fn send(&self, command: PlaybackCommand) -> Result<(), String> {
+        self.commands.send(command)
+            .map_err(|_| "Playback engine is not running".to_string())
+    }
+}
+
+impl Drop for PlaybackEngine {
+    fn drop(&mut self) {
+        let _ = self.commands.send(PlaybackCommand::Shutdown);
+    }
+}
+
+/// Outcome of one microphone frame fed to `BargeIn::process_frame`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VadFrame {
+    pub speech_detected: bool,
+    /// Playback was stopped because the user started speaking.
+    pub barged_in: bool,
+}
+
+/// Stops playback when the user starts speaking over it.
+pub struct BargeIn {
+    vad: Mutex<EnergyVad>,
+    enabled: AtomicBool,
+}
+
+impl Default for BargeIn {
+    fn default() -> Self {
+        BargeIn { vad: Mutex::new(EnergyVad::default()), enabled: AtomicBool::new(true) }
+    }
+}
+
+impl BargeIn {
+    pub fn new() -> Self {
+        BargeIn::default()
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.vad.lock().unwrap().reset();
+    }
+
+    /// Feeds one frame of mono 16-bit samples to the voice activity detector and stops
+    /// `engine` if speech starts while it is active and barge-in is enabled.
+    pub fn process_frame(&self, engine: &PlaybackEngine, samples: &[i16]) -> Result<VadFrame, String> {
+        let speech_detected = self.vad.lock().unwrap().process_frame(samples);
+
+        let barged_in = speech_detected && self.enabled.load(Ordering::Relaxed) && engine.is_active();
+        if barged_in {
+            engine.barge_in()?;
+            self.vad.lock().unwrap().reset();
+        }
+
+        Ok(VadFrame { speech_detected, barged_in })
+    }
+}
+
+fn run_worker<E>(
+    mut sink: Box<dyn AudioSink>,
+    receiver: mpsc::Receiver<PlaybackCommand>,
+    status: Arc<Mutex<PlaybackStatus>>,
+    in_flight: Arc<AtomicUsize>,
+    on_event: E,
+)
+where
+    E: Fn(PlaybackEvent),
+{
+    let mut queue: VecDeque<(u64, Vec<u8>)> = VecDeque::new();
+    let mut current: Option<CurrentClip> = None;
+    let mut paused = false;
+    let mut last_progress = Instant::now();
+
+    loop {
+        match receiver.recv_timeout(TICK_INTERVAL) {
+            Ok(PlaybackCommand::Enqueue(clip_id, audio_data)) => {
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                queue.push_back((clip_id, audio_data));
+            }
+            Ok(PlaybackCommand::Pause) => {
+                if let (Some(clip), false) = (&current, paused) {
+                    sink.pause();
+                    paused = true;
+                    on_event(PlaybackEvent::Paused { clip_id: clip.id });
+                }
+            }
+            Ok(PlaybackCommand::Resume) => {
+                if let (Some(clip), true) = (&current, paused) {
+                    sink.resume();
+                    paused = false;
+                    on_event(PlaybackEvent::Resumed { clip_id: clip.id });
+                }
+            }
+            Ok(PlaybackCommand::Stop(reason)) => {
+                queue.clear();
+                let clip_id = current.take().map(|clip| clip.id);
+                sink.stop();
+                paused = false;
+                on_event(PlaybackEvent::Stopped { clip_id, reason });
+            }
+            Ok(PlaybackCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                sink.stop();
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        // Report progress or completion of the current clip
+        if let Some(clip) = &current {
+            if !paused && sink.is_finished() {
+                on_event(PlaybackEvent::Finished { clip_id: clip.id });
+                current = None;
+            } else if !paused && last_progress.elapsed() >= PROGRESS_INTERVAL {
+                on_event(PlaybackEvent::Progress {
+                    clip_id: clip.id,
+                    position_ms: sink.position().as_millis() as u64,
+                    duration_ms: clip.duration.map(|d| d.as_millis() as u64),
+                });
+                last_progress = Instant::now();
+            }
+        }
+
+        // Start the next clip once the sink is free
+        while current.is_none() {
+            let Some((clip_id, audio_data)) = queue.pop_front() else { break };
+            match sink.start(audio_data) {
+                Ok(duration) => {
+                    on_event(PlaybackEvent::Started {
+                        clip_id,
+                        duration_ms: duration.map(|d| d.as_millis() as u64),
+                    });
+                    current = Some(CurrentClip { id: clip_id, duration });
+                    last_progress = Instant::now();
+                }
+                Err(message) => on_event(PlaybackEvent::Error { clip_id, message }),
+            }
+        }
+
+        // Loads `in_flight` under the lock, see `enqueue`
+        let mut current_status = status.lock().unwrap();
+        *current_status = PlaybackStatus {
+            state: match (&current, paused) {
+                (None, _) => PlaybackState::Idle,
+                (Some(_), true) => PlaybackState::Paused,
+                (Some(_), false) => PlaybackState::Playing,
+            },
+            clip_id: current.as_ref().map(|clip| clip.id),
+            position_ms: if current.is_some() { sink.position().as_millis() as u64 } else { 0 },
+            duration_ms: current.as_ref().and_then(|clip| clip.duration).map(|d| d.as_millis() as u64),
+            queued: queue.len() + in_flight.load(Ordering::SeqCst),
+        };
+    }
+}
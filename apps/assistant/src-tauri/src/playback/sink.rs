@@ -0,0 +1,152 @@
+// Audio output backends for the playback engine
+use std::time::{Duration, Instant};
+
+use crate::audio_format;
+
+/// An audio output the playback engine can drive. Sinks are created and used on the
+/// engine's worker thread, so they do not need to be `Send`.
+pub trait AudioSink {
+    /// Starts playing a clip, replacing whatever was playing before.
+    /// Returns the total duration of the clip if it is known.
+    fn start(&mut self, audio_data: Vec<u8>) -> Result<Option<Duration>, String>;
+    fn pause(&mut self);
+    fn resume(&mut self);
+    fn stop(&mut self);
+    /// Whether the current clip has played to the end (true when nothing is loaded).
+    fn is_finished(&self) -> bool;
+    /// How far into the current clip playback has progressed.
+    fn position(&self) -> Duration;
+}
+
+/// A sink that plays nothing but keeps time like a real device would.
+/// Used when no audio device is available and for headless testing.
+#[derive(Default)]
+pub struct NullSink {
+    duration: Duration,
+    played: Duration,          // Time accumulated before the last pause
+    resumed_at: Option<Instant>, // Set while "playing"
+}
+
+impl NullSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl AudioSink for NullSink {
+    fn start(&mut self, audio_data: Vec<u8>) -> Result<Option<Duration>, String> {
+        // Only WAV carries enough information to know the duration without decoding
+        let duration = wav_duration(&audio_data);
+        self.duration = duration.unwrap_or_default();
+        self.played = Duration::ZERO;
+        self.resumed_at = Some(Instant::now());
+        Ok(duration)
+    }
+
+    fn pause(&mut self) {
+        if let Some(resumed_at) = self.resumed_at.take() {
+            self.played += resumed_at.elapsed();
+        }
+    }
+
+    fn resume(&mut self) {
+        if self.resumed_at.is_none() {
+            self.resumed_at = Some(Instant::now());
+        }
+    }
+
+    fn stop(&mut self) {
+        self.duration = Duration::ZERO;
+        self.played = Duration::ZERO;
+        self.resumed_at = None;
+    }
+
+    fn is_finished(&self) -> bool {
+        self.position() >= self.duration
+    }
+
+    fn position(&self) -> Duration {
+        let position = self.played + self.resumed_at.map(|r| r.elapsed()).unwrap_or_default();
+        position.min(self.duration)
+    }
+}
+
+fn wav_duration(audio_data: &[u8]) -> Option<Duration> {
+    let (info, data) = audio_format::parse_wav(audio_data)?;
+    let bytes_per_second = info.sample_rate as u64 * info.channels as u64 * info.bits_per_sample as u64 / 8;
+    if bytes_per_second == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(data.len() as f64 / bytes_per_second as f64))
+}
+
+/// Plays audio on the default output device through rodio.
+#[cfg(feature = "native-playback")]
+pub struct RodioSink {
+    _stream: rodio::OutputStream, // Must stay alive for the handle to keep working
+    handle: rodio::OutputStreamHandle,
+    sink: Option<rodio::Sink>,
+}
+
+#[cfg(feature = "native-playback")]
+impl RodioSink {
+    pub fn open() -> Result<Self, String> {
+        let (stream, handle) = rodio::OutputStream::try_default()
+            .map_err(|e| format!("Failed to open default audio output device: {}", e))?;
+        Ok(RodioSink { _stream: stream, handle, sink: None })
+    }
+}
+
+#[cfg(feature = "native-playback")]
+impl AudioSink for RodioSink {
+    fn start(&mut self, audio_data: Vec<u8>) -> Result<Option<Duration>, String> {
+        use rodio::Source;
+
+        self.stop();
+        let source = rodio::Decoder::new(std::io::Cursor::new(audio_data))
+            .map_err(|e| format!("Failed to decode audio for playback: {}", e))?;
+        let duration = source.total_duration();
+        let sink = rodio::Sink::try_new(&self.handle)
+            .map_err(|e| format!("Failed to create audio sink: {}", e))?;
+        sink.append(source);
+        self.sink = Some(sink);
+        Ok(duration)
+    }
+
+    fn pause(&mut self) {
+        if let Some(sink) = &self.sink {
+            sink.pause();
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(sink) = &self.sink {
+            sink.play();
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.stop();
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.sink.as_ref().map(|sink| sink.empty()).unwrap_or(true)
+    }
+
+    fn position(&self) -> Duration {
+        self.sink.as_ref().map(|sink| sink.get_pos()).unwrap_or_default()
+    }
+}
+
+/// Returns the best available sink: the audio device when the `native-playback`
+/// feature is enabled and a device can be opened, otherwise a `NullSink`.
+pub fn default_sink() -> Box<dyn AudioSink> {
+    #[cfg(feature = "native-playback")]
+    match RodioSink::open() {
+        Ok(sink) => return Box::new(sink),
+        Err(e) => tracing::warn!("{}, falling back to silent playback", e),
+    }
+    Box::new(NullSink::new())
+}
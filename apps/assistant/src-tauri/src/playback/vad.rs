@@ -0,0 +1,58 @@
+// Energy based voice activity detection used for barge-in
+
+/// Default level above which a frame counts as speech.
+pub const DEFAULT_THRESHOLD_DBFS: f32 = -35.0;
+/// Default number of consecutive speech frames needed before speech is reported.
+/// With 20-30ms frames this ignores clicks and short noises.
+pub const DEFAULT_MIN_SPEECH_FRAMES: u32 = 5;
+
+/// A simple detector that compares the RMS level of each frame against a threshold.
+pub struct EnergyVad {
+    threshold_dbfs: f32,
+    min_speech_frames: u32,
+    speech_frames: u32,
+}
+
+impl Default for EnergyVad {
+    fn default() -> Self {
+        EnergyVad::new(DEFAULT_THRESHOLD_DBFS, DEFAULT_MIN_SPEECH_FRAMES)
+    }
+}
+
+impl EnergyVad {
+    pub fn new(threshold_dbfs: f32, min_speech_frames: u32) -> Self {
+        EnergyVad { threshold_dbfs, min_speech_frames: min_speech_frames.max(1), speech_frames: 0 }
+    }
+
+    /// Feeds one frame of mono 16-bit samples.
+    ///
+    /// # Returns
+    /// `true` once enough consecutive frames have been above the threshold, and for every
+    /// following speech frame until a quiet frame resets the detector.
+    pub fn process_frame(&mut self, samples: &[i16]) -> bool {
+        if frame_level_dbfs(samples) >= self.threshold_dbfs {
+            self.speech_frames = self.speech_frames.saturating_add(1);
+        } else {
+            self.speech_frames = 0;
+        }
+        self.speech_frames >= self.min_speech_frames
+    }
+
+    pub fn reset(&mut self) {
+        self.speech_frames = 0;
+    }
+}
+
+/// Computes the RMS level of a frame in dB relative to full scale.
+pub fn frame_level_dbfs(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let sum_of_squares: f64 = samples.iter().map(|&s| (s as f64) * (s as f64)).sum();
+    let rms = (sum_of_squares / samples.len() as f64).sqrt() / i16::MAX as f64;
+    if rms <= 0.0 {
+        f32::NEG_INFINITY
+    } else {
+        (20.0 * rms.log10()) as f32
+    }
+}
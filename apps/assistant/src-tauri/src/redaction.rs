@@ -0,0 +1,291 @@
+// redaction.rs
+//
+// Masks personal data and secrets in text before it is written anywhere: workflow log
+// records, the tracing output and saved recordings. Rules are regular expressions; the
+// built-in ones can be switched off and custom ones added in config.json.
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use regex::Regex;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{DefaultFields, Writer};
+use tracing_subscriber::fmt::{FormatFields, MakeWriter};
+
+use crate::config::PrivacyConfig;
+use crate::workflow_logger::{WorkflowLogRecord, WorkflowLogSink};
+
+/// Names of the built-in rules, in the order they are applied.
+pub const BUILTIN_RULES: [&str; 5] = ["api_key", "email", "phone", "national_id", "bank_account"];
+
+/// Replaces the text of errors in no-content mode.
+const OMITTED_CONTENT: &str = "[content omitted]";
+
+// Secrets go first so a key that looks like a number is reported as a key; Vietnamese
+// ID numbers (12-digit CCCD, 9-digit CMND) go before the broader bank account pattern.
+// The number rules skip digits that follow `-`, `=`, `.`, `/` or `:` (uuid groups,
+// `duration_us=...` fields, timestamps); the `pre` group keeps the character before a match.
+fn builtin_pattern(name: &str) -> Option<&'static str> {
+    match name {
+        "api_key" => Some(concat!(
+            r"(?i)\b(?:sk|xai|tvly|pk|rk|key)[-_][A-Za-z0-9_\-]{16,}",
+            r"|\bBearer\s+[A-Za-z0-9._~+/=\-]{16,}",
+            r#"|\b(?:api[_-]?key|access[_-]?token|secret)\b["']?\s*[:=]\s*["']?[^\s"',;]+"#,
+        )),
+        "email" => Some(r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}\b"),
+        // Mobile and landline numbers, with or without the +84 prefix and separators
+        "phone" => Some(r"(?:\+84|\b84|\b0)[\s.\-]?[235789](?:[\s.\-]?\d){8}\b"),
+        "national_id" => Some(r"(?P<pre>^|[^\w\-=.:/])(?:\d{12}|\d{9})\b"),
+        // Account numbers (8-19 digits) and card numbers written in groups of four
+        "bank_account" => Some(r"(?P<pre>^|[^\w\-=.:/])(?:\d{4}(?:[\s\-]\d{4}){2,3}|\d{8,19})\b"),
+        _ => None,
+    }
+}
+
+struct RedactionRule {
+    pattern: Regex,
+    replacement: String,
+}
+
+/// Applies the configured redaction rules.
+pub struct Redactor {
+    rules: Vec<RedactionRule>,
+    no_content: bool,
+}
+
+impl Redactor {
+    /// Builds the redactor from the privacy settings.
+    ///
+    /// # Returns
+    /// The redactor, or an error message naming the first unknown rule or invalid pattern.
+    pub fn from_config(config: &PrivacyConfig) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for name in &config.rules {
+            let pattern = builtin_pattern(name)
+                .ok_or_else(|| format!("Unknown redaction rule '{}', expected one of {:?}", name, BUILTIN_RULES))?;
+            rules.push(RedactionRule {
+                pattern: Regex::new(pattern).map_err(|e| format!("Invalid redaction rule '{}': {}", name, e))?,
+                replacement: builtin_replacement(name),
+            });
+        }
+        for custom in &config.custom_rules {
+            rules.push(RedactionRule {
+                pattern: Regex::new(&custom.pattern)
+                    .map_err(|e| format!("Invalid redaction pattern for '{}': {}", custom.name, e))?,
+                replacement: custom.replacement.clone().unwrap_or_else(|| default_replacement(&custom.name)),
+            });
+        }
+        Ok(Redactor { rules, no_content: config.no_content_logging })
+    }
+
+    /// A redactor with every built-in rule, used when the configured rules are invalid.
+    pub fn builtin(no_content: bool) -> Self {
+        let rules = BUILTIN_RULES.iter()
+            .filter_map(|name| Some(RedactionRule {
+                pattern: Regex::new(builtin_pattern(name)?).ok()?,
+                replacement: builtin_replacement(name),
+            }))
+            .collect();
+        Redactor { rules, no_content }
+    }
+
+    /// Whether only timings may be logged (no transcripts, replies or error text).
+    pub fn no_content(&self) -> bool {
+        self.no_content
+    }
+
+    /// Masks every match of every rule in `text`.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut redacted = Cow::Borrowed(text);
+        for rule in &self.rules {
+            if let Cow::Owned(replaced) = rule.pattern.replace_all(&redacted, rule.replacement.as_str()) {
+                redacted = Cow::Owned(replaced);
+            }
+        }
+        redacted
+    }
+
+    /// Redacts the free-text fields of a workflow log record. In no-content mode the error
+    /// text is dropped, and so are the attributes other than numbers and flags, since
+    /// arrays and objects can hold text too.
+    pub fn redact_record(&self, record: &mut WorkflowLogRecord) {
+        if self.no_content {
+            if record.error.is_some() {
+                record.error = Some(OMITTED_CONTENT.to_string());
+            }
+            record.attributes.retain(|_, value| value.is_number() || value.is_boolean());
+            return;
+        }
+
+        for text in [&mut record.error, &mut record.message].into_iter().flatten() {
+            if let Cow::Owned(redacted) = self.redact(text) {
+                *text = redacted;
+            }
+        }
+        for value in record.attributes.values_mut() {
+            if let Some(text) = value.as_str() {
+                if let Cow::Owned(redacted) = self.redact(text) {
+                    *value = redacted.into();
+                }
+            }
+        }
+    }
+}
+
+// e.g. "[REDACTED_EMAIL]"
+fn default_replacement(rule_name: &str) -> String {
+    format!("[REDACTED_{}]", rule_name.to_ascii_uppercase())
+}
+
+// Puts back the `pre` group of the number rules (empty for the other rules)
+fn builtin_replacement(rule_name: &str) -> String {
+    format!("${{pre}}{}", default_replacement(rule_name))
+}
+
+/// Redacts workflow log records before passing them on to another sink.
+pub struct RedactingSink {
+    inner: Arc<dyn WorkflowLogSink>,
+    redactor: Arc<Redactor>,
+}
+
+impl RedactingSink {
+    pub fn new(inner: Arc<dyn WorkflowLogSink>, redactor: Arc<Redactor>) -> Self {
+        RedactingSink { inner, redactor }
+    }
+}
+
+impl WorkflowLogSink for RedactingSink {
+    fn write_record(&self, record: &WorkflowLogRecord) {
+        let mut record = record.clone();
+        self.redactor.redact_record(&mut record);
+        self.inner.write_record(&record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Wraps a tracing writer so every formatted event is redacted before it is written.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    redactor: Arc<Redactor>,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, redactor: Arc<Redactor>) -> Self {
+        RedactingMakeWriter { inner, redactor }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter { inner: self.inner.make_writer(), redactor: self.redactor.clone(), buffer: Vec::new() }
+    }
+}
+
+/// Collects one formatted event and writes it redacted when flushed or dropped, so a
+/// pattern is never split across two writes.
+pub struct RedactingWriter<W: Write> {
+    inner: W,
+    redactor: Arc<Redactor>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let text = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.buffer.clear();
+            self.inner.write_all(self.redactor.redact(&text).as_bytes())?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for RedactingWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Formats the fields of app log lines. In no-content mode only numbers and flags are
+/// written (durations, counts, attempt numbers): the message and text fields, such as an
+/// error quoting an upstream response, are replaced by a single marker.
+pub struct RedactingFields {
+    redactor: Arc<Redactor>,
+    default: DefaultFields,
+}
+
+impl RedactingFields {
+    pub fn new(redactor: Arc<Redactor>) -> Self {
+        RedactingFields { redactor, default: DefaultFields::new() }
+    }
+}
+
+impl<'writer> FormatFields<'writer> for RedactingFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> std::fmt::Result {
+        if !self.redactor.no_content() {
+            return self.default.format_fields(writer, fields);
+        }
+
+        let mut visitor = NumericFields { writer, result: Ok(()), written: false, omitted: false };
+        fields.record(&mut visitor);
+        if visitor.omitted {
+            visitor.write(format_args!("{}", OMITTED_CONTENT));
+        }
+        visitor.result
+    }
+}
+
+// Writes the numeric and boolean fields, and notes whether anything else was left out
+struct NumericFields<'writer> {
+    writer: Writer<'writer>,
+    result: std::fmt::Result,
+    written: bool,
+    omitted: bool,
+}
+
+impl NumericFields<'_> {
+    fn write(&mut self, field: std::fmt::Arguments<'_>) {
+        if self.result.is_ok() {
+            let separator = if self.written { " " } else { "" };
+            self.result = write!(self.writer, "{}{}", separator, field);
+            self.written = true;
+        }
+    }
+}
+
+impl Visit for NumericFields<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.write(format_args!("{}={}", field.name(), value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.write(format_args!("{}={}", field.name(), value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.write(format_args!("{}={}", field.name(), value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.write(format_args!("{}={}", field.name(), value));
+    }
+
+    fn record_str(&mut self, _field: &Field, _value: &str) {
+        self.omitted = true;
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {
+        self.omitted = true;
+    }
+}
@@ -0,0 +1,104 @@
+// retry.rs
+//
+// The retry policy shared by the HTTP providers (STT, LLM and the TTS backends). A failed
+// attempt is retried when the failure is transient (see `AssistantError::is_retryable`):
+// the request could not be sent, or the service answered 408, 429 or 5xx. The wait between
+// attempts grows exponentially with random jitter, unless the service sent a Retry-After
+// header, which is honoured instead, up to a limit. All attempts together must finish before the
+// deadline, and cancelling the token stops an attempt or a wait at once.
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::AssistantError;
+
+/// How a provider's requests are retried, built from `RetryConfig`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first.
+    pub max_attempts: u32,
+    /// Backoff before the second attempt; it doubles for each further attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Time allowed for all attempts together.
+    pub deadline: Option<Duration>,
+    /// Longest Retry-After wait honoured; services asking for more wait this long.
+    pub max_retry_after: Duration,
+}
+
+impl RetryPolicy {
+    /// The wait after failed attempt number `attempt` (starting at 1): the exponential
+    /// backoff, randomly shortened by up to half so clients that failed together do not
+    /// retry together.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_backoff);
+        capped.mul_f64(rand::random_range(0.5..=1.0))
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error that is not worth retrying,
+    /// or the attempts, the deadline or the token run out.
+    ///
+    /// # Arguments
+    /// * `service` - Name of the service, used in logs and in the deadline error.
+    /// * `cancel` - Cancelling it ends the call with `AssistantError::Cancelled`.
+    /// * `attempt` - Sends the request once; receives the attempt number, starting at 1.
+    ///
+    /// # Returns
+    /// The first successful result, or the error of the last attempt.
+    pub async fn run<T, F, Fut>(&self, service: &str, cancel: &CancellationToken, mut attempt: F) -> Result<T, AssistantError>
+    where
+        F: FnMut(u32) -> Fut,
+        Fut: Future<Output = Result<T, AssistantError>>,
+    {
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+        let mut number = 1;
+
+        loop {
+            let result = tokio::select! {
+                _ = cancel.cancelled() => return Err(AssistantError::Cancelled),
+                result = self.before_deadline(service, deadline, attempt(number)) => result,
+            };
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if number >= self.max_attempts || !error.is_retryable() {
+                return Err(error);
+            }
+
+            // Give up early rather than sleep past the deadline
+            let delay = match error.retry_after() {
+                Some(retry_after) => retry_after.min(self.max_retry_after),
+                None => self.backoff(number),
+            };
+            let resume = Instant::now().checked_add(delay);
+            if deadline.is_some_and(|deadline| resume.is_none_or(|resume| resume >= deadline)) {
+                tracing::warn!("{} attempt {} failed and the deadline leaves no time to retry: {}", service, number, error);
+                return Err(error);
+            }
+            tracing::warn!("{} attempt {} failed, retrying in {}ms: {}", service, number, delay.as_millis(), error);
+
+            tokio::select! {
+                _ = cancel.cancelled() => return Err(AssistantError::Cancelled),
+                _ = tokio::time::sleep(delay) => {}
+            }
+            number += 1;
+        }
+    }
+
+    async fn before_deadline<T>(
+        &self,
+        service: &str,
+        deadline: Option<Instant>,
+        attempt: impl Future<Output = Result<T, AssistantError>>,
+    ) -> Result<T, AssistantError> {
+        let Some(deadline) = deadline else { return attempt.await };
+        match tokio::time::timeout_at(deadline, attempt).await {
+            Ok(result) => result,
+            Err(_) => Err(AssistantError::Timeout { service: service.to_string(), after: self.deadline.unwrap_or_default() }),
+        }
+    }
+}
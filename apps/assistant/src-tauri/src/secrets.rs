@@ -0,0 +1,352 @@
+// secrets.rs
+//
+// API keys and other credentials, kept encrypted (AES-256-GCM) in `secrets.json.enc` in the
+// app data directory. The key is either random and stored in `secrets.key` next to it,
+// readable by the user only, or derived with PBKDF2 from a passphrase; a passphrase store
+// stays locked until it is unlocked (`unlock_secrets`, or the passphrase variable).
+// Values never leave the backend: the commands only set, delete and list names.
+//
+// Lookups fall back to environment variables, so keys still in `.env` keep working, and
+// `import_env_file` moves the configured ones from `.env` into the store.
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{KeySource, SecretsConfig};
+use crate::error::AssistantError;
+
+/// File name of the encrypted secrets inside the app data directory.
+pub const SECRETS_FILE_NAME: &str = "secrets.json.enc";
+/// File name of the machine-local key inside the app data directory.
+pub const KEY_FILE_NAME: &str = "secrets.key";
+
+// Name of the store in errors
+const SECRETS_SERVICE: &str = "Secret store";
+// Binds the ciphertext to this file format
+const FILE_AAD: &[u8] = b"mivis-secrets-v1";
+const FILE_VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+// The file on disk; the secrets are a JSON object of name -> value inside `ciphertext`
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    key_source: KeySource,
+    /// PBKDF2 salt, for passphrase stores.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The encrypted secret store. All methods take `&self`, so it can be shared between commands.
+pub struct SecretStore {
+    /// None when the app data directory could not be resolved: only the environment is read.
+    dir: Option<PathBuf>,
+    key_source: KeySource,
+    state: Mutex<StoreState>,
+}
+
+struct StoreState {
+    /// None while a passphrase store is locked.
+    key: Option<StoreKey>,
+    secrets: BTreeMap<String, String>,
+}
+
+struct StoreKey {
+    key: LessSafeKey,
+    salt: Option<Vec<u8>>,
+}
+
+impl SecretStore {
+    /// Opens the store in `dir`, creating the key file on first use. A passphrase store is
+    /// unlocked right away when the passphrase variable is set.
+    pub fn open(dir: &Path, config: &SecretsConfig) -> Result<Self, AssistantError> {
+        fs::create_dir_all(dir).map_err(|e| AssistantError::io(format!("Failed to create {}", dir.display()), e))?;
+        let store = SecretStore {
+            dir: Some(dir.to_path_buf()),
+            key_source: config.key_source,
+            state: Mutex::new(StoreState { key: None, secrets: BTreeMap::new() }),
+        };
+
+        match config.key_source {
+            KeySource::KeyFile => {
+                let key = load_or_create_key_file(&dir.join(KEY_FILE_NAME))?;
+                store.load(StoreKey { key, salt: None })?;
+            }
+            KeySource::Passphrase => {
+                if let Ok(passphrase) = env::var(&config.passphrase_env) {
+                    store.unlock(&passphrase)?;
+                }
+            }
+        }
+        Ok(store)
+    }
+
+    /// A store without a file, used when the app data directory is unknown. Lookups read the
+    /// environment only and changes fail.
+    pub fn env_only() -> Self {
+        SecretStore {
+            dir: None,
+            key_source: KeySource::KeyFile,
+            state: Mutex::new(StoreState { key: None, secrets: BTreeMap::new() }),
+        }
+    }
+
+    /// Whether the store is waiting for its passphrase.
+    pub fn is_locked(&self) -> bool {
+        self.dir.is_some() && self.state.lock().unwrap().key.is_none()
+    }
+
+    /// Unlocks a passphrase store. The first unlock sets the passphrase.
+    ///
+    /// # Returns
+    /// `Auth` if the passphrase does not decrypt the existing file.
+    pub fn unlock(&self, passphrase: &str) -> Result<(), AssistantError> {
+        if self.key_source != KeySource::Passphrase {
+            return Err(AssistantError::InvalidInput("The secret store uses a key file, not a passphrase".to_string()));
+        }
+        if passphrase.is_empty() {
+            return Err(AssistantError::InvalidInput("The passphrase must not be empty".to_string()));
+        }
+
+        // An existing file keeps its salt, a new one gets a random salt
+        let salt = match self.read_file()? {
+            Some(file) => decode(file.salt.as_deref().unwrap_or_default(), "salt")?,
+            None => random_bytes(SALT_LEN)?,
+        };
+        let mut key = [0u8; KEY_LEN];
+        let iterations = NonZeroU32::new(PBKDF2_ITERATIONS).expect("iterations are not zero");
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, passphrase.as_bytes(), &mut key);
+        self.load(StoreKey { key: aead_key(&key)?, salt: Some(salt) })
+    }
+
+    /// The value of the secret `name`, or else of the environment variable `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let stored = self.state.lock().unwrap().secrets.get(name).cloned();
+        stored.or_else(|| env::var(name).ok())
+    }
+
+    /// Stores `value` under `name`, replacing any previous value.
+    pub fn set(&self, name: &str, value: &str) -> Result<(), AssistantError> {
+        validate_name(name)?;
+        if value.is_empty() {
+            return Err(AssistantError::InvalidInput(format!("The value of {} must not be empty", name)));
+        }
+        let mut state = self.unlocked_state()?;
+        state.secrets.insert(name.to_string(), value.to_string());
+        self.save(&state)
+    }
+
+    /// Deletes the secret `name`.
+    ///
+    /// # Returns
+    /// Whether the store had it.
+    pub fn delete(&self, name: &str) -> Result<bool, AssistantError> {
+        let mut state = self.unlocked_state()?;
+        if state.secrets.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.save(&state)?;
+        Ok(true)
+    }
+
+    /// The names of the stored secrets, sorted. Variables only found in the environment
+    /// are not listed.
+    pub fn names(&self) -> Result<Vec<String>, AssistantError> {
+        Ok(self.unlocked_state()?.secrets.keys().cloned().collect())
+    }
+
+    /// Copies the variables in `names` from a dotenv file into the store, unless the store
+    /// already has them. A missing file imports nothing.
+    ///
+    /// # Returns
+    /// The names that were imported.
+    pub fn import_env_file(&self, path: &Path, names: &[String]) -> Result<Vec<String>, AssistantError> {
+        // The iterator reads the file without setting the variables in the process environment
+        #[allow(deprecated)]
+        let Ok(entries) = dotenv::from_path_iter(path) else { return Ok(Vec::new()) };
+        let mut state = self.unlocked_state()?;
+
+        let mut imported = Vec::new();
+        for entry in entries {
+            let Ok((name, value)) = entry else { continue };
+            if names.contains(&name) && !value.is_empty() && !state.secrets.contains_key(&name) {
+                state.secrets.insert(name.clone(), value);
+                imported.push(name);
+            }
+        }
+        if !imported.is_empty() {
+            self.save(&state)?;
+        }
+        Ok(imported)
+    }
+
+    fn unlocked_state(&self) -> Result<std::sync::MutexGuard<'_, StoreState>, AssistantError> {
+        if self.dir.is_none() {
+            return Err(AssistantError::ServiceUnavailable {
+                service: SECRETS_SERVICE.to_string(),
+                reason: "the app data directory could not be resolved".to_string(),
+            });
+        }
+        let state = self.state.lock().unwrap();
+        if state.key.is_none() {
+            return Err(AssistantError::Auth { service: SECRETS_SERVICE.to_string(), reason: "the store is locked, enter the passphrase first".to_string() });
+        }
+        Ok(state)
+    }
+
+    fn file_path(&self) -> Option<PathBuf> {
+        self.dir.as_ref().map(|dir| dir.join(SECRETS_FILE_NAME))
+    }
+
+    fn read_file(&self) -> Result<Option<EncryptedFile>, AssistantError> {
+        let Some(path) = self.file_path() else { return Ok(None) };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(AssistantError::io(format!("Failed to read {}", path.display()), e)),
+        };
+        let file: EncryptedFile = serde_json::from_str(&contents)
+            .map_err(|e| AssistantError::Internal(format!("The secret store {} is damaged: {}", path.display(), e)))?;
+        if file.version != FILE_VERSION || file.key_source != self.key_source {
+            return Err(AssistantError::Internal(format!(
+                "The secret store {} was written with another version or key source ({:?})",
+                path.display(),
+                file.key_source
+            )));
+        }
+        Ok(Some(file))
+    }
+
+    // Decrypts the file, if there is one, with `key` and keeps the key for later saves
+    fn load(&self, key: StoreKey) -> Result<(), AssistantError> {
+        let secrets = match self.read_file()? {
+            Some(file) => {
+                let nonce: [u8; NONCE_LEN] = decode(&file.nonce, "nonce")?
+                    .try_into()
+                    .map_err(|_| AssistantError::Internal("The secret store has an invalid nonce".to_string()))?;
+                let mut data = decode(&file.ciphertext, "ciphertext")?;
+                let plaintext = key.key
+                    .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(FILE_AAD), &mut data)
+                    .map_err(|_| match self.key_source {
+                        KeySource::Passphrase => AssistantError::Auth {
+                            service: SECRETS_SERVICE.to_string(),
+                            reason: "wrong passphrase, or the secrets file was modified".to_string(),
+                        },
+                        KeySource::KeyFile => AssistantError::Internal(format!(
+                            "The secrets file cannot be decrypted with {}; it was modified or the key file was replaced",
+                            KEY_FILE_NAME
+                        )),
+                    })?;
+                serde_json::from_slice(plaintext)
+                    .map_err(|e| AssistantError::Internal(format!("The decrypted secrets are not valid JSON: {}", e)))?
+            }
+            None => BTreeMap::new(),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        *state = StoreState { key: Some(key), secrets };
+        Ok(())
+    }
+
+    // Encrypts the secrets with a fresh nonce and replaces the file atomically
+    fn save(&self, state: &StoreState) -> Result<(), AssistantError> {
+        let (Some(path), Some(key)) = (self.file_path(), state.key.as_ref()) else {
+            return Err(AssistantError::Internal("The secret store is not open".to_string()));
+        };
+
+        let nonce: [u8; NONCE_LEN] = random_bytes(NONCE_LEN)?.try_into().expect("nonce has NONCE_LEN bytes");
+        let mut data = serde_json::to_vec(&state.secrets).map_err(|e| AssistantError::Internal(e.to_string()))?;
+        key.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(FILE_AAD), &mut data)
+            .map_err(|_| AssistantError::Internal("Failed to encrypt the secrets".to_string()))?;
+        let file = EncryptedFile {
+            version: FILE_VERSION,
+            key_source: self.key_source,
+            salt: key.salt.as_ref().map(|salt| BASE64.encode(salt)),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(&data),
+        };
+        let contents = serde_json::to_vec_pretty(&file).map_err(|e| AssistantError::Internal(e.to_string()))?;
+
+        let temp_path = path.with_extension("tmp");
+        write_private(&temp_path, &contents)?;
+        fs::rename(&temp_path, &path).map_err(|e| AssistantError::io(format!("Failed to replace {}", path.display()), e))
+    }
+}
+
+// Secret names are environment variable names, e.g. XAI_API_KEY
+fn validate_name(name: &str) -> Result<(), AssistantError> {
+    let valid = !name.is_empty()
+        && name.len() <= 128
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if !valid {
+        return Err(AssistantError::InvalidInput(format!(
+            "Invalid secret name '{}': use letters, digits and underscores, e.g. XAI_API_KEY",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn load_or_create_key_file(path: &Path) -> Result<LessSafeKey, AssistantError> {
+    match fs::read(path) {
+        Ok(key) if key.len() == KEY_LEN => aead_key(&key),
+        Ok(_) => Err(AssistantError::Internal(format!("The key file {} does not hold a {}-byte key", path.display(), KEY_LEN))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = random_bytes(KEY_LEN)?;
+            write_private(path, &key)?;
+            tracing::info!("Created the secret store key {}", path.display());
+            aead_key(&key)
+        }
+        Err(e) => Err(AssistantError::io(format!("Failed to read {}", path.display()), e)),
+    }
+}
+
+fn aead_key(bytes: &[u8]) -> Result<LessSafeKey, AssistantError> {
+    UnboundKey::new(&AES_256_GCM, bytes)
+        .map(LessSafeKey::new)
+        .map_err(|_| AssistantError::Internal("Invalid secret store key".to_string()))
+}
+
+fn random_bytes(len: usize) -> Result<Vec<u8>, AssistantError> {
+    let mut bytes = vec![0u8; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| AssistantError::Internal("The system random generator failed".to_string()))?;
+    Ok(bytes)
+}
+
+fn decode(value: &str, field: &str) -> Result<Vec<u8>, AssistantError> {
+    BASE64.decode(value).map_err(|e| AssistantError::Internal(format!("The secret store has an invalid {}: {}", field, e)))
+}
+
+// Writes a file only the current user can read (on Unix; Windows keeps the app data ACLs)
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), AssistantError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| AssistantError::io(format!("Failed to create {}", path.display()), e))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| AssistantError::io(format!("Failed to write {}", path.display()), e))
+}
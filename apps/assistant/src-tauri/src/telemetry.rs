@@ -0,0 +1,245 @@
+// telemetry.rs
+//
+// Sets up `tracing` for the app: a levelled log on stderr (stdout is left to CLI output) and in daily files in the app log
+// directory, plus `WorkflowLayer`, which turns spans opened inside a workflow stage into
+// stage records for the workflow log.
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes as SpanAttributes, Id, Record};
+use tracing::{Dispatch, Event, Level, Subscriber};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+use crate::config::LoggingConfig;
+use crate::redaction::{RedactingFields, RedactingMakeWriter, Redactor};
+use crate::workflow_logger::{self, Attributes, StageFailure, WorkflowLogRecord, WorkflowLogSink, WORKFLOW_TRACING_TARGET};
+
+/// File name prefix of the app log; files are named `mivis.<date>.log`.
+pub const APP_LOG_FILE_PREFIX: &str = "mivis";
+
+/// Keeps the background writer of the app log alive. Dropping the guard flushes the
+/// remaining lines, so it is taken and dropped when the app exits.
+pub struct AppLogGuard(pub Mutex<Option<WorkerGuard>>);
+
+/// Builds the `tracing` subscriber of the app. It is not installed here: the app and the
+/// CLI make it the global subscriber (see `Services::install_tracing`), while tests use
+/// the one of each app on their own thread, so apps never share logging state.
+///
+/// # Arguments
+/// * `log_dir` - The directory of the rolling app log files.
+/// * `config` - The level filter and retention of the app log.
+/// * `workflow_sink` - Where `WorkflowLayer` writes stage records.
+/// * `redactor` - Masks personal data in every formatted line before it is written; in
+///   no-content mode the lines keep only their numeric fields.
+///
+/// # Returns
+/// The subscriber and the guard of the app log writer, or an error message if logging
+/// could not be set up.
+pub fn build_subscriber(log_dir: &Path, config: &LoggingConfig, workflow_sink: Arc<dyn WorkflowLogSink>, redactor: Arc<Redactor>) -> Result<(Dispatch, AppLogGuard), String> {
+    let file_appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(APP_LOG_FILE_PREFIX)
+        .filename_suffix("log")
+        .max_log_files(config.max_log_files.max(1))
+        .build(log_dir)
+        .map_err(|e| format!("Failed to open app log in {}: {}", log_dir.display(), e))?;
+    let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+
+    // The level only filters what is printed and written to the app log; the workflow
+    // layer sees every span so stage records do not depend on the log level
+    let log_layers = fmt::layer()
+        .fmt_fields(RedactingFields::new(redactor.clone()))
+        .with_writer(RedactingMakeWriter::new(std::io::stderr, redactor.clone()))
+        .and_then(fmt::layer()
+            .fmt_fields(RedactingFields::new(redactor.clone()))
+            .with_writer(RedactingMakeWriter::new(file_writer, redactor))
+            .with_ansi(false))
+        .with_filter(level_filter(&config.level));
+
+    let subscriber = tracing_subscriber::registry()
+        .with(log_layers)
+        .with(WorkflowLayer::new(workflow_sink));
+
+    Ok((Dispatch::new(subscriber), AppLogGuard(Mutex::new(Some(guard)))))
+}
+
+// Parses the configured level, falling back to "info" if it is not a valid filter
+fn level_filter(level: &str) -> EnvFilter {
+    EnvFilter::try_new(level).unwrap_or_else(|e| {
+        eprintln!("[WARN] Invalid log level '{}' ({}), using info", level, e);
+        EnvFilter::new("info")
+    })
+}
+
+/// Records spans opened inside a workflow stage as child stages of that workflow.
+///
+/// Stage guards from `WorkflowTimings` open spans with the target `WORKFLOW_TRACING_TARGET`
+/// and record themselves; the layer only remembers their workflow. Any other span whose
+/// ancestor belongs to a workflow is written as a stage record when it closes:
+/// * the stage name is the span's `stage` field, or the span name if it has none,
+/// * an `error` field or an ERROR event inside the span marks the stage as failed,
+/// * the remaining fields become attributes.
+pub struct WorkflowLayer {
+    sink: Arc<dyn WorkflowLogSink>,
+}
+
+impl WorkflowLayer {
+    pub fn new(sink: Arc<dyn WorkflowLogSink>) -> Self {
+        WorkflowLayer { sink }
+    }
+}
+
+// Stored in the extensions of every span that belongs to a workflow
+#[derive(Clone)]
+struct WorkflowSpan {
+    workflow_id: String,
+    span_id: u64,
+}
+
+// Stored in the extensions of the spans recorded by the layer
+struct PendingStage {
+    name: String,
+    parent_span_id: u64,
+    started: Instant,
+    error: Option<String>,
+    attributes: Attributes,
+}
+
+impl<S> Layer<S> for WorkflowLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &SpanAttributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut fields = FieldCollector::default();
+        attrs.record(&mut fields);
+
+        // Spans of stage guards carry their workflow id and span id
+        if attrs.metadata().target() == WORKFLOW_TRACING_TARGET {
+            let workflow_id = fields.attributes.get("workflow_id").and_then(|v| v.as_str()).map(str::to_string);
+            let span_id = fields.attributes.get("span_id").and_then(|v| v.as_u64());
+            if let (Some(workflow_id), Some(span_id)) = (workflow_id, span_id) {
+                span.extensions_mut().insert(WorkflowSpan { workflow_id, span_id });
+            }
+            return;
+        }
+
+        // Other spans are only recorded when they run inside a workflow
+        let Some(parent) = span.scope().skip(1).find_map(|ancestor| ancestor.extensions().get::<WorkflowSpan>().cloned()) else {
+            return;
+        };
+        let name = fields.stage.take().unwrap_or_else(|| attrs.metadata().name().to_string());
+        let mut extensions = span.extensions_mut();
+        extensions.insert(WorkflowSpan { workflow_id: parent.workflow_id, span_id: workflow_logger::next_span_id() });
+        extensions.insert(PendingStage {
+            name,
+            parent_span_id: parent.span_id,
+            started: Instant::now(),
+            error: fields.error,
+            attributes: fields.attributes,
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(pending) = extensions.get_mut::<PendingStage>() else { return };
+
+        let mut fields = FieldCollector::default();
+        values.record(&mut fields);
+        if let Some(stage) = fields.stage {
+            pending.name = stage;
+        }
+        if fields.error.is_some() {
+            pending.error = fields.error;
+        }
+        pending.attributes.extend(fields.attributes);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let Some(span) = ctx.event_span(event) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(pending) = extensions.get_mut::<PendingStage>() else { return };
+
+        if pending.error.is_none() {
+            let mut fields = FieldCollector::default();
+            event.record(&mut fields);
+            pending.error = Some(fields.message.unwrap_or_else(|| "error event".to_string()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let mut extensions = span.extensions_mut();
+        let Some(pending) = extensions.remove::<PendingStage>() else { return };
+        let Some(workflow_span) = extensions.remove::<WorkflowSpan>() else { return };
+
+        let record = WorkflowLogRecord::stage(
+            &workflow_span.workflow_id,
+            &pending.name,
+            workflow_span.span_id,
+            Some(pending.parent_span_id),
+            pending.started.elapsed(),
+            pending.error.map(|message| StageFailure { message, kind: None }),
+            pending.attributes,
+        );
+        self.sink.write_record(&record);
+    }
+}
+
+// Splits span and event fields into the stage name, the error, the message and attributes
+#[derive(Default)]
+struct FieldCollector {
+    stage: Option<String>,
+    error: Option<String>,
+    message: Option<String>,
+    attributes: Attributes,
+}
+
+impl FieldCollector {
+    fn insert(&mut self, field: &Field, value: serde_json::Value) {
+        match field.name() {
+            "stage" => self.stage = Some(value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string())),
+            "error" => self.error = Some(value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string())),
+            "message" => self.message = value.as_str().map(str::to_string),
+            name => {
+                self.attributes.insert(name.to_string(), value);
+            }
+        }
+    }
+}
+
+impl Visit for FieldCollector {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.insert(field, format!("{:?}", value).into());
+    }
+}
@@ -0,0 +1,104 @@
+// Ordered fallback across TTS backends
+use std::time::Duration;
+use tracing::Instrument;
+
+use super::{SynthesisRequest, TtsBackend};
+use crate::circuit_breaker::ServiceHealth;
+use crate::error::AssistantError;
+use crate::events::EventSink;
+
+/// Audio produced by the chain, together with the backend that produced it.
+pub struct Synthesis {
+    pub audio_data: Vec<u8>,
+    pub backend: String,
+    /// True when the audio came from the first backend in the chain.
+    pub from_primary: bool,
+}
+
+/// Tries each backend in order and returns the first successful result.
+/// A backend that errors or exceeds the timeout is skipped in favour of the next one,
+/// and so is a backend whose circuit breaker is open, without being asked.
+/// If every backend fails, a single backend's error is returned as is and several
+/// failures are summed up as `ServiceUnavailable`.
+pub struct TtsFallbackChain {
+    backends: Vec<Box<dyn TtsBackend>>,
+    timeout: Duration,
+}
+
+impl TtsFallbackChain {
+    pub fn new(backends: Vec<Box<dyn TtsBackend>>, timeout: Duration) -> Self {
+        TtsFallbackChain { backends, timeout }
+    }
+
+    pub fn backend_names(&self) -> Vec<String> {
+        self.backends.iter().map(|b| b.name().to_string()).collect()
+    }
+
+    /// Health of the backends that have a circuit breaker, in fallback order.
+    pub fn health(&self) -> Vec<ServiceHealth> {
+        self.backends.iter().filter_map(|b| b.breaker()).map(|breaker| breaker.health()).collect()
+    }
+
+    /// Synthesizes `request` with the first backend that succeeds. `events` receives the
+    /// health changes of the backends' services.
+    pub async fn synthesize(&self, request: &SynthesisRequest, events: &dyn EventSink) -> Result<Synthesis, AssistantError> {
+        if self.backends.is_empty() {
+            return Err(AssistantError::ServiceUnavailable { service: "TTS".to_string(), reason: "No TTS backends are configured".to_string() });
+        }
+
+        let mut failures = Vec::new();
+        for (index, backend) in self.backends.iter().enumerate() {
+            // Do not wait on a service that is known to be down
+            let permit = match backend.breaker().map(|breaker| breaker.acquire(events)).transpose() {
+                Ok(permit) => permit,
+                Err(e) => {
+                    tracing::info!("Skipping TTS backend '{}': {}", backend.name(), e);
+                    failures.push(e);
+                    continue;
+                }
+            };
+
+            // Each attempt is a span, recorded as a "tts:backend" stage inside a workflow
+            let span = tracing::info_span!("tts_backend", stage = "tts:backend", backend = backend.name(), error = tracing::field::Empty);
+            let result = match tokio::time::timeout(self.timeout, backend.synthesize(request)).instrument(span.clone()).await {
+                Ok(result) => result,
+                Err(_) => Err(AssistantError::Timeout { service: backend.name().to_string(), after: self.timeout }),
+            };
+            if let Err(e) = &result {
+                span.record("error", e.to_string().as_str());
+            }
+            if let Some(permit) = permit {
+                permit.record(&result, events);
+            }
+
+            match result {
+                Ok(audio_data) => {
+                    if index > 0 {
+                        tracing::info!("TTS fell back to backend '{}' after: {}", backend.name(), join_errors(&failures));
+                    }
+                    return Ok(Synthesis {
+                        audio_data,
+                        backend: backend.name().to_string(),
+                        from_primary: index == 0,
+                    });
+                }
+                // A cancelled request should not move on to the next backend
+                Err(AssistantError::Cancelled) => return Err(AssistantError::Cancelled),
+                Err(e) => {
+                    tracing::warn!("TTS backend '{}' failed: {}", backend.name(), e);
+                    failures.push(e);
+                }
+            }
+        }
+
+        if failures.len() == 1 {
+            return Err(failures.remove(0));
+        }
+        Err(AssistantError::ServiceUnavailable { service: "TTS".to_string(), reason: format!("All TTS backends failed: {}", join_errors(&failures)) })
+    }
+}
+
+// The errors already name their backend
+fn join_errors(errors: &[AssistantError]) -> String {
+    errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
+}
@@ -0,0 +1,117 @@
+// Backend running a local command-line engine (espeak-ng, piper, ...)
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::{SynthesisRequest, TtsBackend};
+use crate::error::AssistantError;
+
+/// Runs a TTS program that writes a WAV file.
+///
+/// Example configurations:
+/// * espeak-ng: `program = "espeak-ng"`, `args = ["-v", "{voice}", "-w", "{output}", "--", "{text}"]`
+/// * piper: `program = "piper"`, `args = ["--model", "{voice}", "--length_scale", "{speed}", "--output_file", "{output}"]`
+///   with `text_via_stdin = true`
+///
+/// Text passed as an argument must follow a `--` argument if it starts with '-', so the
+/// program cannot take it for one of its options.
+pub struct CommandBackend {
+    name: String,
+    program: String,
+    args: Vec<String>,
+    default_voice: String,
+    text_via_stdin: bool,
+}
+
+impl CommandBackend {
+    pub fn new(program: &str, args: Vec<String>, default_voice: &str, text_via_stdin: bool) -> Self {
+        CommandBackend {
+            name: format!("command:{}", program),
+            program: program.to_string(),
+            args,
+            default_voice: default_voice.to_string(),
+            text_via_stdin,
+        }
+    }
+
+    // Whether a `--` argument comes before the text
+    fn ends_options_before_text(&self) -> bool {
+        self.args.iter()
+            .take_while(|arg| !arg.contains("{text}"))
+            .any(|arg| arg == "--")
+    }
+}
+
+#[async_trait]
+impl TtsBackend for CommandBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn synthesize(&self, request: &SynthesisRequest) -> Result<Vec<u8>, AssistantError> {
+        let output_file = OutputFile(std::env::temp_dir().join(format!("tts_{}.wav", uuid::Uuid::new_v4())));
+        let output_str = output_file.0.to_string_lossy().to_string();
+        let voice = request.voice.as_deref().unwrap_or(&self.default_voice);
+        if !self.text_via_stdin && request.text.starts_with('-') && !self.ends_options_before_text() {
+            return Err(AssistantError::InvalidInput(format!(
+                "{} cannot read text starting with '-' as an argument: add \"--\" before \"{{text}}\" or set text_via_stdin", self.name
+            )));
+        }
+
+        let args: Vec<String> = self.args.iter()
+            .map(|arg| {
+                arg.replace("{output}", &output_str)
+                    .replace("{voice}", voice)
+                    .replace("{speed}", &request.speed.to_string())
+                    .replace("{text}", &request.text)
+            })
+            .collect();
+
+        let mut child = Command::new(&self.program)
+            .args(&args)
+            .stdin(if self.text_via_stdin { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true) // The fallback chain may drop us on timeout
+            .spawn()
+            .map_err(|e| AssistantError::ServiceUnavailable { service: self.name.clone(), reason: format!("Failed to start {}: {}", self.program, e) })?;
+
+        // Dropping the child on cancellation kills the program
+        let run = async {
+            if self.text_via_stdin {
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(request.text.as_bytes()).await
+                        .map_err(|e| AssistantError::io(format!("Failed to write text to {}", self.program), e))?;
+                }
+            }
+            child.wait_with_output().await
+                .map_err(|e| AssistantError::io(format!("Failed to wait for {}", self.program), e))
+        };
+        let output = tokio::select! {
+            _ = request.cancel.cancelled() => return Err(AssistantError::Cancelled),
+            output = run => output?,
+        };
+
+        if output.status.success() {
+            tokio::fs::read(&output_file.0).await
+                .map_err(|e| AssistantError::io(format!("{} did not produce audio at {}", self.program, output_str), e))
+        } else {
+            Err(AssistantError::ServiceUnavailable {
+                service: self.name.clone(),
+                reason: format!("{} exited with {}: {}", self.program, output.status, String::from_utf8_lossy(&output.stderr).trim()),
+            })
+        }
+    }
+}
+
+// The file the program writes, deleted however the synthesis ends, including when the
+// fallback chain drops it on timeout
+struct OutputFile(PathBuf);
+
+impl Drop for OutputFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
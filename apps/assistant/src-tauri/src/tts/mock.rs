@@ -0,0 +1,27 @@
+// Silent backend used as the last resort and for testing
+use async_trait::async_trait;
+
+use super::{SynthesisRequest, TtsBackend};
+use crate::audio_format;
+use crate::error::AssistantError;
+
+const MOCK_SAMPLE_RATE: u32 = 16000;
+// Roughly how long it takes to speak one character, so the silence has a plausible length
+const MS_PER_CHARACTER: u64 = 60;
+const MAX_DURATION_MS: u64 = 30_000;
+
+pub struct MockBackend;
+
+#[async_trait]
+impl TtsBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn synthesize(&self, request: &SynthesisRequest) -> Result<Vec<u8>, AssistantError> {
+        let duration_ms = (request.text.chars().count() as u64 * MS_PER_CHARACTER).min(MAX_DURATION_MS);
+        let speed = if request.speed > 0.0 { request.speed as f64 } else { 1.0 };
+        let sample_count = (MOCK_SAMPLE_RATE as f64 * duration_ms as f64 / 1000.0 / speed) as usize;
+        Ok(audio_format::encode_wav(&vec![0u8; sample_count * 2], MOCK_SAMPLE_RATE))
+    }
+}
@@ -0,0 +1,80 @@
+// Text-to-speech backends for Mivis Desktop Assistant
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::audio_format::AudioFormat;
+use crate::circuit_breaker::CircuitBreaker;
+use crate::config::{TtsBackendConfig, TtsConfig};
+use crate::error::AssistantError;
+use crate::http::ServiceClient;
+use crate::secrets::SecretStore;
+
+mod chain;
+mod command;
+mod mock;
+mod openai;
+mod viettts;
+
+pub use chain::{Synthesis, TtsFallbackChain};
+pub use command::CommandBackend;
+pub use mock::MockBackend;
+pub use openai::{ApiKey, OpenAiSpeechBackend};
+pub use viettts::VietTtsBackend;
+
+/// The settings of one synthesis call, shared by all backends.
+#[derive(Debug, Clone)]
+pub struct SynthesisRequest {
+    pub text: String,
+    pub voice: Option<String>, // None selects the backend's default voice
+    pub speed: f32,
+    pub format: AudioFormat,
+    /// Cancelling it stops the backends' retries.
+    pub cancel: CancellationToken,
+}
+
+/// A speech synthesis provider. Backends return audio in whatever format they can
+/// produce; `synthesize_speech` checks and converts it afterwards.
+#[async_trait]
+pub trait TtsBackend: Send + Sync {
+    /// Short name used in logs and error messages.
+    fn name(&self) -> &str;
+
+    /// The circuit breaker of the service behind the backend; local backends have none.
+    fn breaker(&self) -> Option<&CircuitBreaker> {
+        None
+    }
+
+    async fn synthesize(&self, request: &SynthesisRequest) -> Result<Vec<u8>, AssistantError>;
+}
+
+/// Builds the fallback chain described by the TTS configuration; the HTTP backends
+/// send their requests with `client`, each with a circuit breaker of its own, and read
+/// their API keys from `secrets` on every request.
+pub fn build_chain(config: &TtsConfig, client: &ServiceClient, secrets: &Arc<SecretStore>) -> TtsFallbackChain {
+    let backends = config.backends.iter()
+        .map(|backend| -> Box<dyn TtsBackend> {
+            match backend {
+                TtsBackendConfig::Viettts { url, default_voice } => {
+                    Box::new(VietTtsBackend::new(url, default_voice, config.retry.policy(), client))
+                }
+                TtsBackendConfig::OpenaiCompatible { url, model, default_voice, api_key_env } => {
+                    let api_key = match api_key_env {
+                        Some(name) => ApiKey::Secret { name: name.clone(), secrets: secrets.clone() },
+                        None => ApiKey::None,
+                    };
+                    // Named after the server, so each one has a circuit of its own
+                    let name = format!("openai-compatible:{}", url);
+                    Box::new(OpenAiSpeechBackend::new(&name, url, model, default_voice, api_key, config.retry.policy(), client.for_upstream(&name)))
+                }
+                TtsBackendConfig::Command { program, args, default_voice, text_via_stdin } => {
+                    Box::new(CommandBackend::new(program, args.clone(), default_voice, *text_via_stdin))
+                }
+                TtsBackendConfig::Mock => Box::new(MockBackend),
+            }
+        })
+        .collect();
+
+    TtsFallbackChain::new(backends, Duration::from_secs(config.timeout_secs))
+}
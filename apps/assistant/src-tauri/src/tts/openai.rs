@@ -0,0 +1,117 @@
+// Backend for servers implementing OpenAI's /v1/audio/speech
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+
+use super::{SynthesisRequest, TtsBackend};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::error::AssistantError;
+use crate::http::ServiceClient;
+use crate::retry::RetryPolicy;
+use crate::secrets::SecretStore;
+
+/// Where the API key of a request comes from.
+pub enum ApiKey {
+    /// The server needs no key.
+    None,
+    /// A fixed token, such as VietTTS' one.
+    Fixed(String),
+    /// A secret looked up for every request, so keys stored or unlocked after startup apply.
+    Secret { name: String, secrets: Arc<SecretStore> },
+}
+
+pub struct OpenAiSpeechBackend {
+    name: String,
+    url: String,
+    model: String,
+    default_voice: String,
+    api_key: ApiKey,
+    retry: RetryPolicy,
+    client: ServiceClient,
+}
+
+impl OpenAiSpeechBackend {
+    pub fn new(name: &str, url: &str, model: &str, default_voice: &str, api_key: ApiKey, retry: RetryPolicy, client: ServiceClient) -> Self {
+        OpenAiSpeechBackend {
+            name: name.to_string(),
+            url: url.to_string(),
+            model: model.to_string(),
+            default_voice: default_voice.to_string(),
+            api_key,
+            retry,
+            client,
+        }
+    }
+
+    async fn send(&self, payload: &serde_json::Value) -> Result<reqwest::Response, AssistantError> {
+        let mut request = self.client.post(&self.url)
+            .header("Content-Type", "application/json")
+            .json(payload);
+        let api_key = match &self.api_key {
+            ApiKey::None => None,
+            ApiKey::Fixed(token) => Some(token.clone()),
+            ApiKey::Secret { name, secrets } => Some(secrets.get(name).ok_or_else(|| AssistantError::Auth {
+                service: self.name.clone(),
+                reason: format!("Missing API key {}: add it in the settings or to .env", name),
+            })?),
+        };
+        if let Some(api_key) = api_key {
+            request = request.header("Authorization", format!("Bearer {}", api_key));
+        }
+        request.send().await
+            .map_err(|e| self.client.send_error(&self.name, &e))
+    }
+
+    // One attempt of `synthesize`
+    async fn synthesize_once(&self, request: &SynthesisRequest) -> Result<Vec<u8>, AssistantError> {
+        let mut payload = serde_json::json!({
+            "model": self.model,
+            "input": request.text,
+            "voice": request.voice.as_deref().unwrap_or(&self.default_voice),
+            "speed": request.speed,
+            "response_format": request.format.as_str()
+        });
+
+        let mut response = self.send(&payload).await?;
+
+        // A service that rejects the requested format gets asked for its default output instead,
+        // which synthesize_speech converts afterwards. Other invalid requests fail as they are.
+        if matches!(response.status(), StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY) {
+            let status = response.status().as_u16();
+            let text = response.text().await.unwrap_or_else(|_| "No response body".to_string());
+            if !text.contains("response_format") {
+                return Err(AssistantError::from_status(&self.name, status, text, None));
+            }
+            tracing::info!("{} rejected response_format '{}' ({}), retrying with the service default", self.name, request.format.as_str(), text);
+            if let Some(fields) = payload.as_object_mut() {
+                fields.remove("response_format");
+            }
+            response = self.send(&payload).await?;
+        }
+
+        // Check if the request was successful
+        if !response.status().is_success() {
+            return Err(AssistantError::from_response(&self.name, response).await);
+        }
+
+        response.bytes().await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| AssistantError::invalid_response(&self.name, format!("Failed to retrieve audio data: {}", e)))
+    }
+}
+
+#[async_trait]
+impl TtsBackend for OpenAiSpeechBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn breaker(&self) -> Option<&CircuitBreaker> {
+        Some(self.client.breaker())
+    }
+
+    async fn synthesize(&self, request: &SynthesisRequest) -> Result<Vec<u8>, AssistantError> {
+        self.retry.run(&self.name, &request.cancel, |_| self.synthesize_once(request)).await
+    }
+}
@@ -0,0 +1,49 @@
+// Backend for the VietTTS Docker service
+use async_trait::async_trait;
+
+use super::{ApiKey, OpenAiSpeechBackend, SynthesisRequest, TtsBackend};
+use crate::circuit_breaker::CircuitBreaker;
+use crate::error::AssistantError;
+use crate::http::ServiceClient;
+use crate::retry::RetryPolicy;
+
+// VietTTS accepts this fixed token (see packages/tts)
+const VIETTTS_API_TOKEN: &str = "viet-tts";
+const VIETTTS_MODEL: &str = "tts-1";
+const VIETTTS_SERVICE: &str = "VietTTS service";
+
+/// VietTTS speaks the OpenAI speech API, so this is a preconfigured `OpenAiSpeechBackend`.
+pub struct VietTtsBackend {
+    inner: OpenAiSpeechBackend,
+}
+
+impl VietTtsBackend {
+    pub fn new(url: &str, default_voice: &str, retry: RetryPolicy, client: &ServiceClient) -> Self {
+        VietTtsBackend {
+            inner: OpenAiSpeechBackend::new(
+                VIETTTS_SERVICE,
+                url,
+                VIETTTS_MODEL,
+                default_voice,
+                ApiKey::Fixed(VIETTTS_API_TOKEN.to_string()),
+                retry,
+                client.for_upstream(VIETTTS_SERVICE),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl TtsBackend for VietTtsBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn breaker(&self) -> Option<&CircuitBreaker> {
+        self.inner.breaker()
+    }
+
+    async fn synthesize(&self, request: &SynthesisRequest) -> Result<Vec<u8>, AssistantError> {
+        self.inner.synthesize(request).await
+    }
+}
@@ -0,0 +1,263 @@
+// tts_cache.rs
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default upper bound for the on-disk size of the cache (100 MiB).
+pub const DEFAULT_MAX_CACHE_BYTES: u64 = 100 * 1024 * 1024;
+
+const INDEX_FILE_NAME: &str = "index.json";
+const AUDIO_FILE_EXTENSION: &str = "audio";
+/// Hits only change the recency of entries, so the index is saved after this many of
+/// them (or when the cache is dropped) rather than on every hit.
+const HITS_PER_INDEX_SAVE: u32 = 32;
+
+/// The inputs that determine the synthesized audio. Two requests with the same key
+/// are expected to produce the same audio, so the output can be reused.
+pub struct TtsCacheKey<'a> {
+    pub text: &'a str,
+    pub voice: &'a str,
+    pub speed: f32,
+    pub format: &'a str,
+    pub sample_rate: Option<u32>,
+}
+
+impl TtsCacheKey<'_> {
+    /// Computes the content address (hex encoded SHA-256) for this key.
+    /// The text is normalized first so that case and whitespace differences hit the same entry.
+    pub fn digest(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(normalize_text(self.text).as_bytes());
+        hasher.update([0u8]);
+        hasher.update(self.voice.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(format!("{:.2}", self.speed).as_bytes());
+        hasher.update([0u8]);
+        hasher.update(self.format.to_ascii_lowercase().as_bytes());
+        hasher.update([0u8]);
+        hasher.update(self.sample_rate.map(|rate| rate.to_string()).unwrap_or_default().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Lowercases and trims the text and collapses every run of whitespace into a single space.
+fn normalize_text(text: &str) -> String {
+    text.to_lowercase().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CacheEntry {
+    size_bytes: u64,
+    last_access: u64, // Logical clock, higher means more recently used
+}
+
+/// Hit/miss statistics reported to the frontend.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct TtsCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_rate: f64,
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub max_bytes: u64,
+}
+
+/// A content-addressed, size-bounded on-disk cache for synthesized speech.
+/// Least recently used entries are evicted once the total size exceeds `max_bytes`.
+pub struct TtsCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    entries: HashMap<String, CacheEntry>,
+    total_bytes: u64,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    unsaved_hits: u32, // Hits since the index was last saved
+}
+
+impl TtsCache {
+    /// Opens (or creates) the cache stored in `dir`.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory holding the cached audio files and the index.
+    /// * `max_bytes` - The maximum total size of the cached audio.
+    ///
+    /// # Returns
+    /// The opened cache, or an error message if the directory could not be prepared.
+    pub fn open(dir: &Path, max_bytes: u64) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create TTS cache directory {}: {}", dir.display(), e))?;
+
+        let mut entries: HashMap<String, CacheEntry> = match fs::read(dir.join(INDEX_FILE_NAME)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("TTS cache index is corrupt, starting empty: {}", e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        // Drop index entries whose audio file has gone missing
+        entries.retain(|key, _| audio_path(dir, key).is_file());
+
+        // Remove audio files that are not referenced by the index
+        if let Ok(read_dir) = fs::read_dir(dir) {
+            for dir_entry in read_dir.flatten() {
+                let path = dir_entry.path();
+                let is_audio = path.extension().is_some_and(|ext| ext == AUDIO_FILE_EXTENSION);
+                let key = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+                if is_audio && !key.is_some_and(|key| entries.contains_key(&key)) {
+                    let _ = fs::remove_file(&path);
+                }
+            }
+        }
+
+        let total_bytes = entries.values().map(|e| e.size_bytes).sum();
+        let clock = entries.values().map(|e| e.last_access).max().unwrap_or(0);
+
+        let mut cache = TtsCache {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            entries,
+            total_bytes,
+            clock,
+            hits: 0,
+            misses: 0,
+            unsaved_hits: 0,
+        };
+        cache.evict_to_fit();
+        Ok(cache)
+    }
+
+    /// Looks up the audio cached under `digest` (see `TtsCacheKey::digest`), updating its
+    /// recency and the hit/miss counters.
+    pub fn get(&mut self, digest: &str) -> Option<Vec<u8>> {
+        if !self.entries.contains_key(digest) {
+            self.misses += 1;
+            return None;
+        }
+
+        match fs::read(audio_path(&self.dir, digest)) {
+            Ok(audio_data) => {
+                self.clock += 1;
+                if let Some(entry) = self.entries.get_mut(digest) {
+                    entry.last_access = self.clock;
+                }
+                self.hits += 1;
+                self.unsaved_hits += 1;
+                if self.unsaved_hits >= HITS_PER_INDEX_SAVE {
+                    self.save_index();
+                }
+                Some(audio_data)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to read cached TTS audio {}: {}", digest, e);
+                self.remove_entry(digest);
+                self.save_index();
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores synthesized audio under `digest`, evicting least recently used entries if
+    /// needed. Audio larger than the whole cache is not stored.
+    pub fn put(&mut self, digest: &str, audio_data: &[u8]) -> Result<(), String> {
+        let size_bytes = audio_data.len() as u64;
+        if size_bytes > self.max_bytes {
+            return Ok(());
+        }
+
+        fs::write(audio_path(&self.dir, digest), audio_data)
+            .map_err(|e| format!("Failed to write TTS cache entry {}: {}", digest, e))?;
+
+        if let Some(previous) = self.entries.remove(digest) {
+            self.total_bytes -= previous.size_bytes;
+        }
+        self.clock += 1;
+        self.entries.insert(digest.to_string(), CacheEntry { size_bytes, last_access: self.clock });
+        self.total_bytes += size_bytes;
+
+        self.evict_to_fit();
+        self.save_index();
+        Ok(())
+    }
+
+    /// Removes every cached entry and resets the statistics.
+    pub fn clear(&mut self) -> Result<(), String> {
+        let keys: Vec<String> = self.entries.keys().cloned().collect();
+        for key in keys {
+            self.remove_entry(&key);
+        }
+        self.total_bytes = 0;
+        self.clock = 0;
+        self.hits = 0;
+        self.misses = 0;
+        self.unsaved_hits = 0;
+
+        fs::write(self.dir.join(INDEX_FILE_NAME), b"{}")
+            .map_err(|e| format!("Failed to reset TTS cache index: {}", e))
+    }
+
+    /// Returns the current hit/miss statistics and cache occupancy.
+    pub fn stats(&self) -> TtsCacheStats {
+        let lookups = self.hits + self.misses;
+        TtsCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            hit_rate: if lookups > 0 { self.hits as f64 / lookups as f64 } else { 0.0 },
+            entries: self.entries.len(),
+            total_bytes: self.total_bytes,
+            max_bytes: self.max_bytes,
+        }
+    }
+
+    fn evict_to_fit(&mut self) {
+        while self.total_bytes > self.max_bytes {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.remove_entry(&key),
+                None => break,
+            }
+        }
+    }
+
+    fn remove_entry(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.size_bytes;
+        }
+        let path = audio_path(&self.dir, key);
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                tracing::warn!("Failed to remove TTS cache file {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    fn save_index(&mut self) {
+        self.unsaved_hits = 0;
+        match serde_json::to_vec(&self.entries) {
+            Ok(bytes) => {
+                if let Err(e) = fs::write(self.dir.join(INDEX_FILE_NAME), bytes) {
+                    tracing::warn!("Failed to save TTS cache index: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to serialize TTS cache index: {}", e),
+        }
+    }
+}
+
+impl Drop for TtsCache {
+    fn drop(&mut self) {
+        if self.unsaved_hits > 0 {
+            self.save_index();
+        }
+    }
+}
+
+fn audio_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{}.{}", key, AUDIO_FILE_EXTENSION))
+}
@@ -0,0 +1,379 @@
+// usage.rs
+//
+// Token usage and spend of the LLM calls. The `usage` block of every completion is appended
+// to `usage.jsonl` in the app data directory with the conversation and model it belongs
+// to, priced with the `usage.prices` table of the config. Daily and monthly budgets (in
+// local time) send a `budget_warning` event once a share of them is spent, and block LLM
+// calls once all of it is, until the next day or month.
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ModelPrice, UsageConfig};
+use crate::error::AssistantError;
+use crate::events::{AssistantEvent, EventSink};
+
+/// File name of the usage ledger inside the app data directory.
+pub const USAGE_FILE_NAME: &str = "usage.jsonl";
+
+/// Token counts of one completion, from its `usage` block.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    /// Output tokens billed, reasoning tokens included.
+    pub completion_tokens: u64,
+    /// The share of `completion_tokens` spent reasoning, for reasoning models.
+    pub reasoning_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Reads the `usage` block of a chat completion, if it has one.
+    ///
+    /// xAI leaves reasoning tokens out of `completion_tokens` (they are only in
+    /// `total_tokens`) while OpenAI counts them in, so output tokens are taken as whichever
+    /// of `completion_tokens` and `total_tokens - prompt_tokens` is larger.
+    pub fn from_completion(completion: &serde_json::Value) -> Option<Self> {
+        let usage = completion.get("usage").filter(|usage| usage.is_object())?;
+        let count = |value: &serde_json::Value| value.as_u64().unwrap_or(0);
+        let prompt_tokens = count(&usage["prompt_tokens"]);
+        let completion_tokens = count(&usage["completion_tokens"]);
+        let total_tokens = count(&usage["total_tokens"]);
+        Some(TokenUsage {
+            prompt_tokens,
+            completion_tokens: completion_tokens.max(total_tokens.saturating_sub(prompt_tokens)),
+            reasoning_tokens: count(&usage["completion_tokens_details"]["reasoning_tokens"]),
+        })
+    }
+}
+
+/// One LLM call in the ledger.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<FixedOffset>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<String>,
+    pub model: String,
+    #[serde(flatten)]
+    pub tokens: TokenUsage,
+    /// None when the model has no price in the config.
+    pub cost_usd: Option<f64>,
+}
+
+/// The period a budget covers, in local time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    Daily,
+    Monthly,
+}
+
+impl fmt::Display for BudgetPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Monthly => "monthly",
+        })
+    }
+}
+
+/// How much of a budget is spent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetState {
+    Ok,
+    /// At least `warn_at_percent` of the budget is spent.
+    Warning,
+    /// The budget is spent; LLM calls are blocked until the period ends.
+    Exceeded,
+}
+
+/// A budget and the spend of its current period.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BudgetStatus {
+    pub period: BudgetPeriod,
+    pub limit_usd: f64,
+    pub spent_usd: f64,
+    pub state: BudgetState,
+}
+
+/// Usage summed over a set of calls.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cost_usd: f64,
+    /// Calls of models without a price, left out of `cost_usd`.
+    pub unpriced_calls: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.calls += 1;
+        self.prompt_tokens += record.tokens.prompt_tokens;
+        self.completion_tokens += record.tokens.completion_tokens;
+        self.reasoning_tokens += record.tokens.reasoning_tokens;
+        match record.cost_usd {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced_calls += 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ModelUsage {
+    pub model: String,
+    #[serde(flatten)]
+    pub usage: UsageTotals,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConversationUsage {
+    /// None for calls made without a conversation id (e.g. one-off CLI turns).
+    pub conversation_id: Option<String>,
+    /// When the first call of the conversation in the window was made.
+    pub started: DateTime<FixedOffset>,
+    #[serde(flatten)]
+    pub usage: UsageTotals,
+}
+
+/// Returned by `get_usage_report`.
+#[derive(Clone, Debug, Serialize)]
+pub struct UsageReport {
+    pub since: Option<DateTime<FixedOffset>>,
+    pub until: DateTime<FixedOffset>,
+    pub total: UsageTotals,
+    pub by_model: Vec<ModelUsage>, // Sorted by model
+    pub by_conversation: Vec<ConversationUsage>, // Most recent first
+    /// The configured budgets, whatever the window.
+    pub budgets: Vec<BudgetStatus>,
+}
+
+/// The usage ledger. All methods take `&self`, so it can be shared between commands.
+pub struct UsageLedger {
+    /// None when the app data directory could not be resolved: usage is kept in memory only.
+    path: Option<PathBuf>,
+    config: UsageConfig,
+    state: Mutex<LedgerState>,
+}
+
+struct LedgerState {
+    records: Vec<UsageRecord>,
+    /// Models already reported as missing from the price table.
+    unpriced_models: BTreeSet<String>,
+}
+
+impl UsageLedger {
+    /// Opens the ledger in `dir`, reading the calls recorded so far. Lines that cannot be
+    /// read (e.g. cut off by a crash) are skipped.
+    pub fn open(dir: &Path, config: &UsageConfig) -> Result<Self, AssistantError> {
+        fs::create_dir_all(dir).map_err(|e| AssistantError::io(format!("Failed to create {}", dir.display()), e))?;
+        let path = dir.join(USAGE_FILE_NAME);
+        let records = match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let mut skipped = 0;
+                let records: Vec<UsageRecord> = contents.lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .filter_map(|line| serde_json::from_str(line).map_err(|_| skipped += 1).ok())
+                    .collect();
+                if skipped > 0 {
+                    tracing::warn!("Skipped {} unreadable lines of {}", skipped, path.display());
+                }
+                records
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(AssistantError::io(format!("Failed to read {}", path.display()), e)),
+        };
+        Ok(UsageLedger { path: Some(path), ..UsageLedger::with_records(config, records) })
+    }
+
+    /// A ledger that is not saved, used when the app data directory is not available.
+    pub fn in_memory(config: &UsageConfig) -> Self {
+        UsageLedger::with_records(config, Vec::new())
+    }
+
+    fn with_records(config: &UsageConfig, records: Vec<UsageRecord>) -> Self {
+        UsageLedger {
+            path: None,
+            config: config.clone(),
+            state: Mutex::new(LedgerState { records, unpriced_models: BTreeSet::new() }),
+        }
+    }
+
+    /// Fails with `BudgetExceeded` when the daily or monthly budget is spent.
+    pub fn check_budget(&self) -> Result<(), AssistantError> {
+        match self.budgets().into_iter().find(|budget| budget.state == BudgetState::Exceeded) {
+            Some(budget) => Err(AssistantError::BudgetExceeded {
+                period: budget.period,
+                spent_usd: budget.spent_usd,
+                limit_usd: budget.limit_usd,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Records an LLM call, prices it and appends it to the ledger file.
+    ///
+    /// # Arguments
+    /// * `conversation_id` - The conversation the call belongs to, if known.
+    /// * `model` - The model that answered, as reported by the provider.
+    /// * `tokens` - The `usage` block of the completion.
+    /// * `events` - Receives a `budget_warning` event when the call brings a budget to its
+    ///   warning share or spends it.
+    ///
+    /// # Returns
+    /// The record, also when it could not be saved (the failure is logged).
+    pub fn record(&self, conversation_id: Option<&str>, model: &str, tokens: TokenUsage, events: &dyn EventSink) -> UsageRecord {
+        let price = self.config.prices.get(model);
+        let record = UsageRecord {
+            timestamp: Local::now().fixed_offset(),
+            conversation_id: conversation_id.map(str::to_string),
+            model: model.to_string(),
+            cost_usd: price.map(|price| price.cost(&tokens)),
+            tokens,
+        };
+
+        // Both budget states come from the same lock, so concurrent calls each see the
+        // transition their own record makes
+        let (before, after) = {
+            let mut state = self.state.lock().unwrap();
+            if price.is_none() && state.unpriced_models.insert(model.to_string()) {
+                tracing::warn!("No price for model {} in usage.prices, its calls do not count against the budgets", model);
+            }
+            let before = self.budgets_of(&state.records);
+            state.records.push(record.clone());
+            (before, self.budgets_of(&state.records))
+        };
+        if let Err(e) = self.append(&record) {
+            tracing::warn!("Failed to save LLM usage: {}", e);
+        }
+
+        // Warn once when a budget reaches its warning share, and once more when it is spent
+        for (previous, budget) in before.iter().zip(after) {
+            if budget.state == previous.state || budget.state == BudgetState::Ok {
+                continue;
+            }
+            tracing::warn!(
+                "{:.0}% of the {} LLM budget is spent (${:.4} of ${:.2})",
+                budget.spent_usd / budget.limit_usd * 100.0,
+                budget.period,
+                budget.spent_usd,
+                budget.limit_usd
+            );
+            events.emit(AssistantEvent::budget_warning(&budget));
+        }
+        record
+    }
+
+    /// The configured budgets and what is spent of them in the current day and month.
+    pub fn budgets(&self) -> Vec<BudgetStatus> {
+        self.budgets_of(&self.state.lock().unwrap().records)
+    }
+
+    fn budgets_of(&self, records: &[UsageRecord]) -> Vec<BudgetStatus> {
+        let now = Local::now();
+        let limits = [
+            (BudgetPeriod::Daily, self.config.daily_budget_usd, start_of_day(now)),
+            (BudgetPeriod::Monthly, self.config.monthly_budget_usd, start_of_month(now)),
+        ];
+
+        limits.into_iter()
+            .filter_map(|(period, limit, start)| {
+                let limit_usd = limit?;
+                let spent_usd: f64 = records.iter()
+                    .filter(|record| record.timestamp >= start)
+                    .filter_map(|record| record.cost_usd)
+                    .sum();
+                let state = if spent_usd >= limit_usd {
+                    BudgetState::Exceeded
+                } else if spent_usd >= limit_usd * self.config.warn_at_percent / 100.0 {
+                    BudgetState::Warning
+                } else {
+                    BudgetState::Ok
+                };
+                Some(BudgetStatus { period, limit_usd, spent_usd, state })
+            })
+            .collect()
+    }
+
+    /// Sums the usage of the calls made since `since` (`None` for all history), per model
+    /// and per conversation.
+    pub fn report(&self, since: Option<DateTime<FixedOffset>>) -> UsageReport {
+        let until = Local::now().fixed_offset();
+        let mut total = UsageTotals::default();
+        let mut by_model: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        let mut by_conversation: BTreeMap<Option<String>, (DateTime<FixedOffset>, UsageTotals)> = BTreeMap::new();
+
+        let state = self.state.lock().unwrap();
+        for record in state.records.iter().filter(|record| since.is_none_or(|since| record.timestamp >= since)) {
+            total.add(record);
+            by_model.entry(record.model.clone()).or_default().add(record);
+            by_conversation.entry(record.conversation_id.clone())
+                .or_insert_with(|| (record.timestamp, UsageTotals::default()))
+                .1
+                .add(record);
+        }
+        drop(state);
+
+        let mut by_conversation: Vec<ConversationUsage> = by_conversation.into_iter()
+            .map(|(conversation_id, (started, usage))| ConversationUsage { conversation_id, started, usage })
+            .collect();
+        by_conversation.sort_by_key(|conversation| std::cmp::Reverse(conversation.started));
+
+        UsageReport {
+            since,
+            until,
+            total,
+            by_model: by_model.into_iter().map(|(model, usage)| ModelUsage { model, usage }).collect(),
+            by_conversation,
+            budgets: self.budgets(),
+        }
+    }
+
+    fn append(&self, record: &UsageRecord) -> Result<(), AssistantError> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let mut line = serde_json::to_string(record).map_err(|e| AssistantError::Internal(e.to_string()))?;
+        line.push('\n');
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| AssistantError::io(format!("Failed to write {}", path.display()), e))
+    }
+}
+
+impl ModelPrice {
+    /// The price of a call in USD.
+    pub fn cost(&self, tokens: &TokenUsage) -> f64 {
+        (tokens.prompt_tokens as f64 * self.prompt_per_million_usd
+            + tokens.completion_tokens as f64 * self.completion_per_million_usd)
+            / 1_000_000.0
+    }
+}
+
+fn start_of_day(now: DateTime<Local>) -> DateTime<FixedOffset> {
+    local_midnight(now.date_naive(), now)
+}
+
+fn start_of_month(now: DateTime<Local>) -> DateTime<FixedOffset> {
+    local_midnight(now.date_naive().with_day(1).expect("every month has a first day"), now)
+}
+
+// Midnight may not exist on days with a DST change at 00:00, in which case the day
+// starts at the first valid local time
+fn local_midnight(date: chrono::NaiveDate, now: DateTime<Local>) -> DateTime<FixedOffset> {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+    Local.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| Local.from_local_datetime(&(midnight + chrono::Duration::hours(1))).earliest())
+        .unwrap_or(now)
+        .fixed_offset()
+}
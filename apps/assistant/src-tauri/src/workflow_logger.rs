@@ -1,111 +1,701 @@
 // workflow_logger.rs
-use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
-use std::time::{SystemTime, Duration};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
-use chrono::Local;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use flate2::read::GzDecoder;
+
+use crate::log_writer::{self, LogWriter, RotationPolicy};
+
+/// File name of the workflow log inside the app log directory.
+pub const WORKFLOW_LOG_FILE_NAME: &str = "workflow_timings.log";
+
+/// `tracing` target of the spans and events emitted by `WorkflowTimings`.
+pub const WORKFLOW_TRACING_TARGET: &str = "mivis::workflow";
+
+const TEXT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+// Span ids are unique per process, so stages recorded by guards and by the tracing
+// layer never collide within a workflow
+static NEXT_SPAN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Allocates a new span id for a stage record.
+pub fn next_span_id() -> u64 {
+    NEXT_SPAN_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Arbitrary key/value attributes attached to a log record.
+pub type Attributes = BTreeMap<String, serde_json::Value>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkflowEventType {
+    Start,
+    Stage,
+    End,
+}
+
+impl WorkflowEventType {
+    fn as_text(&self) -> &'static str {
+        match self {
+            WorkflowEventType::Start => "START",
+            WorkflowEventType::Stage => "STAGE",
+            WorkflowEventType::End => "END",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordStatus {
+    Ok,
+    Error,
+}
+
+/// One structured workflow log entry, written as a single JSON line.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WorkflowLogRecord {
+    pub timestamp: DateTime<FixedOffset>,
+    pub workflow_id: String,
+    pub event_type: WorkflowEventType,
+    /// The kind of workflow (the id prefix, e.g. "tts"), set on start and end records.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workflow_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_span_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_us: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<RecordStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// The kind of the error, see `StageError::error_kind`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: Attributes,
+}
+
+impl WorkflowLogRecord {
+    fn new(workflow_id: &str, event_type: WorkflowEventType) -> Self {
+        WorkflowLogRecord {
+            timestamp: Local::now().fixed_offset(),
+            workflow_id: workflow_id.to_string(),
+            event_type,
+            workflow_type: None,
+            stage: None,
+            span_id: None,
+            parent_span_id: None,
+            duration_us: None,
+            status: None,
+            error: None,
+            error_kind: None,
+            message: None,
+            attributes: Attributes::new(),
+        }
+    }
+
+    /// Builds the record of a finished stage.
+    pub(crate) fn stage(workflow_id: &str, stage_name: &str, span_id: u64, parent_span_id: Option<u64>, duration: Duration, error: Option<StageFailure>, attributes: Attributes) -> Self {
+        let mut record = WorkflowLogRecord::new(workflow_id, WorkflowEventType::Stage);
+        record.stage = Some(stage_name.to_string());
+        record.span_id = Some(span_id);
+        record.parent_span_id = parent_span_id;
+        record.duration_us = Some(duration.as_micros() as u64);
+        record.status = Some(if error.is_some() { RecordStatus::Error } else { RecordStatus::Ok });
+        record.set_error(error);
+        record.attributes = attributes;
+        record
+    }
+
+    fn set_error(&mut self, error: Option<StageFailure>) {
+        if let Some(failure) = error {
+            self.error = Some(failure.message);
+            self.error_kind = failure.kind.map(str::to_string);
+        }
+    }
+}
+
+/// An error a stage or a workflow can fail with.
+pub trait StageError: std::fmt::Display {
+    /// A short, stable name of the kind of error, e.g. "timeout", which groups failures
+    /// in the metrics. `None` counts the error as "other".
+    fn error_kind(&self) -> Option<&'static str> {
+        None
+    }
+}
+
+impl StageError for String {}
+
+impl StageError for str {}
+
+impl<E: StageError + ?Sized> StageError for &E {
+    fn error_kind(&self) -> Option<&'static str> {
+        (**self).error_kind()
+    }
+}
+
+/// The error of a failed stage or workflow: its message and kind.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageFailure {
+    pub message: String,
+    pub kind: Option<&'static str>,
+}
+
+impl StageFailure {
+    pub fn new(error: &(impl StageError + ?Sized)) -> Self {
+        StageFailure { message: error.to_string(), kind: error.error_kind() }
+    }
+
+    // A stage or workflow abandoned before it finished, e.g. its future was dropped
+    fn abandoned(message: &str) -> Self {
+        StageFailure { message: message.to_string(), kind: Some("cancelled") }
+    }
+}
+
+/// Destination for workflow log records.
+pub trait WorkflowLogSink: Send + Sync {
+    fn write_record(&self, record: &WorkflowLogRecord);
+
+    /// Blocks until the records written so far are stored. Called before the app exits.
+    fn flush(&self) {}
+}
+
+/// Writes one JSON object per line. Lines are written by a background thread, see `LogWriter`.
+pub struct JsonLinesFileSink {
+    writer: LogWriter,
+}
+
+impl JsonLinesFileSink {
+    pub fn new(log_file: &str, policy: RotationPolicy) -> Self {
+        JsonLinesFileSink { writer: LogWriter::spawn(Path::new(log_file), policy) }
+    }
+}
+
+impl WorkflowLogSink for JsonLinesFileSink {
+    fn write_record(&self, record: &WorkflowLogRecord) {
+        match serde_json::to_string(record) {
+            Ok(line) => self.writer.write_line(line),
+            Err(e) => tracing::error!("Failed to serialize workflow log record: {}", e),
+        }
+    }
+
+    fn flush(&self) {
+        self.writer.flush();
+    }
+}
+
+/// Writes the original human-readable format, e.g.
+/// `[2025-05-19 10:00:00.000] ID: x | STAGE | Stage: y | DURATION: 12ms`.
+pub struct TextFileSink {
+    writer: LogWriter,
+}
+
+impl TextFileSink {
+    pub fn new(log_file: &str, policy: RotationPolicy) -> Self {
+        TextFileSink { writer: LogWriter::spawn(Path::new(log_file), policy) }
+    }
+}
+
+impl WorkflowLogSink for TextFileSink {
+    fn write_record(&self, record: &WorkflowLogRecord) {
+        self.writer.write_line(format_text_line(record));
+    }
+
+    fn flush(&self) {
+        self.writer.flush();
+    }
+}
+
+/// Passes every record on to several sinks, e.g. the log file and the metrics.
+pub struct FanoutSink(pub Vec<Arc<dyn WorkflowLogSink>>);
+
+impl WorkflowLogSink for FanoutSink {
+    fn write_record(&self, record: &WorkflowLogRecord) {
+        for sink in &self.0 {
+            sink.write_record(record);
+        }
+    }
+
+    fn flush(&self) {
+        for sink in &self.0 {
+            sink.flush();
+        }
+    }
+}
+
+/// Selects one of the built-in file sinks.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    JsonLines,
+    Text,
+}
+
+impl LogFormat {
+    pub fn file_sink(&self, log_file: &str, policy: RotationPolicy) -> Arc<dyn WorkflowLogSink> {
+        match self {
+            LogFormat::JsonLines => Arc::new(JsonLinesFileSink::new(log_file, policy)),
+            LogFormat::Text => Arc::new(TextFileSink::new(log_file, policy)),
+        }
+    }
+}
 
 /// A structure to track and log timing information for workflow stages.
+///
+/// Cloning is cheap and every clone refers to the same workflow, so a handle can be moved
+/// into async tasks. Stages are usually timed with `stage()`, which returns a guard that
+/// records the stage when it is dropped, including on early returns with `?`.
+#[derive(Clone)]
 pub struct WorkflowTimings {
+    inner: Arc<TimingsInner>,
+}
+
+struct TimingsInner {
     workflow_id: String,
-    start_time: SystemTime,
-    stage_start_time: SystemTime, // To measure current stage
-    stages_data: Vec<(String, Duration)>, // (stage_name, stage_duration)
-    log_file_path: String,
+    workflow_type: String,
+    start_time: Instant,
+    sink: Arc<dyn WorkflowLogSink>,
+    state: Mutex<TimingsState>,
+}
+
+struct TimingsState {
+    stage_start_time: Instant, // To measure current stage for record_stage
+    stages_data: Vec<(String, Duration)>, // (stage_name, stage_duration) of top-level stages
+    finalized: bool,
 }
 
 impl WorkflowTimings {
     /// Creates a new WorkflowTimings instance with a unique ID and logs the start event.
-    /// 
+    /// Records are written as JSON lines; use `with_sink` to select another format.
+    ///
     /// # Arguments
     /// * `id_prefix` - A prefix for the workflow ID to categorize the type of workflow.
     /// * `log_file` - The file path where logs will be written.
-    /// 
+    ///
     /// # Returns
     /// A new instance of WorkflowTimings.
     pub fn new(id_prefix: &str, log_file: &str) -> Self {
-        let id = format!("{}-{}", id_prefix, Uuid::new_v4().to_string());
-        let now = SystemTime::now();
-        
-        // Ensure the logs directory exists
-        if let Some(parent_dir) = Path::new(log_file).parent() {
-            if !parent_dir.exists() {
-                if let Err(e) = fs::create_dir_all(parent_dir) {
-                    eprintln!("[ERROR] Failed to create logs directory {}: {}", parent_dir.display(), e);
-                } else {
-                    println!("[INFO] Created logs directory: {}", parent_dir.display());
-                }
-            }
-        }
-        
+        Self::with_sink(id_prefix, LogFormat::JsonLines.file_sink(log_file, RotationPolicy::default()))
+    }
+
+    /// Creates a new WorkflowTimings instance that writes to the given sink and logs the start event.
+    ///
+    /// # Arguments
+    /// * `id_prefix` - A prefix for the workflow ID to categorize the type of workflow.
+    /// * `sink` - The destination for the log records.
+    pub fn with_sink(id_prefix: &str, sink: Arc<dyn WorkflowLogSink>) -> Self {
+        let id = format!("{}-{}", id_prefix, Uuid::new_v4());
+        let now = Instant::now();
+
         let instance = WorkflowTimings {
-            workflow_id: id.clone(),
-            start_time: now,
-            stage_start_time: now, // Initialize stage start time
-            stages_data: Vec::new(),
-            log_file_path: log_file.to_string(),
+            inner: Arc::new(TimingsInner {
+                workflow_id: id,
+                workflow_type: id_prefix.to_string(),
+                start_time: now,
+                sink,
+                state: Mutex::new(TimingsState {
+                    stage_start_time: now, // Initialize stage start time
+                    stages_data: Vec::new(),
+                    finalized: false,
+                }),
+            }),
         };
         // Log workflow start immediately
-        instance.log_entry("START", "Voice input processing initiated.");
+        let mut record = WorkflowLogRecord::new(&instance.inner.workflow_id, WorkflowEventType::Start);
+        record.workflow_type = Some(id_prefix.to_string());
+        record.message = Some(format!("Workflow {} started.", id_prefix));
+        instance.inner.sink.write_record(&record);
+        tracing::debug!(target: WORKFLOW_TRACING_TARGET, workflow_id = %instance.inner.workflow_id, "Workflow {} started", id_prefix);
         instance
     }
 
+    pub fn workflow_id(&self) -> &str {
+        &self.inner.workflow_id
+    }
+
+    /// Starts timing a top-level stage. The stage is recorded when the returned guard is
+    /// dropped: as successful if `succeed` was called, otherwise as failed.
+    ///
+    /// # Arguments
+    /// * `stage_name` - The name of the stage, e.g. "STT".
+    pub fn stage(&self, stage_name: &str) -> StageGuard {
+        StageGuard::new(self.clone(), stage_name, None)
+    }
+
     /// Resets the start time for the next stage.
-    pub fn start_new_stage(&mut self) {
-        self.stage_start_time = SystemTime::now();
+    pub fn start_new_stage(&self) {
+        self.inner.state.lock().unwrap().stage_start_time = Instant::now();
     }
-    
-    /// Records the duration of the current stage and logs it.
-    /// 
+
+    /// Records the duration of the current stage as successful and logs it.
+    ///
     /// # Arguments
     /// * `stage_name` - The name of the stage that just completed.
-    pub fn record_stage(&mut self, stage_name: String) {
-        let duration = SystemTime::now().duration_since(self.stage_start_time)
-            .unwrap_or_default();
-        self.stages_data.push((stage_name.clone(), duration));
-        self.log_entry("STAGE", &format!("Stage: {} | DURATION: {}ms", stage_name, duration.as_millis()));
-        self.start_new_stage(); // Prepare for the next stage
+    pub fn record_stage(&self, stage_name: String) {
+        self.record_stage_result(&stage_name, None, Attributes::new());
+    }
+
+    /// Records the duration of the current stage with its outcome and logs it.
+    ///
+    /// # Arguments
+    /// * `stage_name` - The name of the stage that just completed.
+    /// * `error` - The error message if the stage failed, `None` if it succeeded.
+    /// * `attributes` - Extra key/value details to store with the record.
+    pub fn record_stage_result(&self, stage_name: &str, error: Option<String>, attributes: Attributes) {
+        let duration = {
+            let mut state = self.inner.state.lock().unwrap();
+            let duration = state.stage_start_time.elapsed();
+            state.stage_start_time = Instant::now(); // Prepare for the next stage
+            duration
+        };
+        let error = error.map(|message| StageFailure { message, kind: None });
+        self.record_span(stage_name, next_span_id(), None, duration, error, attributes);
     }
 
     /// Finalizes the workflow timing, calculates overall statistics, and logs the end event.
-    /// 
+    ///
     /// # Arguments
     /// * `overall_status_message` - A message describing the final status of the workflow.
     pub fn finalize_and_log(&self, overall_status_message: &str) {
-        let total_duration = SystemTime::now().duration_since(self.start_time).unwrap_or_default();
-        let num_stages = self.stages_data.len();
-        let avg_stage_time_ms = if num_stages > 0 {
-            self.stages_data.iter().map(|s| s.1.as_millis()).sum::<u128>() / num_stages as u128
-        } else {
-            0
+        self.inner.finalize(overall_status_message, None);
+    }
+
+    /// Finalizes the workflow as failed and logs the end event.
+    ///
+    /// # Arguments
+    /// * `overall_status_message` - A message describing the final status of the workflow.
+    /// * `error` - The error that ended the workflow.
+    pub fn finalize_with_error(&self, overall_status_message: &str, error: &(impl StageError + ?Sized)) {
+        self.inner.finalize(overall_status_message, Some(StageFailure::new(error)));
+    }
+
+    fn record_span(&self, stage_name: &str, span_id: u64, parent_span_id: Option<u64>, duration: Duration, error: Option<StageFailure>, attributes: Attributes) {
+        if parent_span_id.is_none() {
+            self.inner.state.lock().unwrap().stages_data.push((stage_name.to_string(), duration));
+        }
+
+        let record = WorkflowLogRecord::stage(&self.inner.workflow_id, stage_name, span_id, parent_span_id, duration, error, attributes);
+        self.inner.sink.write_record(&record);
+    }
+}
+
+impl TimingsInner {
+    fn finalize(&self, overall_status_message: &str, error: Option<StageFailure>) {
+        let (num_stages, avg_stage_time_us) = {
+            let mut state = self.state.lock().unwrap();
+            if state.finalized {
+                return; // The end event is only logged once
+            }
+            state.finalized = true;
+
+            let num_stages = state.stages_data.len();
+            let avg_stage_time_us = if num_stages > 0 {
+                state.stages_data.iter().map(|s| s.1.as_micros()).sum::<u128>() / num_stages as u128
+            } else {
+                0
+            };
+            (num_stages, avg_stage_time_us)
         };
 
-        let log_message = format!(
-            "END: {} | TOTAL_E2E: {}ms | STAGES_COUNT: {} | AVG_STAGE_TIME: {}ms",
-            overall_status_message,
-            total_duration.as_millis(),
-            num_stages,
-            avg_stage_time_ms
-        );
-        self.log_entry("END", &log_message);
+        let mut record = WorkflowLogRecord::new(&self.workflow_id, WorkflowEventType::End);
+        record.workflow_type = Some(self.workflow_type.clone());
+        record.duration_us = Some(self.start_time.elapsed().as_micros() as u64);
+        record.message = Some(overall_status_message.to_string());
+        record.status = Some(if error.is_some() { RecordStatus::Error } else { RecordStatus::Ok });
+        record.set_error(error);
+        record.attributes.insert("stages_count".to_string(), num_stages.into());
+        record.attributes.insert("avg_stage_time_us".to_string(), (avg_stage_time_us as u64).into());
+        self.sink.write_record(&record);
+
+        let total_ms = self.start_time.elapsed().as_millis() as u64;
+        match &record.error {
+            None => tracing::info!(target: WORKFLOW_TRACING_TARGET, workflow_id = %self.workflow_id, total_ms, "{}", overall_status_message),
+            Some(e) => tracing::warn!(target: WORKFLOW_TRACING_TARGET, workflow_id = %self.workflow_id, total_ms, error = %e, "{}", overall_status_message),
+        }
     }
+}
 
-    /// Logs an entry to the specified log file with a timestamp and event details.
-    /// 
-    /// # Arguments
-    /// * `event_type` - The type of event being logged (e.g., START, STAGE, END).
-    /// * `message` - The message or details of the event.
-    fn log_entry(&self, event_type: &str, message: &str) {
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
-        let log_line = format!("[{}] ID: {} | {} | {}\n", timestamp, self.workflow_id, event_type, message);
-        
-        if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(&self.log_file_path) {
-            if let Err(e) = file.write_all(log_line.as_bytes()) {
-                eprintln!("[ERROR] Failed to write to workflow log: {}", e);
+impl Drop for TimingsInner {
+    // A workflow that is never finalized (e.g. its command returned early) still gets an end event
+    fn drop(&mut self) {
+        self.finalize("Workflow ended without being finalized.", Some(StageFailure::abandoned("not finalized")));
+    }
+}
+
+/// Times one stage (span) of a workflow and records it when dropped.
+///
+/// Call `succeed`, `fail` or `complete` to set the outcome; a guard dropped without an
+/// outcome (early return, `?`, cancelled future) is recorded as failed. Guards are `Send`,
+/// so they can be held across `.await` points.
+///
+/// Each guard also opens a `tracing` span (target `WORKFLOW_TRACING_TARGET`). Instrument
+/// work with it to give its log events the stage context; spans created inside it are
+/// recorded as child stages by `telemetry::WorkflowLayer`.
+#[must_use = "the stage is recorded when the guard is dropped"]
+pub struct StageGuard {
+    timings: WorkflowTimings,
+    name: String,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    started: Instant,
+    outcome: Option<Result<(), StageFailure>>,
+    attributes: Attributes,
+    span: tracing::Span,
+}
+
+impl StageGuard {
+    fn new(timings: WorkflowTimings, name: &str, parent: Option<&StageGuard>) -> Self {
+        let span_id = next_span_id();
+        let workflow_id = timings.workflow_id();
+        let span = match parent {
+            Some(parent) => tracing::info_span!(target: WORKFLOW_TRACING_TARGET, parent: &parent.span, "stage", workflow_id, span_id, stage = name),
+            None => tracing::info_span!(target: WORKFLOW_TRACING_TARGET, "stage", workflow_id, span_id, stage = name),
+        };
+        StageGuard {
+            name: name.to_string(),
+            span_id,
+            parent_span_id: parent.map(|parent| parent.span_id),
+            started: Instant::now(),
+            outcome: None,
+            attributes: Attributes::new(),
+            span,
+            timings,
+        }
+    }
+
+    /// Starts a nested stage, e.g. "tool:web_search" inside "LLM".
+    pub fn child(&self, stage_name: &str) -> StageGuard {
+        StageGuard::new(self.timings.clone(), stage_name, Some(self))
+    }
+
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    /// The `tracing` span of this stage, e.g. for `future.instrument(stage.span().clone())`.
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Attaches a key/value attribute to the stage record.
+    pub fn set_attribute(&mut self, key: &str, value: impl Into<serde_json::Value>) {
+        self.attributes.insert(key.to_string(), value.into());
+    }
+
+    /// Ends the stage successfully.
+    pub fn succeed(mut self) {
+        self.outcome = Some(Ok(()));
+    }
+
+    /// Ends the stage as failed with the given error.
+    pub fn fail(mut self, error: impl StageError) {
+        self.outcome = Some(Err(StageFailure::new(&error)));
+    }
+
+    /// Ends the stage with the outcome of `result`.
+    pub fn complete<T, E: StageError>(self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.succeed(),
+            Err(e) => self.fail(e), // `&E` is a `StageError` too
+        }
+    }
+}
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        let error = match self.outcome.take() {
+            Some(Ok(())) => None,
+            Some(Err(e)) => Some(e),
+            None => Some(StageFailure::abandoned("stage ended without completing")),
+        };
+        let duration = self.started.elapsed();
+        match &error {
+            None => tracing::debug!(target: WORKFLOW_TRACING_TARGET, parent: &self.span, duration_us = duration.as_micros() as u64, "Stage {} finished", self.name),
+            Some(failure) => tracing::warn!(target: WORKFLOW_TRACING_TARGET, parent: &self.span, duration_us = duration.as_micros() as u64, error = %failure.message, "Stage {} failed", self.name),
+        }
+        let attributes = std::mem::take(&mut self.attributes);
+        self.timings.record_span(&self.name, self.span_id, self.parent_span_id, duration, error, attributes);
+    }
+}
+
+/// Reads a workflow log file written in either format; `.gz` segments are decompressed.
+/// Lines that cannot be parsed are skipped.
+///
+/// # Arguments
+/// * `log_file` - The path of the log file.
+///
+/// # Returns
+/// The parsed records in file order, or an error message if the file cannot be opened.
+pub fn read_log_file(log_file: &Path) -> Result<Vec<WorkflowLogRecord>, String> {
+    let file = fs::File::open(log_file)
+        .map_err(|e| format!("Failed to open workflow log {}: {}", log_file.display(), e))?;
+    let reader: Box<dyn Read> = if log_file.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    Ok(BufReader::new(reader).lines()
+        .map_while(Result::ok)
+        .filter_map(|line| parse_log_line(&line))
+        .collect())
+}
+
+/// Reads the rotated segments of a workflow log followed by the active file.
+///
+/// # Arguments
+/// * `log_file` - The path of the active log file.
+///
+/// # Returns
+/// The parsed records, oldest first. Segments that cannot be read are skipped.
+pub fn read_log_history(log_file: &Path) -> Result<Vec<WorkflowLogRecord>, String> {
+    let mut records = Vec::new();
+    for segment in log_writer::list_segments(log_file) {
+        match read_log_file(&segment) {
+            Ok(segment_records) => records.extend(segment_records),
+            Err(e) => tracing::warn!("Skipping workflow log segment: {}", e),
+        }
+    }
+    if log_file.exists() {
+        records.extend(read_log_file(log_file)?);
+    }
+    Ok(records)
+}
+
+/// Parses one log line in either the JSON-lines or the text format.
+pub fn parse_log_line(line: &str) -> Option<WorkflowLogRecord> {
+    let line = line.trim();
+    if line.starts_with('{') {
+        serde_json::from_str(line).ok()
+    } else if line.starts_with('[') {
+        parse_text_line(line)
+    } else {
+        None
+    }
+}
+
+fn format_text_line(record: &WorkflowLogRecord) -> String {
+    let timestamp = record.timestamp.format(TEXT_TIMESTAMP_FORMAT);
+    let message = match record.event_type {
+        WorkflowEventType::Start => record.message.clone().unwrap_or_default(),
+        WorkflowEventType::Stage => format!(
+            "Stage: {} | DURATION: {}ms",
+            record.stage.as_deref().unwrap_or(""),
+            record.duration_us.unwrap_or(0) / 1000
+        ),
+        WorkflowEventType::End => {
+            let avg_stage_time_ms = record.attributes.get("avg_stage_time_us")
+                .and_then(|v| v.as_u64()).unwrap_or(0) / 1000;
+            format!(
+                "END: {} | TOTAL_E2E: {}ms | STAGES_COUNT: {} | AVG_STAGE_TIME: {}ms",
+                record.message.as_deref().unwrap_or(""),
+                record.duration_us.unwrap_or(0) / 1000,
+                record.attributes.get("stages_count").and_then(|v| v.as_u64()).unwrap_or(0),
+                avg_stage_time_ms
+            )
+        }
+    };
+
+    let mut line = format!("[{}] ID: {} | {} | {}", timestamp, record.workflow_id, record.event_type.as_text(), message);
+    if let Some(workflow_type) = &record.workflow_type {
+        line.push_str(&format!(" | TYPE: {}", workflow_type));
+    }
+    if let Some(span_id) = record.span_id {
+        line.push_str(&format!(" | SPAN: {}", span_id));
+    }
+    if let Some(parent_span_id) = record.parent_span_id {
+        line.push_str(&format!(" | PARENT: {}", parent_span_id));
+    }
+    if record.status == Some(RecordStatus::Error) {
+        line.push_str(" | STATUS: error");
+    }
+    if let Some(error) = &record.error {
+        line.push_str(&format!(" | ERROR: {}", error.replace('|', "/")));
+    }
+    if let Some(error_kind) = &record.error_kind {
+        line.push_str(&format!(" | KIND: {}", error_kind));
+    }
+    if record.event_type != WorkflowEventType::End {
+        for (key, value) in &record.attributes {
+            // Values are JSON, where \u007c stands for the separator and parses back to it
+            line.push_str(&format!(" | {}={}", key, value.to_string().replace('|', "\\u007c")));
+        }
+    }
+    line
+}
+
+fn parse_text_line(line: &str) -> Option<WorkflowLogRecord> {
+    let (timestamp_str, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let naive = NaiveDateTime::parse_from_str(timestamp_str, TEXT_TIMESTAMP_FORMAT).ok()?;
+    let timestamp = Local.from_local_datetime(&naive).earliest()?.fixed_offset();
+
+    let mut fields = rest.split(" | ");
+    let workflow_id = fields.next()?.strip_prefix("ID: ")?;
+    let event_type = match fields.next()? {
+        "START" => WorkflowEventType::Start,
+        "STAGE" => WorkflowEventType::Stage,
+        "END" => WorkflowEventType::End,
+        _ => return None,
+    };
+
+    let mut record = WorkflowLogRecord::new(workflow_id, event_type);
+    record.timestamp = timestamp;
+    if event_type == WorkflowEventType::Stage {
+        record.status = Some(RecordStatus::Ok); // Older text logs only recorded successful stages
+    }
+
+    for field in fields {
+        if let Some(stage) = field.strip_prefix("Stage: ") {
+            record.stage = Some(stage.to_string());
+        } else if let Some(duration) = field.strip_prefix("DURATION: ") {
+            record.duration_us = parse_millis(duration).map(|ms| ms * 1000);
+        } else if let Some(message) = field.strip_prefix("END: ") {
+            record.message = Some(message.to_string());
+        } else if let Some(total) = field.strip_prefix("TOTAL_E2E: ") {
+            record.duration_us = parse_millis(total).map(|ms| ms * 1000);
+        } else if let Some(count) = field.strip_prefix("STAGES_COUNT: ") {
+            if let Ok(count) = count.parse::<u64>() {
+                record.attributes.insert("stages_count".to_string(), count.into());
+            }
+        } else if let Some(avg) = field.strip_prefix("AVG_STAGE_TIME: ") {
+            if let Some(ms) = parse_millis(avg) {
+                record.attributes.insert("avg_stage_time_us".to_string(), (ms * 1000).into());
             }
-        } else {
-            eprintln!("[ERROR] Failed to open workflow log file: {}", self.log_file_path);
+        } else if let Some(span_id) = field.strip_prefix("SPAN: ") {
+            record.span_id = span_id.parse().ok();
+        } else if let Some(parent_span_id) = field.strip_prefix("PARENT: ") {
+            record.parent_span_id = parent_span_id.parse().ok();
+        } else if field == "STATUS: error" {
+            record.status = Some(RecordStatus::Error);
+        } else if let Some(error) = field.strip_prefix("ERROR: ") {
+            record.error = Some(error.to_string());
+        } else if let Some(error_kind) = field.strip_prefix("KIND: ") {
+            record.error_kind = Some(error_kind.to_string());
+        } else if let Some(workflow_type) = field.strip_prefix("TYPE: ") {
+            record.workflow_type = Some(workflow_type.to_string());
+        } else if let Some((key, value)) = field.split_once('=') {
+            let value = serde_json::from_str(value).unwrap_or_else(|_| value.into());
+            record.attributes.insert(key.to_string(), value);
+        } else if event_type == WorkflowEventType::Start {
+            record.message = Some(field.to_string());
         }
     }
+    Some(record)
+}
+
+fn parse_millis(value: &str) -> Option<u64> {
+    value.strip_suffix("ms")?.trim().parse().ok()
 }
@@ -0,0 +1,77 @@
+// Tests of the latency statistics computed from workflow log records
+use assistant_lib::analytics::{compute_latency_report, StageLatencyStats, END_TO_END_STAGE};
+use assistant_lib::workflow_logger::WorkflowLogRecord;
+use chrono::{DateTime, FixedOffset};
+use serde_json::json;
+
+fn at(timestamp: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(timestamp).unwrap()
+}
+
+fn record(timestamp: &str, workflow_id: &str, stage: Option<&str>, duration_us: u64, status: &str) -> WorkflowLogRecord {
+    let event_type = if stage.is_some() { "stage" } else { "end" };
+    serde_json::from_value(json!({
+        "timestamp": timestamp,
+        "workflow_id": workflow_id,
+        "event_type": event_type,
+        "stage": stage,
+        "duration_us": duration_us,
+        "status": status,
+    })).unwrap()
+}
+
+fn stats(stage: &str, count: usize, error_count: usize, [min_us, p50_us, p90_us, p99_us, max_us]: [u64; 5]) -> StageLatencyStats {
+    StageLatencyStats {
+        stage: stage.to_string(),
+        count,
+        error_count,
+        error_rate: error_count as f64 / count as f64,
+        min_us, p50_us, p90_us, p99_us, max_us,
+    }
+}
+
+#[test]
+fn reports_nearest_rank_percentiles_per_stage() {
+    // STT takes 1..=100 ms in shuffled order, one call in ten fails
+    let mut records: Vec<WorkflowLogRecord> = (1..=100u64)
+        .map(|n| {
+            let status = if n % 10 == 0 { "error" } else { "ok" };
+            record("2024-05-01T10:00:00+07:00", &format!("w{}", n), Some("STT"), (n * 37 % 101) * 1000, status)
+        })
+        .collect();
+    for (n, duration_us) in [30_000, 10_000, 20_000].into_iter().enumerate() {
+        records.push(record("2024-05-01T10:00:00+07:00", &format!("w{}", n + 1), Some("LLM"), duration_us, "ok"));
+        records.push(record("2024-05-01T10:00:01+07:00", &format!("w{}", n + 1), None, duration_us + 5_000, "ok"));
+    }
+
+    let report = compute_latency_report(&records, None, at("2024-05-02T00:00:00+07:00"));
+
+    assert_eq!(report.workflows, 100);
+    assert_eq!(report.stages, vec![
+        stats("LLM", 3, 0, [10_000, 20_000, 30_000, 30_000, 30_000]),
+        stats("STT", 100, 10, [1_000, 50_000, 90_000, 99_000, 100_000]),
+    ]);
+    assert_eq!(report.end_to_end, Some(stats(END_TO_END_STAGE, 3, 0, [15_000, 25_000, 35_000, 35_000, 35_000])));
+}
+
+#[test]
+fn counts_only_the_records_inside_the_window() {
+    let records = vec![
+        record("2024-04-30T09:59:59+07:00", "old", Some("TTS"), 900_000, "error"),
+        record("2024-04-30T09:59:59+07:00", "old", None, 950_000, "error"),
+        record("2024-05-01T10:00:00+07:00", "w1", Some("TTS"), 2_000, "ok"),
+        record("2024-05-01T03:30:00Z", "w2", Some("TTS"), 4_000, "error"),
+        record("2024-05-01T10:00:01+07:00", "w2", None, 6_000, "ok"),
+        // The end of the window is excluded
+        record("2024-05-01T11:00:00+07:00", "new", Some("TTS"), 1, "ok"),
+    ];
+
+    let report = compute_latency_report(&records, Some(at("2024-05-01T10:00:00+07:00")), at("2024-05-01T11:00:00+07:00"));
+
+    assert_eq!(report.workflows, 2);
+    assert_eq!(report.stages, vec![stats("TTS", 2, 1, [2_000, 2_000, 4_000, 4_000, 4_000])]);
+    assert_eq!(report.end_to_end, Some(stats(END_TO_END_STAGE, 1, 0, [6_000; 5])));
+
+    let empty = compute_latency_report(&records, Some(at("2024-05-02T00:00:00+07:00")), at("2024-05-03T00:00:00+07:00"));
+    assert_eq!((empty.workflows, empty.stages.len(), empty.end_to_end), (0, 0, None));
+}
@@ -0,0 +1,114 @@
+// Integration tests of file attachments: reading images and documents of the app data
+// directory, and sending them to the vision model as content parts
+mod support;
+
+use std::path::{Path, PathBuf};
+
+use assistant_lib::attachments::attach;
+use assistant_lib::{AssistantError, ContentPart, ImageUrl, MessageContent};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use image::{DynamicImage, GenericImageView, RgbImage, RgbaImage};
+use serde_json::{json, Value};
+use support::*;
+
+fn data_dir(app: &TestApp) -> PathBuf {
+    let data_dir = app.root.join("data");
+    std::fs::create_dir_all(&data_dir).unwrap();
+    data_dir
+}
+
+// The MIME type and the decoded image of an image part
+fn inline_image(part: &ContentPart) -> (String, DynamicImage) {
+    let ContentPart::ImageUrl { image_url: ImageUrl { url, .. } } = part else {
+        panic!("expected an image, got {:?}", part);
+    };
+    let (mime_type, data) = url.strip_prefix("data:").and_then(|url| url.split_once(";base64,")).expect("not a base64 data URL");
+    (mime_type.to_string(), image::load_from_memory(&BASE64.decode(data).unwrap()).unwrap())
+}
+
+#[test]
+fn downscales_photos_to_jpeg() {
+    let app = TestApp::start(json!({}));
+    let data_dir = data_dir(&app);
+    RgbImage::from_pixel(2000, 1000, image::Rgb([200, 30, 30])).save(data_dir.join("photo.png")).unwrap();
+
+    let part = attach(Path::new("photo.png"), &data_dir, &app.services.config.attachments).unwrap();
+
+    let (mime_type, image) = inline_image(&part);
+    assert_eq!(mime_type, "image/jpeg");
+    assert_eq!(image.dimensions(), (1024, 512));
+}
+
+#[test]
+fn keeps_small_transparent_images_as_png() {
+    let app = TestApp::start(json!({}));
+    let data_dir = data_dir(&app);
+    RgbaImage::from_pixel(40, 30, image::Rgba([0, 0, 0, 0])).save(data_dir.join("icon.png")).unwrap();
+
+    // Absolute paths inside the directory work too
+    let part = attach(&data_dir.join("icon.png"), &data_dir, &app.services.config.attachments).unwrap();
+
+    let (mime_type, image) = inline_image(&part);
+    assert_eq!(mime_type, "image/png");
+    assert_eq!(image.dimensions(), (40, 30));
+    assert!(image.color().has_alpha());
+}
+
+#[test]
+fn attaches_an_excerpt_of_text_documents() {
+    let app = TestApp::start(json!({ "attachments": { "max_excerpt_chars": 12 } }));
+    let data_dir = data_dir(&app);
+    std::fs::create_dir_all(data_dir.join("notes")).unwrap();
+    std::fs::write(data_dir.join("notes/lịch.md"), "Thứ hai: họp nhóm lúc 9 giờ").unwrap();
+
+    let part = attach(Path::new("notes/lịch.md"), &data_dir, &app.services.config.attachments).unwrap();
+
+    assert_eq!(part, ContentPart::text("File lịch.md:\n```\nThứ hai: họp\n```\n(Cut off after 12 of 27 characters)"));
+}
+
+#[test]
+fn refuses_files_outside_of_the_data_directory_and_other_kinds() {
+    let app = TestApp::start(json!({ "attachments": { "max_file_bytes": 1024 } }));
+    let data_dir = data_dir(&app);
+    let config = &app.services.config.attachments;
+
+    // The config directory is next to the data directory
+    let reason = invalid_input(attach(Path::new("../config/config.json"), &data_dir, config));
+    assert!(reason.contains("outside of the app data directory"), "{}", reason);
+
+    std::fs::write(data_dir.join("archive.zip"), [0x50, 0x4b, 0x03, 0x04, 0xff, 0xfe, 0x00]).unwrap();
+    let reason = invalid_input(attach(Path::new("archive.zip"), &data_dir, config));
+    assert!(reason.contains("neither an image nor a text document"), "{}", reason);
+
+    std::fs::write(data_dir.join("big.txt"), "a".repeat(2048)).unwrap();
+    let reason = invalid_input(attach(Path::new("big.txt"), &data_dir, config));
+    assert!(reason.contains("more than the 1024 bytes"), "{}", reason);
+
+    assert!(matches!(attach(Path::new("missing.png"), &data_dir, config), Err(AssistantError::Io { .. })));
+}
+
+#[tokio::test]
+async fn sends_conversations_with_images_to_the_vision_model() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Một con mèo"), chat_completion("Chào")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-3-mini-beta" } }));
+    let data_dir = data_dir(&app);
+    RgbImage::from_pixel(8, 8, image::Rgb([255, 255, 255])).save(data_dir.join("cat.png")).unwrap();
+    let image = attach(Path::new("cat.png"), &data_dir, &app.services.config.attachments).unwrap();
+
+    app.chat(user_request(MessageContent::Parts(vec![ContentPart::text("Đây là gì?"), image.clone()]))).await.unwrap();
+    app.chat(user_request("Chào")).await.unwrap();
+
+    let requests = server.requests();
+    let body = requests[0].json();
+    assert_eq!(body["model"], "grok-2-vision-1212");
+    let content = &body["messages"][1]["content"];
+    assert_eq!(content[0], json!({ "type": "text", "text": "Đây là gì?" }));
+    assert_eq!(content[1]["type"], "image_url");
+    assert!(content[1]["image_url"]["url"].as_str().unwrap().starts_with("data:image/jpeg;base64,"));
+
+    // Text stays a plain string and goes to the configured model
+    let body = requests[1].json();
+    assert_eq!(body["model"], "grok-3-mini-beta");
+    assert_eq!(body["messages"][1]["content"], Value::from("Chào"));
+}
@@ -0,0 +1,171 @@
+// Integration tests of the circuit breakers of the STT service and the TTS backends
+mod support;
+
+use std::time::Duration;
+
+use assistant_lib::circuit_breaker::{CircuitState, ServiceHealth};
+use assistant_lib::events::{AssistantEvent, RecordingEventSink};
+use assistant_lib::pipeline::{self, SpeechRequest};
+use assistant_lib::{AssistantError, CancellationToken};
+use serde_json::{json, Value};
+use support::*;
+
+// One attempt per call, so each call counts as one failure
+fn stt_app(url: &str, circuit_breaker: Value) -> TestApp {
+    TestApp::start(json!({ "stt": { "url": url, "retry": { "max_attempts": 1 }, "circuit_breaker": circuit_breaker } }))
+}
+
+async fn transcribe(app: &TestApp, events: &RecordingEventSink) -> Result<String, AssistantError> {
+    let services = &app.services;
+    let recording = wav_bytes(16_000, &[0; 1600]);
+    pipeline::transcribe(recording, &services.config.stt, &services.http.stt, services.workflow_log.clone(), &services.redactor, events, &CancellationToken::new()).await
+}
+
+fn states(events: &RecordingEventSink) -> Vec<CircuitState> {
+    events.health_changes().into_iter().map(|change| change.state).collect()
+}
+
+#[tokio::test]
+async fn fails_fast_once_the_service_keeps_failing() {
+    let server = MockServer::start(STT_PATH, vec![stt_error(503, "model loading")]).await;
+    let app = stt_app(&server.url(), json!({ "failure_threshold": 2, "open_secs": 60 }));
+    let events = RecordingEventSink::new();
+
+    assert!(matches!(transcribe(&app, &events).await, Err(AssistantError::Upstream { status: 503, .. })));
+    assert!(states(&events).is_empty());
+    assert!(matches!(transcribe(&app, &events).await, Err(AssistantError::Upstream { status: 503, .. })));
+    assert_eq!(states(&events), vec![CircuitState::Open]);
+
+    // The third call is not sent
+    let error = transcribe(&app, &events).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::ServiceUnavailable { reason, .. } if reason.starts_with("circuit open")), "{}", error);
+    assert_eq!(server.requests().len(), 2);
+
+    let services = &app.services;
+    let health = pipeline::service_health(&services.http, &services.tts_chain);
+    assert_eq!(health[0], ServiceHealth { service: "STT service".to_string(), state: CircuitState::Open });
+    assert_eq!(health[1].state, CircuitState::Closed);
+
+    let event = events.events().into_iter().find(|event| event.name() == "service_health_changed").unwrap();
+    let payload = serde_json::to_value(&event).unwrap();
+    assert_eq!(payload["service"], "STT service");
+    assert_eq!(payload["state"], "open");
+    assert_eq!(payload["previous"], "closed");
+    assert!(payload["reason"].as_str().unwrap().contains("503"));
+    assert!(matches!(event, AssistantEvent::ServiceHealthChanged(_)));
+}
+
+#[tokio::test]
+async fn closes_after_a_successful_probe() {
+    let server = MockServer::start(STT_PATH, vec![stt_error(500, "down"), transcription("đã lên")]).await;
+    let app = stt_app(&server.url(), json!({ "failure_threshold": 1, "open_secs": 1 }));
+    let events = RecordingEventSink::new();
+
+    assert!(transcribe(&app, &events).await.is_err());
+    assert!(transcribe(&app, &events).await.is_err());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    assert_eq!(transcribe(&app, &events).await.unwrap(), "đã lên");
+    assert_eq!(states(&events), vec![CircuitState::Open, CircuitState::HalfOpen, CircuitState::Closed]);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn reopens_after_a_failed_probe() {
+    let server = MockServer::start(STT_PATH, vec![stt_error(502, "still down")]).await;
+    let app = stt_app(&server.url(), json!({ "failure_threshold": 1, "open_secs": 1 }));
+    let events = RecordingEventSink::new();
+
+    assert!(transcribe(&app, &events).await.is_err());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert!(matches!(transcribe(&app, &events).await, Err(AssistantError::Upstream { status: 502, .. })));
+
+    assert_eq!(states(&events), vec![CircuitState::Open, CircuitState::HalfOpen, CircuitState::Open]);
+    assert!(matches!(transcribe(&app, &events).await, Err(AssistantError::ServiceUnavailable { .. })));
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn probes_again_when_the_probe_is_abandoned() {
+    let server = MockServer::start(STT_PATH, vec![
+        stt_error(500, "down"),
+        transcription("quá muộn").delayed(Duration::from_secs(2)),
+        transcription("đã lên"),
+    ]).await;
+    let app = stt_app(&server.url(), json!({ "failure_threshold": 1, "open_secs": 1 }));
+    let events = RecordingEventSink::new();
+
+    assert!(transcribe(&app, &events).await.is_err());
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    // The caller gives up on the probe, dropping its future
+    assert!(tokio::time::timeout(Duration::from_millis(200), transcribe(&app, &events)).await.is_err());
+    assert_eq!(app.services.http.stt.breaker().state(), CircuitState::HalfOpen);
+
+    // The next call becomes the probe instead of failing fast
+    assert_eq!(transcribe(&app, &events).await.unwrap(), "đã lên");
+    assert_eq!(states(&events), vec![CircuitState::Open, CircuitState::HalfOpen, CircuitState::Closed]);
+}
+
+#[tokio::test]
+async fn ignores_failures_that_do_not_mean_the_service_is_down() {
+    let server = MockServer::start(STT_PATH, vec![stt_error(400, "bad audio")]).await;
+    let app = stt_app(&server.url(), json!({ "failure_threshold": 1, "open_secs": 60 }));
+    let events = RecordingEventSink::new();
+
+    for _ in 0..3 {
+        assert!(matches!(transcribe(&app, &events).await, Err(AssistantError::Upstream { status: 400, .. })));
+    }
+    assert!(states(&events).is_empty());
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn skips_a_tts_backend_with_an_open_circuit() {
+    let server = MockServer::start(TTS_PATH, vec![Reply::Hangup]).await;
+    let app = TestApp::start(json!({ "tts": {
+        "backends": [{ "type": "viettts", "url": server.url(), "default_voice": "diep-chi" }, { "type": "mock" }],
+        "retry": { "max_attempts": 1 },
+        "circuit_breaker": { "failure_threshold": 1, "open_secs": 60 }
+    } }));
+    let services = &app.services;
+    let events = RecordingEventSink::new();
+
+    for text in ["Một", "Hai"] {
+        let request = SpeechRequest { text: text.to_string(), ..Default::default() };
+        let audio = pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &events, &CancellationToken::new()).await;
+        assert!(audio.unwrap().starts_with(b"RIFF"));
+    }
+
+    // The second phrase went straight to the mock backend
+    assert_eq!(server.requests().len(), 1);
+    let changes = events.health_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!((changes[0].service.as_str(), changes[0].state), ("VietTTS service", CircuitState::Open));
+}
+
+#[tokio::test]
+async fn gives_each_openai_compatible_backend_a_circuit_of_its_own() {
+    let down = MockServer::start(TTS_PATH, vec![Reply::Hangup]).await;
+    let up = MockServer::start(TTS_PATH, vec![speech(test_tone())]).await;
+    let backend = |url: String| json!({ "type": "openai_compatible", "url": url, "model": "tts-1", "default_voice": "alloy" });
+    let app = TestApp::start(json!({ "tts": {
+        "backends": [backend(down.url()), backend(up.url())],
+        "retry": { "max_attempts": 1 },
+        "circuit_breaker": { "failure_threshold": 1, "open_secs": 60 }
+    } }));
+    let services = &app.services;
+    let events = RecordingEventSink::new();
+
+    for text in ["Một", "Hai"] {
+        let request = SpeechRequest { text: text.to_string(), ..Default::default() };
+        pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &events, &CancellationToken::new()).await.unwrap();
+    }
+
+    // Only the circuit of the failing server opened
+    assert_eq!(down.requests().len(), 1);
+    assert_eq!(up.requests().len(), 2);
+    let changes = events.health_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].service, format!("openai-compatible:{}", down.url()));
+}
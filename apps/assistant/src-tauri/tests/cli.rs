@@ -0,0 +1,122 @@
+// Tests of mivis-cli: the arguments it accepts, and the reports and secrets of an app
+// directory given by MIVIS_APP_DIR
+mod support;
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use assistant_lib::secrets::SecretStore;
+use assistant_lib::usage::USAGE_FILE_NAME;
+use assistant_lib::workflow_logger::WorkflowTimings;
+use serde_json::{json, Value};
+use support::*;
+
+// Runs the CLI on the directories of `app`, with `stdin` as its input
+fn run_cli(app: &TestApp, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mivis-cli"))
+        .args(args)
+        .env("MIVIS_APP_DIR", &app.root)
+        .current_dir(&app.root) // Away from the .env of the source tree
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn rejects_invalid_arguments_with_the_usage() {
+    let app = TestApp::start(json!({}));
+
+    let cases: [(&[&str], &str); 7] = [
+        (&[], "Usage:"),
+        (&["translate"], "Unknown command: translate"),
+        (&["stats", "--since-hours", "0"], "--since-hours must be greater than zero, got 0"),
+        (&["usage", "--since-hours"], "Missing value for --since-hours"),
+        (&["usage", "--verbose"], "Unknown argument: --verbose"),
+        (&["chat", "--temperature", "hot"], "Invalid temperature"),
+        (&["speak", "Xin chào"], "Usage:"),
+    ];
+    for (args, error) in cases {
+        let output = run_cli(&app, args, "");
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&output).contains(error), "{:?}: {}", args, stderr(&output));
+    }
+
+    let output = run_cli(&app, &["--help"], "");
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("mivis-cli stats [--since-hours <hours>] [--json]"));
+}
+
+#[test]
+fn reports_the_latency_of_the_app_directory() {
+    let app = TestApp::start(json!({}));
+    for outcome in [Ok(()), Err("busy")] {
+        let timings = WorkflowTimings::with_sink("stt", app.services.workflow_log.clone());
+        let stage = timings.stage("STT");
+        match outcome {
+            Ok(()) => stage.succeed(),
+            Err(error) => stage.fail(error.to_string()),
+        }
+        timings.finalize_and_log("Completed.");
+    }
+    app.flush_logs();
+
+    let output = run_cli(&app, &["stats", "--since-hours", "1", "--json"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stats: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(stats["latency"]["workflows"], 2);
+    assert_eq!(stats["latency"]["stages"][0]["stage"], "STT");
+    assert_eq!((stats["latency"]["stages"][0]["count"].as_u64(), stats["latency"]["stages"][0]["error_count"].as_u64()), (Some(2), Some(1)));
+    assert_eq!(stats["tts_cache"]["entries"], 0);
+
+    let output = run_cli(&app, &["stats"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("STT") && stdout(&output).contains("TTS cache: 0 entries"), "{}", stdout(&output));
+}
+
+#[test]
+fn reports_the_usage_of_the_app_directory() {
+    let app = TestApp::start(json!({}));
+    let now = chrono::Local::now().fixed_offset();
+    let record = |timestamp: chrono::DateTime<chrono::FixedOffset>, conversation: &str, cost: f64| {
+        json!({ "timestamp": timestamp, "conversation_id": conversation, "model": "grok-3-mini-beta", "prompt_tokens": 12, "completion_tokens": 5, "reasoning_tokens": 0, "cost_usd": cost }).to_string()
+    };
+    let lines = [record(now - chrono::Duration::days(3), "old", 1.0), record(now, "a", 0.25), record(now, "b", 0.5)];
+    std::fs::write(app.root.join("data").join(USAGE_FILE_NAME), lines.join("\n")).unwrap();
+
+    let output = run_cli(&app, &["usage", "--json"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let report: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!((report["total"]["calls"].as_u64(), report["total"]["prompt_tokens"].as_u64()), (Some(3), Some(36)));
+    assert_eq!(report["total"]["cost_usd"], 1.75);
+
+    let output = run_cli(&app, &["usage", "--since-hours", "24"], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let total = stdout(&output).lines().find(|line| line.starts_with("TOTAL")).unwrap().to_string();
+    assert_eq!(total.split_whitespace().collect::<Vec<_>>(), vec!["TOTAL", "2", "24", "10", "0.7500"]);
+    assert!(stdout(&output).contains("2 conversations"));
+}
+
+#[test]
+fn sets_a_secret_from_piped_input() {
+    let app = TestApp::start(json!({}));
+
+    let output = run_cli(&app, &["secrets", "set", "TAVILY_API_KEY"], "tvly-piped-value\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stdout(&output).contains("tvly-piped-value") && !stderr(&output).contains("tvly-piped-value"));
+
+    let secrets = SecretStore::open(&app.root.join("data"), &app.services.config.secrets).unwrap();
+    assert_eq!(secrets.get("TAVILY_API_KEY").as_deref(), Some("tvly-piped-value"));
+    assert_eq!(stdout(&run_cli(&app, &["secrets", "list"], "")), "TAVILY_API_KEY\n");
+}
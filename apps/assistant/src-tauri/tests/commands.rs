@@ -0,0 +1,200 @@
+// Integration tests of the commands that do not call an external service: the latency
+// report, playback and barge-in, and the error payload every command returns
+mod support;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::pipeline;
+use assistant_lib::{AssistantError, CancellationToken};
+use assistant_lib::playback::{BargeIn, NullSink, PlaybackEngine, PlaybackEvent, PlaybackState};
+use serde_json::json;
+use support::*;
+
+#[tokio::test]
+async fn latency_report_covers_the_workflow_log() {
+    let server = MockServer::start(STT_PATH, vec![transcription("một"), stt_error(503, "busy")]).await;
+    let app = TestApp::start(json!({ "stt": { "url": server.url() } }));
+    let services = &app.services;
+    for _ in 0..2 {
+        let recording = wav_bytes(16_000, &[0; 160]);
+        let _ = pipeline::transcribe(recording, &services.config.stt, &services.http.stt, services.workflow_log.clone(), &services.redactor, &RecordingEventSink::new(), &CancellationToken::new()).await;
+    }
+    app.flush_logs();
+
+    let report = pipeline::latency_report(&services.log_dir, None).unwrap();
+    assert_eq!(report.workflows, 2);
+    let stt = report.stages.iter().find(|stage| stage.stage == "STT").expect("no STT stage");
+    assert_eq!((stt.count, stt.error_count), (2, 1));
+
+    let recent = pipeline::latency_report(&services.log_dir, Some(1.0)).unwrap();
+    assert_eq!(recent.workflows, 2);
+}
+
+#[tokio::test]
+async fn latency_report_rejects_an_empty_window() {
+    let app = TestApp::start(json!({}));
+
+    let error = pipeline::latency_report(&app.services.log_dir, Some(0.0)).unwrap_err();
+    assert!(matches!(&error, AssistantError::InvalidInput(reason) if reason == "window_hours must be greater than zero"), "{}", error);
+}
+
+#[tokio::test]
+async fn reports_reject_windows_past_the_representable_dates() {
+    let app = TestApp::start(json!({}));
+    let services = &app.services;
+
+    // The first overflows the window itself, the second its start date
+    for hours in [1e13, 1e10, f64::INFINITY] {
+        let error = pipeline::latency_report(&services.log_dir, Some(hours)).unwrap_err();
+        assert!(matches!(&error, AssistantError::InvalidInput(reason) if reason.starts_with("window_hours is too large")), "{}", error);
+        let error = pipeline::usage_report(&services.usage, Some(hours)).unwrap_err();
+        assert!(matches!(error, AssistantError::InvalidInput(_)), "{}", error);
+    }
+}
+
+fn start_engine() -> (PlaybackEngine, Arc<Mutex<Vec<PlaybackEvent>>>) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let engine = PlaybackEngine::new(|| Box::new(NullSink::new()), move |event| recorded.lock().unwrap().push(event));
+    (engine, events)
+}
+
+// Waits until the worker thread has caught up with the commands sent so far
+async fn settle() {
+    tokio::time::sleep(Duration::from_millis(150)).await;
+}
+
+#[tokio::test]
+async fn playback_commands_drive_the_engine() {
+    let (engine, events) = start_engine();
+
+    let clip_id = engine.enqueue(test_tone()).unwrap();
+    settle().await;
+    let status = engine.status();
+    assert_eq!((status.state, status.clip_id, status.duration_ms), (PlaybackState::Playing, Some(clip_id), Some(1000)));
+
+    engine.pause().unwrap();
+    settle().await;
+    assert_eq!(engine.status().state, PlaybackState::Paused);
+
+    engine.resume().unwrap();
+    settle().await;
+    assert_eq!(engine.status().state, PlaybackState::Playing);
+
+    engine.stop().unwrap();
+    settle().await;
+    assert_eq!(engine.status().state, PlaybackState::Idle);
+    assert!(!engine.is_active());
+
+    let events = events.lock().unwrap();
+    assert!(matches!(events.first(), Some(PlaybackEvent::Started { .. })));
+    assert!(events.iter().any(|event| matches!(event, PlaybackEvent::Paused { .. })));
+    assert!(events.iter().any(|event| matches!(event, PlaybackEvent::Resumed { .. })));
+    assert!(matches!(events.last(), Some(PlaybackEvent::Stopped { clip_id: Some(_), .. })));
+}
+
+#[tokio::test]
+async fn enqueued_clips_count_before_the_worker_takes_them() {
+    let (engine, _events) = start_engine();
+    let barge_in = BargeIn::new();
+
+    engine.enqueue(test_tone()).unwrap();
+    engine.enqueue(test_tone()).unwrap();
+    assert!(engine.is_active());
+    assert!(engine.status().queued >= 1);
+
+    // Speech right after the reply was queued still stops it
+    let frames: Vec<_> = (0..5).map(|_| barge_in.process_frame(&engine, &[20_000i16; 480]).unwrap()).collect();
+    assert!(frames.last().unwrap().barged_in);
+    settle().await;
+    assert!(!engine.is_active());
+    assert_eq!(engine.status().queued, 0);
+}
+
+#[tokio::test]
+async fn playback_plays_unknown_audio_without_a_duration() {
+    let (engine, _events) = start_engine();
+
+    engine.enqueue(b"not audio".to_vec()).unwrap();
+    settle().await;
+
+    // The null sink cannot tell how long the clip is, so it finishes right away
+    assert_eq!(engine.status().state, PlaybackState::Idle);
+}
+
+#[tokio::test]
+async fn barge_in_stops_active_playback() {
+    let (engine, events) = start_engine();
+    let barge_in = BargeIn::new();
+    let loud = vec![20_000i16; 480];
+    let quiet = vec![0i16; 480];
+
+    // Speech with nothing playing is reported but stops nothing
+    assert!(!barge_in.process_frame(&engine, &quiet).unwrap().speech_detected);
+    let frames: Vec<_> = (0..5).map(|_| barge_in.process_frame(&engine, &loud).unwrap()).collect();
+    assert!(frames.last().unwrap().speech_detected);
+    assert!(frames.iter().all(|frame| !frame.barged_in));
+
+    engine.enqueue(test_tone()).unwrap();
+    settle().await;
+    let frames: Vec<_> = (0..5).map(|_| barge_in.process_frame(&engine, &loud).unwrap()).collect();
+    assert!(frames.iter().any(|frame| frame.barged_in));
+    settle().await;
+    assert_eq!(engine.status().state, PlaybackState::Idle);
+    assert!(events.lock().unwrap().iter().any(|event| matches!(event, PlaybackEvent::Stopped { .. })));
+}
+
+#[tokio::test]
+async fn barge_in_can_be_disabled() {
+    let (engine, _events) = start_engine();
+    let barge_in = BargeIn::new();
+    barge_in.set_enabled(false);
+
+    engine.enqueue(test_tone()).unwrap();
+    settle().await;
+    let frames: Vec<_> = (0..10).map(|_| barge_in.process_frame(&engine, &[20_000i16; 480]).unwrap()).collect();
+    assert!(frames.iter().all(|frame| !frame.barged_in));
+    assert_eq!(engine.status().state, PlaybackState::Playing);
+}
+
+#[test]
+fn errors_serialize_to_tagged_json() {
+    let error = AssistantError::RateLimited { service: "LLM API".to_string(), retry_after: Some(Duration::from_secs(20)) };
+    assert_eq!(serde_json::to_value(&error).unwrap(), json!({
+        "kind": "rate_limited",
+        "message": "Đã gửi quá nhiều yêu cầu tới mô hình ngôn ngữ. Vui lòng thử lại sau 20 giây.",
+        "detail": "LLM API rate limited the request, retry after 20s",
+        "service": "LLM API",
+        "retry_after_secs": 20
+    }));
+
+    let error = AssistantError::Upstream { service: "STT service".to_string(), status: 500, body: "model not loaded".to_string(), retry_after: None };
+    let value = serde_json::to_value(&error).unwrap();
+    assert_eq!((value["kind"].as_str(), value["status"].as_u64()), (Some("upstream"), Some(500)));
+    assert_eq!(value["message"], "Dịch vụ nhận dạng giọng nói trả về lỗi 500.");
+
+    assert_eq!(serde_json::to_value(AssistantError::Cancelled).unwrap()["kind"], "cancelled");
+}
+
+#[test]
+fn user_messages_name_services_in_vietnamese() {
+    let unavailable = |service: &str| AssistantError::ServiceUnavailable { service: service.to_string(), reason: "down".to_string() };
+    let cases = [
+        (unavailable("TTS"), "Hiện không dùng được dịch vụ đọc văn bản. Vui lòng thử lại sau."),
+        (unavailable("TTS cache"), "Hiện không dùng được bộ nhớ đệm giọng đọc. Vui lòng thử lại sau."),
+        (unavailable("Secret store"), "Hiện không dùng được kho khóa bí mật. Vui lòng thử lại sau."),
+        // Configured TTS backends keep their own names
+        (unavailable("piper"), "Hiện không dùng được dịch vụ piper. Vui lòng thử lại sau."),
+        (AssistantError::Auth { service: "VietTTS service".to_string(), reason: "status 401".to_string() },
+            "Xác thực với dịch vụ VietTTS thất bại. Vui lòng kiểm tra khóa API."),
+        (AssistantError::Timeout { service: "STT service".to_string(), after: Duration::from_secs(30) },
+            "Không nhận được phản hồi từ dịch vụ nhận dạng giọng nói sau 30 giây."),
+        (AssistantError::InvalidResponse { service: "LLM API".to_string(), reason: "no choices".to_string() },
+            "Mô hình ngôn ngữ trả về phản hồi không hợp lệ."),
+    ];
+    for (error, message) in cases {
+        assert_eq!(error.user_message(), message, "{}", error);
+    }
+}
@@ -0,0 +1,142 @@
+// Integration tests of the generation parameters of `invoke_llm_chat` (chat): merging,
+// the request body and the checks against the provider
+mod support;
+
+use assistant_lib::{ChatRequest, GenerationParams, ReasoningEffort, ResponseFormat};
+use serde_json::{json, Value};
+use support::*;
+
+// The user's greeting, with the persona and parameters of the call
+fn request(persona: Option<&str>, params: GenerationParams) -> ChatRequest {
+    ChatRequest { persona: persona.map(str::to_string), params, ..user_request("Chào") }
+}
+
+#[tokio::test]
+async fn sends_only_the_configured_model_and_messages_by_default() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Chào")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+
+    app.chat(request(None, GenerationParams::default())).await.unwrap();
+
+    let body = server.requests()[0].json();
+    let mut keys: Vec<&String> = body.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, vec!["messages", "model"]);
+}
+
+#[tokio::test]
+async fn call_params_override_the_persona_and_the_config() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("{}")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": {
+        "model": "grok-3-beta",
+        "params": { "temperature": 0.7, "max_tokens": 200, "top_p": 0.9 },
+        "personas": { "arisu": { "temperature": 1.2, "seed": 7 } }
+    } }));
+
+    let params = GenerationParams {
+        max_tokens: Some(50),
+        stop: Some(vec!["\n\n".to_string()]),
+        response_format: Some(ResponseFormat::JsonObject),
+        ..GenerationParams::default()
+    };
+    app.chat(request(Some("arisu"), params)).await.unwrap();
+
+    let body = server.requests()[0].json();
+    assert_eq!(body["model"], "grok-3-beta");
+    assert_eq!(body["temperature"].as_f64().map(|t| (t * 10.0).round()), Some(12.0));
+    assert_eq!(body["top_p"].as_f64().map(|p| (p * 10.0).round()), Some(9.0));
+    assert_eq!(body["max_tokens"], 50);
+    assert_eq!(body["seed"], 7);
+    assert_eq!(body["stop"], json!(["\n\n"]));
+    assert_eq!(body["response_format"], json!({ "type": "json_object" }));
+    assert!(body.get("presence_penalty").is_none() && body.get("reasoning_effort").is_none());
+}
+
+#[tokio::test]
+async fn sets_the_reasoning_effort_of_grok_3_mini() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Nghĩ xong")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-3-mini-beta" } }));
+
+    let params = GenerationParams { reasoning_effort: Some(ReasoningEffort::High), ..GenerationParams::default() };
+    app.chat(request(None, params)).await.unwrap();
+
+    assert_eq!(server.requests()[0].json()["reasoning_effort"], "high");
+}
+
+#[tokio::test]
+async fn rejects_parameters_the_xai_model_does_not_support() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Không gửi")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-3-mini-beta" } }));
+
+    // Reasoning models take no penalties or stop sequences, and xAI has no medium effort
+    let params = GenerationParams {
+        presence_penalty: Some(0.5),
+        stop: Some(vec!["END".to_string()]),
+        reasoning_effort: Some(ReasoningEffort::Medium),
+        ..GenerationParams::default()
+    };
+    let reason = invalid_input(app.chat(request(None, params)).await);
+    assert!(reason.contains("reasoning_effort \"medium\", presence_penalty, stop not supported by grok-3-mini-beta on xAI"), "{}", reason);
+
+    // Nothing was sent
+    assert!(server.requests().is_empty());
+
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Không gửi")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-3-beta" } }));
+    let params = GenerationParams { reasoning_effort: Some(ReasoningEffort::Low), ..GenerationParams::default() };
+    assert!(invalid_input(app.chat(request(None, params)).await).contains("reasoning_effort not supported by grok-3-beta"));
+}
+
+#[tokio::test]
+async fn checks_openai_models_and_renames_max_tokens() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Xong")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "provider": "openai", "model": "o3-mini" } }));
+
+    let params = GenerationParams { temperature: Some(0.2), ..GenerationParams::default() };
+    assert!(invalid_input(app.chat(request(None, params)).await).contains("temperature not supported by o3-mini on OpenAI"));
+
+    let params = GenerationParams { max_tokens: Some(64), reasoning_effort: Some(ReasoningEffort::Medium), ..GenerationParams::default() };
+    app.chat(request(None, params)).await.unwrap();
+    let body = server.requests()[0].json();
+    assert_eq!(body["max_completion_tokens"], 64);
+    assert!(body.get("max_tokens").is_none());
+    assert_eq!(body["reasoning_effort"], "medium");
+}
+
+#[tokio::test]
+async fn passes_everything_to_compatible_servers() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Được")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "provider": "compatible", "model": "llama-local" } }));
+
+    let params = GenerationParams {
+        frequency_penalty: Some(1.0),
+        reasoning_effort: Some(ReasoningEffort::Low),
+        ..GenerationParams::default()
+    };
+    app.chat(request(None, params)).await.unwrap();
+    let body = server.requests()[0].json();
+    assert_eq!(body["frequency_penalty"], 1.0);
+    assert_eq!(body["reasoning_effort"], "low");
+    assert_eq!(body["max_tokens"], Value::Null);
+}
+
+#[tokio::test]
+async fn rejects_out_of_range_values_and_unknown_personas() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Không gửi")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-3-beta", "personas": { "arisu": {} } } }));
+
+    let params = GenerationParams {
+        temperature: Some(3.0),
+        max_tokens: Some(0),
+        stop: Some(vec!["a", "b", "c", "d", "e"].into_iter().map(str::to_string).collect()),
+        ..GenerationParams::default()
+    };
+    let reason = invalid_input(app.chat(request(None, params)).await);
+    assert!(reason.contains("temperature must be between 0 and 2, got 3"), "{}", reason);
+    assert!(reason.contains("max_tokens must be at least 1"), "{}", reason);
+    assert!(reason.contains("stop takes 1 to 4 non-empty sequences"), "{}", reason);
+
+    let reason = invalid_input(app.chat(request(Some("kuro"), GenerationParams::default())).await);
+    assert_eq!(reason, "Unknown persona 'kuro' (configured: arisu)");
+    assert!(server.requests().is_empty());
+}
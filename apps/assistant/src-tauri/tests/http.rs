@@ -0,0 +1,87 @@
+// Integration tests of the shared HTTP clients: User-Agent, timeouts, proxy and CA settings
+mod support;
+
+use std::time::{Duration, Instant};
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::http::{HttpClients, USER_AGENT};
+use assistant_lib::pipeline::{self, SpeechRequest};
+use assistant_lib::{AssistantError, CancellationToken};
+use serde_json::{json, Value};
+use support::*;
+
+async fn transcribe(app: &TestApp) -> Result<String, AssistantError> {
+    let services = &app.services;
+    let recording = wav_bytes(16_000, &[0; 1600]);
+    pipeline::transcribe(recording, &services.config.stt, &services.http.stt, services.workflow_log.clone(), &services.redactor, &RecordingEventSink::new(), &CancellationToken::new()).await
+}
+
+fn stt_app(stt: Value, http: Value) -> TestApp {
+    TestApp::start(json!({ "stt": stt, "http": http }))
+}
+
+#[tokio::test]
+async fn identifies_the_app_version() {
+    let stt = MockServer::start(STT_PATH, vec![transcription("xin chào")]).await;
+    let tts = MockServer::start(TTS_PATH, vec![speech(test_tone())]).await;
+    let app = TestApp::start(json!({ "stt": { "url": stt.url() }, "tts": tts_config(&tts.url())["tts"] }));
+
+    transcribe(&app).await.unwrap();
+    let services = &app.services;
+    let request = SpeechRequest { text: "Phiên bản".to_string(), ..Default::default() };
+    pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &RecordingEventSink::new(), &CancellationToken::new()).await.unwrap();
+
+    assert!(USER_AGENT.starts_with("Mivis-Assistant/") && USER_AGENT.ends_with(env!("CARGO_PKG_VERSION")));
+    assert_eq!(stt.requests()[0].header("user-agent"), Some(USER_AGENT));
+    assert_eq!(tts.requests()[0].header("user-agent"), Some(USER_AGENT));
+}
+
+#[tokio::test]
+async fn times_out_a_stalled_service() {
+    let server = MockServer::start(STT_PATH, vec![transcription("quá muộn").delayed(Duration::from_secs(5))]).await;
+    let app = stt_app(json!({ "url": server.url(), "timeouts": { "read_secs": 1 }, "retry": { "max_attempts": 1 } }), json!({}));
+
+    let started = Instant::now();
+    let error = transcribe(&app).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Timeout { service, after } if service == "STT service" && *after == Duration::from_secs(1)), "{}", error);
+    assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn sends_requests_through_the_proxy() {
+    // A proxy receives the absolute URL of the target in the request line
+    let proxy = MockServer::start("http://stt.example/transcribe", vec![transcription("qua proxy")]).await;
+    let app = stt_app(json!({ "url": "http://stt.example/transcribe" }), json!({ "http_proxy": proxy.origin() }));
+
+    assert_eq!(transcribe(&app).await.unwrap(), "qua proxy");
+    assert_eq!(proxy.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn reaches_local_services_without_the_proxy() {
+    let server = MockServer::start(STT_PATH, vec![transcription("trực tiếp")]).await;
+    let app = stt_app(json!({ "url": server.url() }), json!({ "http_proxy": unreachable_url("").await }));
+
+    // 127.0.0.1 is in the default no_proxy list
+    assert_eq!(transcribe(&app).await.unwrap(), "trực tiếp");
+}
+
+#[test]
+fn rejects_unusable_ca_certificates() {
+    let app = TestApp::start(json!({}));
+    let mut config = app.services.config.clone();
+
+    config.http.ca_certificate = Some(app.root.join("missing.pem"));
+    let error = HttpClients::from_config(&config).unwrap_err();
+    assert!(error.starts_with("Failed to read CA certificate"), "{}", error);
+
+    let path = app.root.join("empty.pem");
+    std::fs::write(&path, "not a certificate").unwrap();
+    config.http.ca_certificate = Some(path);
+    let error = HttpClients::from_config(&config).unwrap_err();
+    assert!(error.starts_with("No certificate found") || error.starts_with("Invalid CA certificate"), "{}", error);
+
+    config.http.ca_certificate = None;
+    config.http.https_proxy = Some("not a url".to_string());
+    assert!(HttpClients::from_config(&config).unwrap_err().starts_with("Invalid https_proxy"));
+}
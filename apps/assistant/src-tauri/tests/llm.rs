@@ -0,0 +1,155 @@
+// Integration tests of `invoke_llm_chat` (chat) against a mock xAI chat completions API
+mod support;
+
+use std::time::Duration;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::usage::TokenUsage;
+use assistant_lib::{AssistantError, CancellationToken, ChatRequest, ChatResult, FinishReason, Message};
+use serde_json::{json, Value};
+use support::*;
+
+#[tokio::test]
+async fn returns_the_reply() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Chào mày")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+    let events = RecordingEventSink::new();
+
+    let messages = vec![Message { role: "user".to_string(), content: "Chào".into(), source: Some("text".to_string()) }];
+    let reply = app.chat_with(ChatRequest::new(messages), &events, &CancellationToken::new()).await.unwrap();
+    assert_eq!(reply, ChatResult {
+        content: "Chào mày".to_string(),
+        reasoning_content: None,
+        finish_reason: Some(FinishReason::Stop),
+        usage: Some(TokenUsage { prompt_tokens: 12, completion_tokens: 5, reasoning_tokens: 0 }),
+        model: "grok-3-mini-beta".to_string(),
+    });
+
+    // The configured model and key are used and the system prompt goes first
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].header("authorization"), Some("Bearer test-key"));
+    let body = requests[0].json();
+    assert_eq!(body["model"], "grok-mock");
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(body["messages"][1], json!({ "role": "user", "content": "Chào", "source": "text" }));
+
+    assert_eq!(events.stages(), vec!["PROCESSING_API"]);
+}
+
+#[tokio::test]
+async fn waits_for_a_slow_provider() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Từ từ").delayed(Duration::from_millis(300))]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    assert_eq!(app.chat(user_request("Nhanh lên")).await.unwrap().content, "Từ từ");
+}
+
+#[tokio::test]
+async fn reports_error_statuses_with_the_body() {
+    let server = MockServer::start(LLM_PATH, vec![api_error(500, "The server had an error")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Upstream { status: 500, body, .. } if body.contains("The server had an error")), "{}", error);
+    assert!(error.to_string().starts_with("LLM API returned error status 500"), "{}", error);
+}
+
+#[tokio::test]
+async fn classifies_rejected_keys_and_rate_limits() {
+    let server = MockServer::start(LLM_PATH, vec![
+        api_error(401, "Incorrect API key provided"),
+        api_error(429, "Too many requests").with_header("Retry-After", "20"),
+    ]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Auth { reason, .. } if reason.contains("Incorrect API key provided")), "{}", error);
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::RateLimited { retry_after: Some(delay), .. } if delay == Duration::from_secs(20)), "{}", error);
+}
+
+// A completion with the given message and finish reason
+fn completion(message: Value, finish_reason: Value) -> Reply {
+    Reply::json(200, json!({
+        "model": "grok-3-mini-beta",
+        "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }]
+    }))
+}
+
+#[tokio::test]
+async fn returns_the_reasoning_apart_from_the_answer() {
+    let server = MockServer::start(LLM_PATH, vec![
+        completion(json!({ "role": "assistant", "content": "Hà Nội", "reasoning_content": "Dễ mà." }), json!("stop")),
+        // Servers that inline the reasoning, and a reply cut off by max_tokens
+        completion(json!({ "role": "assistant", "content": "<think>\nNghĩ đã...\n</think>\n\nHà Nội chứ đâu" }), json!("length")),
+        completion(json!({ "role": "assistant", "content": "<think>Vẫn đang nghĩ" }), json!("length")),
+    ]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let reply = app.chat(user_request("Thủ đô?")).await.unwrap();
+    assert_eq!((reply.content.as_str(), reply.reasoning_content.as_deref()), ("Hà Nội", Some("Dễ mà.")));
+    assert_eq!(reply.usage, None);
+
+    let reply = app.chat(user_request("Thủ đô?")).await.unwrap();
+    assert_eq!((reply.content.as_str(), reply.reasoning_content.as_deref()), ("Hà Nội chứ đâu", Some("Nghĩ đã...")));
+    assert_eq!(reply.finish_reason, Some(FinishReason::Length));
+
+    let reply = app.chat(user_request("Thủ đô?")).await.unwrap();
+    assert_eq!((reply.content.as_str(), reply.reasoning_content.as_deref()), ("", Some("Vẫn đang nghĩ")));
+}
+
+#[tokio::test]
+async fn accepts_replies_without_content() {
+    let server = MockServer::start(LLM_PATH, vec![
+        completion(json!({ "role": "assistant", "content": null, "tool_calls": [] }), json!("tool_calls")),
+        completion(json!({ "role": "assistant", "content": "" }), json!("content_filter")),
+        completion(json!({ "role": "assistant", "content": "Hết" }), json!("end_turn")),
+    ]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let reply = app.chat(user_request("Chào")).await.unwrap();
+    assert_eq!((reply.content.as_str(), reply.finish_reason), ("", Some(FinishReason::ToolCalls)));
+    assert_eq!(app.chat(user_request("Chào")).await.unwrap().finish_reason, Some(FinishReason::ContentFilter));
+    // Reasons this version does not know are kept as `other`
+    assert_eq!(app.chat(user_request("Chào")).await.unwrap().finish_reason, Some(FinishReason::Other));
+}
+
+#[tokio::test]
+async fn rejects_malformed_json() {
+    let server = MockServer::start(LLM_PATH, vec![Reply::malformed_json()]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::InvalidResponse { .. }), "{}", error);
+}
+
+#[tokio::test]
+async fn rejects_a_completion_without_content() {
+    let server = MockServer::start(LLM_PATH, vec![Reply::json(200, json!({ "choices": [] }))]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert_eq!(error.to_string(), "LLM API returned an invalid response: Content field not found in response");
+}
+
+#[tokio::test]
+async fn rejects_an_unrequested_stream() {
+    // The backend asks for a complete response, so an SSE stream cannot be parsed
+    let server = MockServer::start(LLM_PATH, vec![chat_completion_stream(&["Chào", " mày"])]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "model": "grok-mock" } }));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::InvalidResponse { .. }), "{}", error);
+    assert_ne!(server.requests()[0].json()["stream"], json!(true));
+}
+
+#[tokio::test]
+async fn requires_the_api_key() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("unused")]).await;
+    let app = TestApp::start(json!({ "llm": { "url": server.url(), "api_key_env": "MIVIS_TEST_UNSET_API_KEY" } }));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Auth { reason, .. } if reason.starts_with("Missing API key MIVIS_TEST_UNSET_API_KEY")), "{}", error);
+    assert!(server.requests().is_empty());
+}
@@ -0,0 +1,149 @@
+// Tests of the background log writer: batching, rotation, gzipped segments and retention
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use assistant_lib::log_writer::{self, LogWriter, RotationPolicy};
+use chrono::{Local, NaiveDate};
+use flate2::read::GzDecoder;
+
+// A directory of its own, deleted when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("mivis-log-writer-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    fn log_file(&self) -> PathBuf {
+        self.0.join("workflow.log")
+    }
+
+    // The name of segment `sequence` of `date` next to the log file
+    fn segment(&self, date: NaiveDate, sequence: u32) -> PathBuf {
+        self.0.join(format!("workflow.{}-{}.log.gz", date.format("%Y%m%d"), sequence))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn today() -> NaiveDate {
+    Local::now().date_naive()
+}
+
+fn gunzip(path: &Path) -> String {
+    let mut text = String::new();
+    GzDecoder::new(File::open(path).unwrap()).read_to_string(&mut text).unwrap();
+    text
+}
+
+fn set_age(path: &Path, age: Duration) {
+    File::options().write(true).open(path).unwrap().set_modified(SystemTime::now() - age).unwrap();
+}
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[test]
+fn rotates_by_size_into_numbered_gzipped_segments() {
+    let dir = TempDir::new();
+    let writer = LogWriter::spawn(&dir.log_file(), RotationPolicy { max_file_bytes: 20, retention_days: 0 });
+
+    // Each line takes 10 bytes with its newline, so every third one starts a new file
+    for line in ["line-0001", "line-0002", "line-0003", "line-0004", "line-0005", "line-0006", "line-0007"] {
+        writer.write_line(line.to_string());
+        writer.flush();
+    }
+
+    let segments = log_writer::list_segments(&dir.log_file());
+    assert_eq!(segments, vec![dir.segment(today(), 1), dir.segment(today(), 2), dir.segment(today(), 3)]);
+    assert_eq!(gunzip(&segments[0]), "line-0001\nline-0002\n");
+    assert_eq!(gunzip(&segments[1]), "line-0003\nline-0004\n");
+    assert_eq!(gunzip(&segments[2]), "line-0005\nline-0006\n");
+    assert_eq!(fs::read_to_string(dir.log_file()).unwrap(), "line-0007\n");
+    // Only the gzipped copies are kept
+    assert!(!dir.0.join(format!("workflow.{}-1.log", today().format("%Y%m%d"))).exists());
+}
+
+#[test]
+fn picks_the_next_free_number_for_a_segment() {
+    let dir = TempDir::new();
+    fs::write(dir.segment(today(), 1), b"older").unwrap();
+    fs::write(dir.0.join(format!("workflow.{}-2.log", today().format("%Y%m%d"))), b"not yet gzipped").unwrap();
+    let writer = LogWriter::spawn(&dir.log_file(), RotationPolicy { max_file_bytes: 10, retention_days: 0 });
+
+    for line in ["line-0001", "line-0002"] {
+        writer.write_line(line.to_string());
+        writer.flush();
+    }
+
+    assert_eq!(gunzip(&dir.segment(today(), 3)), "line-0001\n");
+    assert_eq!(fs::read(dir.segment(today(), 1)).unwrap(), b"older");
+}
+
+#[test]
+fn rotates_the_file_of_an_earlier_day() {
+    let dir = TempDir::new();
+    fs::write(dir.log_file(), "yesterday\n").unwrap();
+    set_age(&dir.log_file(), DAY);
+    let yesterday = today().pred_opt().unwrap();
+
+    let writer = LogWriter::spawn(&dir.log_file(), RotationPolicy { max_file_bytes: 1024, retention_days: 0 });
+    writer.write_line("today".to_string());
+    writer.flush();
+
+    assert_eq!(log_writer::list_segments(&dir.log_file()), vec![dir.segment(yesterday, 1)]);
+    assert_eq!(gunzip(&dir.segment(yesterday, 1)), "yesterday\n");
+    assert_eq!(fs::read_to_string(dir.log_file()).unwrap(), "today\n");
+}
+
+#[test]
+fn deletes_segments_past_the_retention_age() {
+    let dir = TempDir::new();
+    let expired = dir.segment(today() - chrono::Days::new(40), 1);
+    let kept = dir.segment(today() - chrono::Days::new(2), 1);
+    for (segment, age) in [(&expired, 40 * DAY), (&kept, 2 * DAY)] {
+        fs::write(segment, b"").unwrap();
+        set_age(segment, age);
+    }
+
+    let writer = LogWriter::spawn(&dir.log_file(), RotationPolicy { max_file_bytes: 1024, retention_days: 30 });
+    writer.flush();
+
+    assert!(!expired.exists());
+    assert!(kept.exists());
+}
+
+#[test]
+fn lists_segments_oldest_first() {
+    let dir = TempDir::new();
+    let names = ["workflow.20250519-10.log.gz", "workflow.20250518-2.log.gz", "workflow.20250519-9.log.gz", "workflow.20250519-1.log"];
+    for name in names.iter().chain(["workflow.log", "other.20250519-1.log.gz"].iter()) {
+        fs::write(dir.0.join(name), b"").unwrap();
+    }
+
+    let segments: Vec<String> = log_writer::list_segments(&dir.log_file()).iter()
+        .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(segments, vec!["workflow.20250518-2.log.gz", "workflow.20250519-1.log", "workflow.20250519-9.log.gz", "workflow.20250519-10.log.gz"]);
+}
+
+#[test]
+fn writes_the_queued_lines_when_dropped() {
+    let dir = TempDir::new();
+    let writer = LogWriter::spawn(&dir.log_file(), RotationPolicy::default());
+
+    let lines: Vec<String> = (0..100).map(|n| format!("line {}", n)).collect();
+    for line in &lines {
+        writer.write_line(line.clone());
+    }
+    drop(writer);
+
+    assert_eq!(fs::read_to_string(dir.log_file()).unwrap(), format!("{}\n", lines.join("\n")));
+}
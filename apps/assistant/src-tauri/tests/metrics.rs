@@ -0,0 +1,65 @@
+// Integration test of the metrics endpoint, fed by the records of a workflow
+use std::sync::Arc;
+use std::time::Duration;
+
+use assistant_lib::metrics::{self, Metrics};
+use assistant_lib::workflow_logger::WorkflowTimings;
+use assistant_lib::AssistantError;
+
+async fn scrape(port: u16, path: &str) -> (u16, String) {
+    let response = reqwest::get(format!("http://127.0.0.1:{}{}", port, path)).await.unwrap();
+    (response.status().as_u16(), response.text().await.unwrap())
+}
+
+#[tokio::test]
+async fn serves_the_stages_of_workflows() {
+    let listener = metrics::bind(0).await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let registry = Arc::new(Metrics::new());
+    tokio::spawn(metrics::serve(listener, registry.clone()));
+
+    let timings = WorkflowTimings::with_sink("tts", registry.clone());
+    let mut stage = timings.stage("TTS");
+    stage.set_attribute("cache_hit", false);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    stage.succeed();
+    let error = AssistantError::InvalidInput("Speed must be between 0.25 and 4, got 5".to_string());
+    timings.stage("TTS").fail(&error);
+    timings.finalize_with_error("Failed.", &error);
+
+    let (status, body) = scrape(port, "/metrics").await;
+    assert_eq!(status, 200);
+    for line in [
+        "# TYPE mivis_stage_requests_total counter",
+        "mivis_stage_requests_total{stage=\"TTS\",status=\"ok\"} 1",
+        "mivis_stage_requests_total{stage=\"TTS\",status=\"error\"} 1",
+        // The slow stage is only in the buckets from 0.1s up
+        "mivis_stage_duration_seconds_bucket{stage=\"TTS\",le=\"0.05\"} 1",
+        "mivis_stage_duration_seconds_bucket{stage=\"TTS\",le=\"0.1\"} 2",
+        "mivis_stage_duration_seconds_bucket{stage=\"TTS\",le=\"+Inf\"} 2",
+        "mivis_stage_duration_seconds_count{stage=\"TTS\"} 2",
+        // The kind comes from the error, not from its message
+        "mivis_stage_errors_total{stage=\"TTS\",kind=\"invalid_input\"} 1",
+        "mivis_workflows_total{workflow=\"tts\",status=\"error\"} 1",
+        "mivis_workflow_duration_seconds_count{workflow=\"tts\"} 1",
+        "mivis_tts_cache_lookups_total{result=\"miss\"} 1",
+    ] {
+        assert!(body.lines().any(|l| l == line), "{:?} missing from:\n{}", line, body);
+    }
+
+    assert_eq!(scrape(port, "/").await.0, 404);
+}
+
+#[tokio::test]
+async fn counts_abandoned_stages_as_cancelled() {
+    let registry = Arc::new(Metrics::new());
+    let timings = WorkflowTimings::with_sink("chat", registry.clone());
+    drop(timings.stage("LLM"));
+    timings.stage("LLM").fail("upstream said no".to_string());
+    drop(timings);
+
+    let body = registry.render();
+    assert!(body.contains("mivis_stage_errors_total{stage=\"LLM\",kind=\"cancelled\"} 1"), "{}", body);
+    assert!(body.contains("mivis_stage_errors_total{stage=\"LLM\",kind=\"other\"} 1"), "{}", body);
+    assert!(body.contains("mivis_workflows_total{workflow=\"chat\",status=\"error\"} 1"), "{}", body);
+}
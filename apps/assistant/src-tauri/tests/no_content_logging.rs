@@ -0,0 +1,39 @@
+// Integration test of the no-content logging mode on the app log
+mod support;
+
+use serde_json::json;
+use support::*;
+
+// Files of the app log are named mivis.<date>.log
+const APP_LOG_FILE_PREFIX: &str = "mivis.";
+const UPSTREAM_BODY: &str = "Người dùng hỏi về bệnh án của mình";
+
+#[tokio::test]
+async fn the_app_log_keeps_only_timings() {
+    let server = MockServer::start(LLM_PATH, vec![api_error(500, UPSTREAM_BODY); 3]).await;
+    let app = TestApp::with_llm(&server.url(), json!({
+        "logging": { "level": "debug" },
+        "privacy": { "no_content_logging": true }
+    }));
+
+    app.chat(user_request("Chào")).await.unwrap_err();
+
+    // Dropping the guard writes the queued lines
+    let services = &app.services;
+    drop(services.log_guard.as_ref().unwrap().0.lock().unwrap().take());
+    let mut log = String::new();
+    for entry in std::fs::read_dir(&services.log_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.file_name().unwrap().to_string_lossy().starts_with(APP_LOG_FILE_PREFIX) {
+            log.push_str(&std::fs::read_to_string(path).unwrap());
+        }
+    }
+
+    // The retries, the stage and the workflow were logged with their timings only
+    assert!(log.contains("duration_us="), "{}", log);
+    assert!(log.contains("total_ms="), "{}", log);
+    assert!(log.contains("[content omitted]"), "{}", log);
+    for text in [UPSTREAM_BODY, "LLM API", "retrying", "failed"] {
+        assert!(!log.contains(text), "{:?} in the app log:\n{}", text, log);
+    }
+}
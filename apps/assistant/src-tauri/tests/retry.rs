@@ -0,0 +1,165 @@
+// Integration tests of the retry policy shared by the STT, LLM and TTS requests
+mod support;
+
+use std::time::{Duration, Instant};
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::pipeline::{self, SpeechRequest};
+use assistant_lib::retry::RetryPolicy;
+use assistant_lib::{AssistantError, CancellationToken};
+use serde_json::json;
+use support::*;
+
+#[test]
+fn backoff_grows_exponentially_with_jitter_up_to_the_cap() {
+    let policy = RetryPolicy {
+        max_attempts: 5,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(300),
+        deadline: None,
+        max_retry_after: Duration::from_secs(60),
+    };
+    for _ in 0..20 {
+        let first = policy.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100), "{:?}", first);
+        let second = policy.backoff(2);
+        assert!(second >= Duration::from_millis(100) && second <= Duration::from_millis(200), "{:?}", second);
+        let capped = policy.backoff(10);
+        assert!(capped >= Duration::from_millis(150) && capped <= Duration::from_millis(300), "{:?}", capped);
+    }
+}
+
+#[tokio::test]
+async fn retries_server_errors_until_one_succeeds() {
+    let server = MockServer::start(LLM_PATH, vec![
+        api_error(503, "Overloaded"),
+        api_error(502, "Bad gateway"),
+        chat_completion("Lần ba"),
+    ]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+
+    assert_eq!(app.chat(user_request("Chào")).await.unwrap().content, "Lần ba");
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn honours_retry_after() {
+    let server = MockServer::start(LLM_PATH, vec![
+        api_error(429, "Too many requests").with_header("Retry-After", "1"),
+        chat_completion("Đã chờ"),
+    ]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+
+    let started = Instant::now();
+    assert_eq!(app.chat(user_request("Chào")).await.unwrap().content, "Đã chờ");
+    assert!(started.elapsed() >= Duration::from_secs(1), "{:?}", started.elapsed());
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn does_not_retry_client_errors() {
+    for status in [400, 401, 422] {
+        let server = MockServer::start(LLM_PATH, vec![api_error(status, "No"), chat_completion("Không tới")]).await;
+        let app = TestApp::with_llm(&server.url(), json!({}));
+
+        assert!(app.chat(user_request("Chào")).await.is_err());
+        assert_eq!(server.requests().len(), 1, "status {}", status);
+    }
+}
+
+#[tokio::test]
+async fn retries_request_timeouts() {
+    let server = MockServer::start(LLM_PATH, vec![api_error(408, "Request timeout"), chat_completion("Được")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+
+    assert_eq!(app.chat(user_request("Chào")).await.unwrap().content, "Được");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn returns_the_last_error_once_the_attempts_run_out() {
+    let server = MockServer::start(STT_PATH, vec![stt_error(500, "model not loaded")]).await;
+    let app = TestApp::start(json!({ "stt": { "url": server.url(), "retry": { "max_attempts": 4, "initial_backoff_ms": 10, "max_backoff_ms": 10 } } }));
+
+    let services = &app.services;
+    let recording = wav_bytes(16_000, &[0; 1600]);
+    let error = pipeline::transcribe(recording, &services.config.stt, &services.http.stt, services.workflow_log.clone(), &services.redactor, &RecordingEventSink::new(), &CancellationToken::new())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, AssistantError::Upstream { status: 500, .. }), "{}", error);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn stops_at_the_deadline() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Quá muộn").delayed(Duration::from_secs(5))]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "retry": { "deadline_secs": 1 } } }));
+
+    let started = Instant::now();
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Timeout { service, after } if service == "LLM API" && *after == Duration::from_secs(1)), "{}", error);
+    assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn gives_up_when_retry_after_passes_the_deadline() {
+    let server = MockServer::start(LLM_PATH, vec![api_error(429, "Slow down").with_header("Retry-After", "30")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::RateLimited { .. }), "{}", error);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn caps_the_retry_after_wait() {
+    const HUGE: &str = "18446744073709551615";
+
+    // Even capped, the wait passes the deadline, so the call gives up at once
+    let server = MockServer::start(LLM_PATH, vec![api_error(429, "Slow down").with_header("Retry-After", HUGE)]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+    let error = app.chat(user_request("Chào")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::RateLimited { .. }), "{}", error);
+
+    // Without a deadline the call waits for the cap instead of forever
+    let server = MockServer::start(LLM_PATH, vec![
+        api_error(429, "Slow down").with_header("Retry-After", HUGE),
+        chat_completion("Đã chờ"),
+    ]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "llm": { "retry": { "max_attempts": 2, "deadline_secs": 0, "max_retry_after_secs": 1 } } }));
+    let started = Instant::now();
+    let reply = tokio::time::timeout(Duration::from_secs(5), app.chat(user_request("Chào"))).await.unwrap();
+    assert_eq!(reply.unwrap().content, "Đã chờ");
+    assert!(started.elapsed() >= Duration::from_secs(1), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn cancelling_stops_the_request_and_the_retries() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Không bao giờ").delayed(Duration::from_secs(5))]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+    let cancel = CancellationToken::new();
+
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        canceller.cancel();
+    });
+    let started = Instant::now();
+    let error = app.chat_with(user_request("Chào"), &RecordingEventSink::new(), &cancel).await.unwrap_err();
+    assert!(matches!(error, AssistantError::Cancelled), "{}", error);
+    assert!(started.elapsed() < Duration::from_secs(1), "{:?}", started.elapsed());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn retries_dropped_tts_connections() {
+    let audio = test_tone();
+    let server = MockServer::start(TTS_PATH, vec![Reply::Hangup, speech(audio.clone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    let services = &app.services;
+    let request = SpeechRequest { text: "Thử lại".to_string(), ..Default::default() };
+    let result = pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &RecordingEventSink::new(), &CancellationToken::new()).await;
+    assert_eq!(result.unwrap(), audio);
+    assert_eq!(server.requests().len(), 2);
+}
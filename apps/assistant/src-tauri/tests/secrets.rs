@@ -0,0 +1,137 @@
+// Integration tests of the encrypted secret store and its use by the LLM and TTS requests
+mod support;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::pipeline::{self, SpeechRequest};
+use assistant_lib::secrets::{SecretStore, KEY_FILE_NAME, SECRETS_FILE_NAME};
+use assistant_lib::{AssistantError, CancellationToken};
+use serde_json::json;
+use support::*;
+
+fn passphrase_app() -> TestApp {
+    TestApp::start(json!({ "secrets": { "key_source": "passphrase", "passphrase_env": "MIVIS_TEST_UNSET_PASSPHRASE" } }))
+}
+
+#[tokio::test]
+async fn stores_secrets_encrypted() {
+    let app = TestApp::start(json!({}));
+    let secrets = &app.services.secrets;
+
+    secrets.set("XAI_API_KEY", "xai-very-secret-value").unwrap();
+    secrets.set("TAVILY_API_KEY", "tvly-another-value").unwrap();
+    assert_eq!(secrets.names().unwrap(), vec!["TAVILY_API_KEY", "XAI_API_KEY"]);
+
+    // The file does not contain the values, and the store reads them back after a restart
+    let data_dir = app.root.join("data");
+    let contents = std::fs::read_to_string(data_dir.join(SECRETS_FILE_NAME)).unwrap();
+    assert!(!contents.contains("xai-very-secret-value") && !contents.contains("XAI_API_KEY"));
+    let reopened = SecretStore::open(&data_dir, &app.services.config.secrets).unwrap();
+    assert_eq!(reopened.get("XAI_API_KEY").as_deref(), Some("xai-very-secret-value"));
+
+    assert!(reopened.delete("XAI_API_KEY").unwrap());
+    assert!(!reopened.delete("XAI_API_KEY").unwrap());
+    assert_eq!(reopened.names().unwrap(), vec!["TAVILY_API_KEY"]);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(data_dir.join(KEY_FILE_NAME)).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
+
+#[tokio::test]
+async fn sends_the_stored_api_key() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Có khóa")]).await;
+    let app = TestApp::start(json!({ "llm": { "url": server.url(), "api_key_env": "MIVIS_TEST_STORED_API_KEY" } }));
+    let services = &app.services;
+    services.secrets.set("MIVIS_TEST_STORED_API_KEY", "stored-key").unwrap();
+
+    let reply = app.chat(user_request("Chào")).await;
+    assert_eq!(reply.unwrap().content, "Có khóa");
+    assert_eq!(server.requests()[0].header("authorization"), Some("Bearer stored-key"));
+}
+
+#[tokio::test]
+async fn tts_reads_keys_stored_after_startup() {
+    let server = MockServer::start(TTS_PATH, vec![speech(test_tone())]).await;
+    let app = TestApp::start(json!({ "tts": { "backends": [{
+        "type": "openai_compatible", "url": server.url(), "model": "tts-1", "default_voice": "alloy",
+        "api_key_env": "MIVIS_TEST_TTS_STORED_KEY"
+    }] } }));
+    let services = &app.services;
+
+    // Without the key the request is not sent
+    let request = SpeechRequest { text: "Có khóa".to_string(), ..Default::default() };
+    let (events, cancel) = (RecordingEventSink::new(), CancellationToken::new());
+    let synthesize = |request| pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &events, &cancel);
+    assert!(synthesize(request.clone()).await.is_err());
+    assert!(server.requests().is_empty());
+
+    // The chain is already built, and still picks up the new key
+    services.secrets.set("MIVIS_TEST_TTS_STORED_KEY", "stored-tts-key").unwrap();
+    synthesize(request).await.unwrap();
+    assert_eq!(server.requests()[0].header("authorization"), Some("Bearer stored-tts-key"));
+}
+
+#[tokio::test]
+async fn falls_back_to_the_environment() {
+    let app = TestApp::start(json!({}));
+    std::env::set_var("MIVIS_TEST_ENV_ONLY_SECRET", "from-env");
+
+    assert_eq!(app.services.secrets.get("MIVIS_TEST_ENV_ONLY_SECRET").as_deref(), Some("from-env"));
+    // Only stored secrets are listed
+    assert!(app.services.secrets.names().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn moves_configured_keys_from_the_env_file() {
+    let app = TestApp::start(json!({ "secrets": { "import_from_env_file": ["MIVIS_TEST_IMPORTED_KEY"] } }));
+    let env_file = app.root.join("test.env");
+    std::fs::write(&env_file, "MIVIS_TEST_IMPORTED_KEY=from-dotenv\nMIVIS_TEST_NOT_A_SECRET=ignored\n").unwrap();
+
+    app.services.import_env_file(&env_file);
+    let secrets = &app.services.secrets;
+    assert_eq!(secrets.names().unwrap(), vec!["MIVIS_TEST_IMPORTED_KEY"]);
+    assert_eq!(secrets.get("MIVIS_TEST_IMPORTED_KEY").as_deref(), Some("from-dotenv"));
+
+    // A value set in the store is not overwritten by a later import
+    secrets.set("MIVIS_TEST_IMPORTED_KEY", "from-settings").unwrap();
+    let imported = secrets.import_env_file(&env_file, &["MIVIS_TEST_IMPORTED_KEY".to_string()]).unwrap();
+    assert!(imported.is_empty());
+    assert_eq!(secrets.get("MIVIS_TEST_IMPORTED_KEY").as_deref(), Some("from-settings"));
+}
+
+#[tokio::test]
+async fn keeps_a_passphrase_store_locked_until_unlocked() {
+    let app = passphrase_app();
+    let secrets = &app.services.secrets;
+
+    assert!(secrets.is_locked());
+    assert!(matches!(secrets.set("XAI_API_KEY", "value"), Err(AssistantError::Auth { .. })));
+    assert!(secrets.names().is_err());
+
+    // The first unlock sets the passphrase
+    secrets.unlock("đúng mật khẩu").unwrap();
+    secrets.set("XAI_API_KEY", "passphrase-protected").unwrap();
+
+    let reopened = SecretStore::open(&app.root.join("data"), &app.services.config.secrets).unwrap();
+    assert!(reopened.is_locked());
+    let error = reopened.unlock("sai mật khẩu").unwrap_err();
+    assert!(matches!(&error, AssistantError::Auth { reason, .. } if reason.starts_with("wrong passphrase")), "{}", error);
+    reopened.unlock("đúng mật khẩu").unwrap();
+    assert_eq!(reopened.get("XAI_API_KEY").as_deref(), Some("passphrase-protected"));
+    assert!(!app.root.join("data").join(KEY_FILE_NAME).exists());
+}
+
+#[tokio::test]
+async fn validates_secret_names_and_values() {
+    let app = TestApp::start(json!({}));
+    let secrets = &app.services.secrets;
+
+    for name in ["", "has space", "1_STARTS_WITH_DIGIT", "../escape"] {
+        assert!(matches!(secrets.set(name, "value"), Err(AssistantError::InvalidInput(_))), "{:?}", name);
+    }
+    assert!(matches!(secrets.set("EMPTY_VALUE", ""), Err(AssistantError::InvalidInput(_))));
+    assert!(matches!(secrets.unlock("x"), Err(AssistantError::InvalidInput(_))));
+}
@@ -0,0 +1,103 @@
+// Integration tests of `invoke_stt_transcription` (pipeline::transcribe) against a mock STT service
+mod support;
+
+use std::time::Duration;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::{AssistantError, CancellationToken};
+use assistant_lib::pipeline;
+use assistant_lib::workflow_logger::{RecordStatus, WorkflowEventType};
+use serde_json::json;
+use support::*;
+
+fn stt_app(url: &str) -> TestApp {
+    TestApp::start(json!({ "stt": { "url": url } }))
+}
+
+async fn transcribe(app: &TestApp, events: &RecordingEventSink) -> Result<String, AssistantError> {
+    let services = &app.services;
+    let recording = wav_bytes(16_000, &[0; 1600]);
+    pipeline::transcribe(recording, &services.config.stt, &services.http.stt, services.workflow_log.clone(), &services.redactor, events, &CancellationToken::new()).await
+}
+
+#[tokio::test]
+async fn returns_the_transcription() {
+    let server = MockServer::start(STT_PATH, vec![transcription("xin chào")]).await;
+    let app = stt_app(&server.url());
+    let events = RecordingEventSink::new();
+
+    assert_eq!(transcribe(&app, &events).await.unwrap(), "xin chào");
+
+    // The recording is uploaded as the multipart field `audio`
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "POST");
+    assert!(requests[0].header("content-type").unwrap().starts_with("multipart/form-data"));
+    let body = String::from_utf8_lossy(&requests[0].body);
+    assert!(body.contains("name=\"audio\"") && body.contains("filename=\"audio.wav\""));
+
+    assert_eq!(events.stages(), vec!["TRANSCRIBING"]);
+
+    let records = app.workflow_records();
+    let stage = records.iter().find(|r| r.stage.as_deref() == Some("STT")).expect("no STT stage record");
+    assert_eq!(stage.status, Some(RecordStatus::Ok));
+    assert_eq!(stage.attributes["audio_bytes"], json!(3244));
+}
+
+#[tokio::test]
+async fn waits_for_a_slow_service() {
+    let server = MockServer::start(STT_PATH, vec![transcription("chậm").delayed(Duration::from_millis(300))]).await;
+    let app = stt_app(&server.url());
+
+    assert_eq!(transcribe(&app, &RecordingEventSink::new()).await.unwrap(), "chậm");
+}
+
+#[tokio::test]
+async fn reports_error_statuses_with_the_body() {
+    let server = MockServer::start(STT_PATH, vec![stt_error(500, "model not loaded")]).await;
+    let app = stt_app(&server.url());
+
+    let error = transcribe(&app, &RecordingEventSink::new()).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Upstream { status: 500, body, .. } if body.contains("model not loaded")), "{}", error);
+
+    // The failure ends the workflow with an error record
+    let records = app.workflow_records();
+    let end = records.iter().find(|r| r.event_type == WorkflowEventType::End).expect("no END record");
+    assert_eq!(end.status, Some(RecordStatus::Error));
+}
+
+#[tokio::test]
+async fn rejects_malformed_json() {
+    let server = MockServer::start(STT_PATH, vec![Reply::malformed_json()]).await;
+    let app = stt_app(&server.url());
+
+    let error = transcribe(&app, &RecordingEventSink::new()).await.unwrap_err();
+    assert!(matches!(error, AssistantError::InvalidResponse { .. }), "{}", error);
+    assert!(error.to_string().contains("Failed to parse JSON"), "{}", error);
+}
+
+#[tokio::test]
+async fn rejects_a_response_without_transcription() {
+    let server = MockServer::start(STT_PATH, vec![Reply::json(200, json!({ "text": "wrong field" }))]).await;
+    let app = stt_app(&server.url());
+
+    let error = transcribe(&app, &RecordingEventSink::new()).await.unwrap_err();
+    assert_eq!(error.to_string(), "STT service returned an invalid response: Transcription field not found in response");
+}
+
+#[tokio::test]
+async fn reports_an_unreachable_service() {
+    let app = stt_app(&unreachable_url(STT_PATH).await);
+
+    let error = transcribe(&app, &RecordingEventSink::new()).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::ServiceUnavailable { service, .. } if service == "STT service"), "{}", error);
+}
+
+#[tokio::test]
+async fn reports_a_dropped_connection() {
+    let server = MockServer::start(STT_PATH, vec![Reply::Hangup]).await;
+    let app = stt_app(&server.url());
+
+    let error = transcribe(&app, &RecordingEventSink::new()).await.unwrap_err();
+    assert!(matches!(error, AssistantError::ServiceUnavailable { .. }), "{}", error);
+}
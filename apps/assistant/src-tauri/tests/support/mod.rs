@@ -0,0 +1,494 @@
+// Test support: in-process mock servers for the HTTP services the backend talks to, and a
+// `TestApp` that starts the pipeline services against them.
+//
+// Each mock imitates the contract of the real service closely enough for the backend:
+// * STT (packages/stt, Flask): POST /transcribe, multipart `audio` -> {"transcription": ...}
+// * VietTTS: POST /v1/audio/speech, OpenAI speech JSON -> audio bytes
+// * xAI: POST /v1/chat/completions, OpenAI chat JSON -> completion JSON or an SSE stream
+#![allow(dead_code)] // Every test binary uses a different part of this module
+
+pub mod vcr;
+
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::pipeline::{AppDirs, Services};
+use assistant_lib::{chat, AssistantError, CancellationToken, ChatRequest, ChatResult, Message, MessageContent};
+use assistant_lib::workflow_logger::{self, WorkflowLogRecord};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::dispatcher::DefaultGuard;
+
+pub const STT_PATH: &str = "/transcribe";
+pub const TTS_PATH: &str = "/v1/audio/speech";
+pub const LLM_PATH: &str = "/v1/chat/completions";
+
+/// The variable holding the API key of `TestApp::with_llm` apps.
+pub const LLM_API_KEY_ENV: &str = "MIVIS_TEST_LLM_API_KEY";
+
+/// A request received by a mock server.
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("request body is not JSON")
+    }
+}
+
+/// What a mock server answers.
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// A complete response with a Content-Length and optional extra headers.
+    Full { status: u16, content_type: String, headers: Vec<(String, String)>, body: Vec<u8> },
+    /// A chunked response, written piece by piece with a pause in between.
+    Chunked { status: u16, content_type: String, chunks: Vec<Vec<u8>>, interval: Duration },
+    /// Waits before answering with the inner reply.
+    Delayed(Duration, Box<Reply>),
+    /// Closes the connection without answering.
+    Hangup,
+}
+
+impl Reply {
+    pub fn json(status: u16, body: Value) -> Self {
+        Reply::bytes(status, "application/json", body.to_string().into_bytes())
+    }
+
+    pub fn bytes(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Reply::Full { status, content_type: content_type.to_string(), headers: Vec::new(), body }
+    }
+
+    /// Adds a header to a complete response (e.g. Retry-After).
+    pub fn with_header(self, name: &str, value: &str) -> Self {
+        match self {
+            Reply::Full { status, content_type, mut headers, body } => {
+                headers.push((name.to_string(), value.to_string()));
+                Reply::Full { status, content_type, headers, body }
+            }
+            Reply::Delayed(delay, inner) => Reply::Delayed(delay, Box::new(inner.with_header(name, value))),
+            _ => panic!("with_header only applies to complete responses"),
+        }
+    }
+
+    /// A 200 response that claims to be JSON but cannot be parsed.
+    pub fn malformed_json() -> Self {
+        Reply::bytes(200, "application/json", b"{\"choices\": [{\"message\": ".to_vec())
+    }
+
+    pub fn delayed(self, delay: Duration) -> Self {
+        Reply::Delayed(delay, Box::new(self))
+    }
+}
+
+// ---- Contracts of the real services ----
+
+/// Successful answer of the STT service.
+pub fn transcription(text: &str) -> Reply {
+    Reply::json(200, json!({ "transcription": text }))
+}
+
+/// Error answer of the STT service (Flask returns `{"error": ...}`).
+pub fn stt_error(status: u16, message: &str) -> Reply {
+    Reply::json(status, json!({ "error": message }))
+}
+
+/// Successful answer of a speech endpoint.
+pub fn speech(audio: Vec<u8>) -> Reply {
+    Reply::bytes(200, "audio/wav", audio)
+}
+
+/// Speech streamed in chunks of `chunk_size` bytes, as VietTTS does for long texts.
+pub fn streamed_speech(audio: Vec<u8>, chunk_size: usize) -> Reply {
+    Reply::Chunked {
+        status: 200,
+        content_type: "audio/wav".to_string(),
+        chunks: audio.chunks(chunk_size).map(<[u8]>::to_vec).collect(),
+        interval: Duration::from_millis(10),
+    }
+}
+
+/// Error answer of an OpenAI-compatible API.
+pub fn api_error(status: u16, message: &str) -> Reply {
+    Reply::json(status, json!({ "error": { "message": message, "type": "invalid_request_error" } }))
+}
+
+/// Successful non-streaming chat completion.
+pub fn chat_completion(content: &str) -> Reply {
+    Reply::json(200, json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 1_700_000_000,
+        "model": "grok-3-mini-beta",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop"
+        }],
+        "usage": { "prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17 }
+    }))
+}
+
+/// A chat completion streamed as server-sent events, one chunk per piece of content.
+pub fn chat_completion_stream(pieces: &[&str]) -> Reply {
+    let mut chunks: Vec<Vec<u8>> = pieces.iter()
+        .map(|piece| {
+            let chunk = json!({
+                "id": "chatcmpl-mock",
+                "object": "chat.completion.chunk",
+                "model": "grok-3-mini-beta",
+                "choices": [{ "index": 0, "delta": { "content": piece }, "finish_reason": null }]
+            });
+            format!("data: {}\n\n", chunk).into_bytes()
+        })
+        .collect();
+    chunks.push(b"data: [DONE]\n\n".to_vec());
+    Reply::Chunked { status: 200, content_type: "text/event-stream".to_string(), chunks, interval: Duration::from_millis(10) }
+}
+
+/// A 16-bit mono WAV file of `samples`.
+pub fn wav_bytes(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// One second of a quiet tone at 24 kHz, the format VietTTS answers with.
+pub fn test_tone() -> Vec<u8> {
+    let samples: Vec<i16> = (0..24_000).map(|i| ((i as f32 * 0.05).sin() * 3000.0) as i16).collect();
+    wav_bytes(24_000, &samples)
+}
+
+// ---- The server ----
+
+/// An HTTP server on 127.0.0.1 that answers requests to one path with a scripted sequence
+/// of replies. The last reply is repeated once the sequence is used up; other paths get 404.
+pub struct MockServer {
+    addr: SocketAddr,
+    path: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    task: JoinHandle<()>,
+}
+
+impl MockServer {
+    pub async fn start(path: &str, replies: Vec<Reply>) -> Self {
+        assert!(!replies.is_empty(), "a mock server needs at least one reply");
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind mock server");
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let served_path = Arc::new(path.to_string());
+        let replies = Arc::new(replies);
+        let recorded = requests.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { continue };
+                let (path, replies, recorded) = (served_path.clone(), replies.clone(), recorded.clone());
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, &path, &replies, &recorded).await;
+                });
+            }
+        });
+
+        MockServer { addr, path: path.to_string(), requests, task }
+    }
+
+    /// The URL of the served path, e.g. `http://127.0.0.1:51234/transcribe`.
+    pub fn url(&self) -> String {
+        format!("http://{}{}", self.addr, self.path)
+    }
+
+    /// `http://127.0.0.1:<port>`, for using the server as a proxy.
+    pub fn origin(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The requests received so far, oldest first.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A URL nothing listens on, for connection failures.
+pub async fn unreachable_url(path: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    format!("http://{}{}", addr, path)
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    path: &str,
+    replies: &[Reply],
+    recorded: &Mutex<Vec<RecordedRequest>>,
+) -> std::io::Result<()> {
+    let request = read_request(&mut stream).await?;
+    if request.path != path {
+        return write_full(&mut stream, 404, "text/plain", &[], b"Not Found").await;
+    }
+    // The n-th request gets the n-th reply
+    let reply = {
+        let mut recorded = recorded.lock().unwrap();
+        recorded.push(request);
+        replies[(recorded.len() - 1).min(replies.len() - 1)].clone()
+    };
+    write_reply(&mut stream, reply).await
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<RecordedRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let header = |name: &str| headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, v)| v.clone());
+
+    let mut body = buffer[head_end..].to_vec();
+    if let Some(length) = header("content-length").and_then(|v| v.parse::<usize>().ok()) {
+        while body.len() < length {
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..read]);
+        }
+    } else if header("transfer-encoding").is_some_and(|v| v.eq_ignore_ascii_case("chunked")) {
+        while !body.ends_with(b"0\r\n\r\n") {
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..read]);
+        }
+        body = decode_chunked(&body);
+    }
+
+    Ok(RecordedRequest { method, path, headers, body })
+}
+
+fn decode_chunked(mut raw: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    while let Some(line_end) = raw.windows(2).position(|w| w == b"\r\n") {
+        let size = usize::from_str_radix(String::from_utf8_lossy(&raw[..line_end]).trim(), 16).unwrap_or(0);
+        if size == 0 || raw.len() < line_end + 2 + size {
+            break;
+        }
+        body.extend_from_slice(&raw[line_end + 2..line_end + 2 + size]);
+        raw = &raw[(line_end + 4 + size).min(raw.len())..];
+    }
+    body
+}
+
+async fn write_reply(stream: &mut TcpStream, reply: Reply) -> std::io::Result<()> {
+    let mut reply = reply;
+    while let Reply::Delayed(delay, inner) = reply {
+        tokio::time::sleep(delay).await;
+        reply = *inner;
+    }
+
+    match reply {
+        Reply::Full { status, content_type, headers, body } => write_full(stream, status, &content_type, &headers, &body).await,
+        Reply::Chunked { status, content_type, chunks, interval } => {
+            let head = format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n",
+                status, reason(status), content_type
+            );
+            stream.write_all(head.as_bytes()).await?;
+            for chunk in chunks {
+                stream.write_all(format!("{:x}\r\n", chunk.len()).as_bytes()).await?;
+                stream.write_all(&chunk).await?;
+                stream.write_all(b"\r\n").await?;
+                stream.flush().await?;
+                tokio::time::sleep(interval).await;
+            }
+            stream.write_all(b"0\r\n\r\n").await?;
+            stream.shutdown().await
+        }
+        Reply::Hangup => stream.shutdown().await,
+        Reply::Delayed(..) => unreachable!(),
+    }
+}
+
+async fn write_full(stream: &mut TcpStream, status: u16, content_type: &str, headers: &[(String, String)], body: &[u8]) -> std::io::Result<()> {
+    let extra: String = headers.iter().map(|(name, value)| format!("{}: {}\r\n", name, value)).collect();
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status, reason(status), content_type, body.len(), extra
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        408 => "Request Timeout",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+// ---- The app ----
+
+/// The pipeline services started with their own config, cache and log directories.
+/// The directories are deleted when the app is dropped.
+pub struct TestApp {
+    pub services: Services,
+    pub root: PathBuf,
+    // Routes the logs of the test's thread to this app's subscriber
+    _tracing: Option<DefaultGuard>,
+}
+
+impl TestApp {
+    /// Starts the services with `config` as config.json. The app log is turned off
+    /// unless the config sets a level, so test output stays readable, and the providers
+    /// retry with millisecond backoffs unless the config sets their retries. Tracing on
+    /// the test's thread goes to the app's own subscriber while the app lives.
+    pub fn start(mut config: Value) -> Self {
+        let root = std::env::temp_dir().join(format!("mivis-test-{}", uuid::Uuid::new_v4()));
+        let config_dir = root.join("config");
+        std::fs::create_dir_all(&config_dir).unwrap();
+
+        if config.pointer("/logging/level").is_none() {
+            config["logging"]["level"] = json!("off");
+        }
+        for service in ["stt", "llm", "tts"] {
+            if config[service].get("retry").is_none() {
+                config[service]["retry"] = fast_retry();
+            }
+        }
+        std::fs::write(config_dir.join("config.json"), config.to_string()).unwrap();
+
+        let services = Services::start(&AppDirs {
+            config_dir: Some(config_dir),
+            cache_dir: Some(root.join("cache")),
+            log_dir: Some(root.join("logs")),
+            data_dir: Some(root.join("data")),
+        });
+        let _tracing = services.tracing.as_ref().map(tracing::dispatcher::set_default);
+        TestApp { services, root, _tracing }
+    }
+
+    /// Starts the services with the LLM at `url`, whose key is in `LLM_API_KEY_ENV`.
+    /// `config` adds sections, or fields of the `llm` section.
+    pub fn with_llm(url: &str, mut config: Value) -> Self {
+        std::env::set_var(LLM_API_KEY_ENV, "test-key");
+        config["llm"]["url"] = json!(url);
+        if config["llm"].get("api_key_env").is_none() {
+            config["llm"]["api_key_env"] = json!(LLM_API_KEY_ENV);
+        }
+        Self::start(config)
+    }
+
+    /// Sends `request` to the LLM of the app.
+    pub async fn chat(&self, request: ChatRequest) -> Result<ChatResult, AssistantError> {
+        self.chat_with(request, &RecordingEventSink::new(), &CancellationToken::new()).await
+    }
+
+    /// `chat`, with the events and cancellation of the caller.
+    pub async fn chat_with(&self, request: ChatRequest, events: &RecordingEventSink, cancel: &CancellationToken) -> Result<ChatResult, AssistantError> {
+        chat(request, self.services.chat_context(events, cancel)).await
+    }
+
+    /// Writes the queued workflow log records to disk.
+    pub fn flush_logs(&self) {
+        self.services.workflow_log.flush();
+    }
+
+    /// The records in the workflow log so far.
+    pub fn workflow_records(&self) -> Vec<WorkflowLogRecord> {
+        self.flush_logs();
+        workflow_logger::read_log_history(&self.services.log_dir.join(workflow_logger::WORKFLOW_LOG_FILE_NAME)).unwrap()
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// A request with one user message.
+pub fn user_request(content: impl Into<MessageContent>) -> ChatRequest {
+    ChatRequest::new(vec![Message { role: "user".to_string(), content: content.into(), source: None }])
+}
+
+/// The reason of an `InvalidInput` error; panics on any other result.
+pub fn invalid_input<T: std::fmt::Debug>(result: Result<T, AssistantError>) -> String {
+    match result {
+        Err(AssistantError::InvalidInput(reason)) => reason,
+        other => panic!("expected invalid input, got {:?}", other),
+    }
+}
+
+/// Retries that keep failing tests fast: 3 attempts, 10-50 ms apart, 5 s in total.
+pub fn fast_retry() -> Value {
+    json!({ "max_attempts": 3, "initial_backoff_ms": 10, "max_backoff_ms": 50, "deadline_secs": 5 })
+}
+
+/// A config that sends TTS to `tts_url` only.
+pub fn tts_config(tts_url: &str) -> Value {
+    json!({ "tts": { "backends": [{ "type": "viettts", "url": tts_url, "default_voice": "diep-chi" }], "timeout_secs": 2 } })
+}
@@ -0,0 +1,381 @@
+// Record/replay ("VCR") of provider HTTP traffic.
+//
+// A `VcrServer` stands in for one upstream service; tests point the configured service URL
+// at it, so it covers every HTTP client in the backend.
+// * Replay (the default) serves the interactions of a cassette file in tests/fixtures/vcr
+//   without touching the network. Requests are matched on method, path and JSON body.
+// * Record (`MIVIS_VCR=record`) forwards every request to the real service and saves the
+//   request/response pairs to the cassette when the server is dropped. Secrets (auth
+//   headers, API keys, the values of the secret environment variables) are scrubbed first.
+//
+// Re-record a cassette against the real services with e.g.
+//   MIVIS_VCR=record XAI_API_KEY=... cargo test --test replay
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use base64::Engine;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use super::{read_request, write_full, RecordedRequest};
+
+/// Replaces every scrubbed secret in a cassette.
+pub const SCRUBBED: &str = "[SCRUBBED]";
+
+// Environment variables whose values must never end up in a fixture
+const SECRET_ENV_VARS: [&str; 3] = ["XAI_API_KEY", "TAVILY_API_KEY", "MCP_SERVER_URL"];
+// Request headers that carry credentials; they are kept with a scrubbed value
+const SECRET_HEADERS: [&str; 4] = ["authorization", "x-api-key", "api-key", "cookie"];
+// Headers worth keeping in a cassette besides the secret ones
+const KEPT_HEADERS: [&str; 3] = ["content-type", "retry-after", "x-request-id"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VcrMode {
+    Record,
+    Replay,
+}
+
+impl VcrMode {
+    /// `MIVIS_VCR=record` records, anything else replays.
+    pub fn from_env() -> Self {
+        match std::env::var("MIVIS_VCR").as_deref() {
+            Ok("record") => VcrMode::Record,
+            _ => VcrMode::Replay,
+        }
+    }
+}
+
+/// The recorded traffic of one test.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Interaction {
+    pub request: RecordedMessage,
+    pub response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedMessage {
+    pub method: String,
+    pub path: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Body,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Body,
+}
+
+/// A message body as stored in a cassette.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+    #[default]
+    Empty,
+    Json(Value),
+    Text(String),
+    Base64(String),
+    /// Uploads such as multipart recordings are not stored, only their size.
+    Omitted { bytes: usize },
+}
+
+impl Body {
+    fn from_bytes(content_type: Option<&str>, bytes: &[u8], keep_binary: bool) -> Self {
+        if bytes.is_empty() {
+            return Body::Empty;
+        }
+        let content_type = content_type.unwrap_or_default();
+        if content_type.contains("json") {
+            if let Ok(value) = serde_json::from_slice(bytes) {
+                return Body::Json(value);
+            }
+        }
+        if content_type.starts_with("text/") || content_type.contains("json") {
+            if let Ok(text) = std::str::from_utf8(bytes) {
+                return Body::Text(text.to_string());
+            }
+        }
+        if keep_binary {
+            Body::Base64(base64::engine::general_purpose::STANDARD.encode(bytes))
+        } else {
+            Body::Omitted { bytes: bytes.len() }
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Body::Empty | Body::Omitted { .. } => Vec::new(),
+            Body::Json(value) => value.to_string().into_bytes(),
+            Body::Text(text) => text.clone().into_bytes(),
+            Body::Base64(data) => base64::engine::general_purpose::STANDARD.decode(data).expect("invalid base64 in cassette"),
+        }
+    }
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Self {
+        let contents = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Missing cassette {} ({}), record it with MIVIS_VCR=record", path.display(), e));
+        serde_json::from_str(&contents).unwrap_or_else(|e| panic!("Invalid cassette {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap() + "\n").unwrap();
+    }
+}
+
+/// The cassette file of `name` in tests/fixtures/vcr.
+pub fn cassette_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("vcr").join(format!("{}.json", name))
+}
+
+/// The environment variable holding the API key: the real one when recording, and a
+/// variable set to a dummy key when replaying (the cassette does not contain the key).
+pub fn api_key_env(real: &str) -> String {
+    match VcrMode::from_env() {
+        VcrMode::Record => real.to_string(),
+        VcrMode::Replay => {
+            let replay_env = format!("MIVIS_VCR_REPLAY_{}", real);
+            std::env::set_var(&replay_env, "replay-key");
+            replay_env
+        }
+    }
+}
+
+struct VcrState {
+    mode: VcrMode,
+    upstream: String,
+    cassette: Cassette,
+    replayed: Vec<bool>, // Which interactions have been served
+    client: reqwest::Client,
+}
+
+/// Stands in for one upstream service, see the module comment.
+pub struct VcrServer {
+    addr: SocketAddr,
+    cassette_path: PathBuf,
+    state: Arc<Mutex<VcrState>>,
+    task: JoinHandle<()>,
+}
+
+impl VcrServer {
+    /// Serves the cassette `name` in the mode selected by `MIVIS_VCR`.
+    ///
+    /// # Arguments
+    /// * `name` - File name of the cassette in tests/fixtures/vcr, without `.json`.
+    /// * `upstream` - Base URL of the real service (scheme and host), used when recording.
+    pub async fn start(name: &str, upstream: &str) -> Self {
+        VcrServer::with_mode(cassette_path(name), upstream, VcrMode::from_env()).await
+    }
+
+    pub async fn with_mode(cassette_path: PathBuf, upstream: &str, mode: VcrMode) -> Self {
+        let cassette = match mode {
+            VcrMode::Replay => Cassette::load(&cassette_path),
+            VcrMode::Record => Cassette::default(),
+        };
+        let state = Arc::new(Mutex::new(VcrState {
+            mode,
+            upstream: upstream.trim_end_matches('/').to_string(),
+            replayed: vec![false; cassette.interactions.len()],
+            cassette,
+            client: reqwest::Client::new(),
+        }));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind VCR server");
+        let addr = listener.local_addr().unwrap();
+        let served_state = state.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else { continue };
+                let state = served_state.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(stream, &state).await;
+                });
+            }
+        });
+
+        VcrServer { addr, cassette_path, state, task }
+    }
+
+    /// The URL of `path` on this server, e.g. `http://127.0.0.1:51234/v1/chat/completions`.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// How many recorded interactions have not been replayed (always 0 when recording).
+    pub fn unused_interactions(&self) -> usize {
+        self.state.lock().unwrap().replayed.iter().filter(|served| !**served).count()
+    }
+}
+
+impl Drop for VcrServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let state = self.state.lock().unwrap();
+        if state.mode == VcrMode::Record {
+            state.cassette.save(&self.cassette_path);
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &Mutex<VcrState>) -> std::io::Result<()> {
+    let request = read_request(&mut stream).await?;
+    let mode = state.lock().unwrap().mode;
+    let response = match mode {
+        VcrMode::Replay => replay(&request, state),
+        VcrMode::Record => record(&request, state).await,
+    };
+    let content_type = response.headers.get("content-type").cloned().unwrap_or_else(|| "text/plain".to_string());
+    let headers: Vec<(String, String)> = response.headers.iter()
+        .filter(|(name, _)| name.as_str() != "content-type")
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    write_full(&mut stream, response.status, &content_type, &headers, &response.body.to_bytes()).await
+}
+
+// Serves the first unused interaction that matches the request
+fn replay(request: &RecordedRequest, state: &Mutex<VcrState>) -> RecordedResponse {
+    let recorded = scrub_request(request);
+    let mut state = state.lock().unwrap();
+    let VcrState { cassette, replayed, .. } = &mut *state;
+
+    let matching = cassette.interactions.iter().enumerate()
+        .find(|(index, interaction)| !replayed[*index] && matches(&interaction.request, &recorded));
+    match matching {
+        Some((index, interaction)) => {
+            replayed[index] = true;
+            interaction.response.clone()
+        }
+        None => RecordedResponse {
+            status: 599,
+            headers: BTreeMap::from([("content-type".to_string(), "text/plain".to_string())]),
+            body: Body::Text(format!(
+                "VCR: no recorded interaction for {} {} with this body; re-record the cassette with MIVIS_VCR=record",
+                request.method, request.path
+            )),
+        },
+    }
+}
+
+// Interactions match on method and path, and on the body when it is JSON
+fn matches(recorded: &RecordedMessage, request: &RecordedMessage) -> bool {
+    recorded.method == request.method
+        && recorded.path == request.path
+        && match (&recorded.body, &request.body) {
+            (Body::Json(expected), Body::Json(actual)) => expected == actual,
+            _ => true,
+        }
+}
+
+// Forwards the request to the real service and keeps the scrubbed pair
+async fn record(request: &RecordedRequest, state: &Mutex<VcrState>) -> RecordedResponse {
+    let (client, url) = {
+        let state = state.lock().unwrap();
+        (state.client.clone(), format!("{}{}", state.upstream, request.path))
+    };
+
+    let method = reqwest::Method::from_bytes(request.method.as_bytes()).expect("invalid method");
+    let mut upstream_request = client.request(method, &url).body(request.body.clone());
+    for (name, value) in &request.headers {
+        if !["host", "content-length", "connection", "transfer-encoding"].contains(&name.to_ascii_lowercase().as_str()) {
+            upstream_request = upstream_request.header(name, value);
+        }
+    }
+
+    let response = match upstream_request.send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let headers = kept_headers(response.headers().iter().map(|(name, value)| (name.as_str(), value.to_str().unwrap_or_default())));
+            let bytes = response.bytes().await.map(|b| b.to_vec()).unwrap_or_default();
+            let body = scrub_body(Body::from_bytes(headers.get("content-type").map(String::as_str), &bytes, true));
+            RecordedResponse { status, headers, body }
+        }
+        Err(e) => panic!("VCR: failed to reach {} while recording: {}", url, e),
+    };
+
+    state.lock().unwrap().cassette.interactions.push(Interaction {
+        request: scrub_request(request),
+        response: response.clone(),
+    });
+    response
+}
+
+fn scrub_request(request: &RecordedRequest) -> RecordedMessage {
+    let headers = kept_headers(request.headers.iter().map(|(name, value)| (name.as_str(), value.as_str())));
+    let body = Body::from_bytes(headers.get("content-type").map(String::as_str), &request.body, false);
+    RecordedMessage { method: request.method.clone(), path: request.path.clone(), headers, body: scrub_body(body) }
+}
+
+fn kept_headers<'a>(headers: impl Iterator<Item = (&'a str, &'a str)>) -> BTreeMap<String, String> {
+    headers
+        .filter_map(|(name, value)| {
+            let name = name.to_ascii_lowercase();
+            if SECRET_HEADERS.contains(&name.as_str()) {
+                // Keep the scheme so the fixture still shows how the key was sent
+                let scheme = value.split_whitespace().next().filter(|_| value.contains(' '));
+                Some((name, scheme.map(|s| format!("{} {}", s, SCRUBBED)).unwrap_or_else(|| SCRUBBED.to_string())))
+            } else if KEPT_HEADERS.contains(&name.as_str()) {
+                // Multipart boundaries change on every request
+                let value = if value.starts_with("multipart/") { value.split(';').next().unwrap_or(value) } else { value };
+                Some((name, scrub_text(value)))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn scrub_body(body: Body) -> Body {
+    match body {
+        Body::Json(value) => Body::Json(scrub_json(value)),
+        Body::Text(text) => Body::Text(scrub_text(&text)),
+        other => other,
+    }
+}
+
+fn scrub_json(value: Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(scrub_text(&text)),
+        Value::Array(items) => Value::Array(items.into_iter().map(scrub_json).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter()
+            .map(|(key, value)| {
+                let secret_key = ["api_key", "apikey", "access_token", "secret", "password"].contains(&key.to_ascii_lowercase().as_str());
+                let value = if secret_key { Value::String(SCRUBBED.to_string()) } else { scrub_json(value) };
+                (key, value)
+            })
+            .collect()),
+        other => other,
+    }
+}
+
+/// Masks the values of the secret environment variables and anything shaped like a key.
+pub fn scrub_text(text: &str) -> String {
+    let mut scrubbed = text.to_string();
+    for name in SECRET_ENV_VARS {
+        if let Ok(secret) = std::env::var(name) {
+            if secret.len() >= 8 {
+                scrubbed = scrubbed.replace(&secret, SCRUBBED);
+            }
+        }
+    }
+    let key_pattern = Regex::new(r"\b(?:xai|sk|tvly)-[A-Za-z0-9_\-]{16,}|\bBearer\s+[A-Za-z0-9._~+/=\-]{8,}").unwrap();
+    key_pattern.replace_all(&scrubbed, SCRUBBED).into_owned()
+}
@@ -0,0 +1,222 @@
+// Integration tests of `synthesize_speech`, `get_tts_cache_stats` and `clear_tts_cache`
+// against a mock VietTTS service
+mod support;
+
+use std::time::Duration;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::pipeline::{self, SpeechRequest};
+use assistant_lib::{AssistantError, CancellationToken};
+use serde_json::{json, Value};
+use support::*;
+
+fn request(text: &str) -> SpeechRequest {
+    SpeechRequest { text: text.to_string(), ..Default::default() }
+}
+
+async fn synthesize(app: &TestApp, request: SpeechRequest) -> Result<Vec<u8>, AssistantError> {
+    let services = &app.services;
+    pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &RecordingEventSink::new(), &CancellationToken::new()).await
+}
+
+#[tokio::test]
+async fn returns_the_synthesized_audio() {
+    let audio = test_tone();
+    let server = MockServer::start(TTS_PATH, vec![speech(audio.clone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+    let events = RecordingEventSink::new();
+
+    let services = &app.services;
+    let request = SpeechRequest { voice: Some("nu-nhe-nhang".to_string()), speed: Some(1.25), ..request("Xin chào") };
+    let result = pipeline::synthesize(request, &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &events, &CancellationToken::new()).await;
+    assert_eq!(result.unwrap(), audio);
+
+    // The request follows the OpenAI speech API with VietTTS' fixed token
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].header("authorization"), Some("Bearer viet-tts"));
+    assert_eq!(requests[0].json(), json!({
+        "model": "tts-1",
+        "input": "Xin chào",
+        "voice": "nu-nhe-nhang",
+        "speed": 1.25,
+        "response_format": "wav"
+    }));
+    assert_eq!(events.stages(), vec!["SYNTHESIZING_VOICE"]);
+}
+
+#[tokio::test]
+async fn serves_repeated_phrases_from_the_cache() {
+    let audio = test_tone();
+    let server = MockServer::start(TTS_PATH, vec![speech(audio.clone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    assert_eq!(synthesize(&app, request("Một")).await.unwrap(), audio);
+    assert_eq!(synthesize(&app, request("Một")).await.unwrap(), audio);
+    assert_eq!(server.requests().len(), 1);
+
+    let stats = pipeline::tts_cache_stats(&app.services.tts_cache).await.unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+    // After clearing, the phrase is synthesized again
+    pipeline::clear_tts_cache(&app.services.tts_cache).await.unwrap();
+    assert_eq!(pipeline::tts_cache_stats(&app.services.tts_cache).await.unwrap().entries, 0);
+    synthesize(&app, request("Một")).await.unwrap();
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn reports_a_missing_cache() {
+    let server = MockServer::start(TTS_PATH, vec![speech(test_tone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+    *app.services.tts_cache.lock().unwrap() = None;
+
+    // Synthesis still works, only the cache commands fail
+    synthesize(&app, request("Không cache")).await.unwrap();
+    let error = pipeline::tts_cache_stats(&app.services.tts_cache).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::ServiceUnavailable { service, .. } if service == "TTS cache"), "{}", error);
+    let error = pipeline::clear_tts_cache(&app.services.tts_cache).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::ServiceUnavailable { service, .. } if service == "TTS cache"), "{}", error);
+}
+
+#[tokio::test]
+async fn reads_streamed_audio() {
+    let audio = test_tone();
+    let server = MockServer::start(TTS_PATH, vec![streamed_speech(audio.clone(), 4096)]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    assert_eq!(synthesize(&app, request("Câu dài")).await.unwrap(), audio);
+}
+
+#[tokio::test]
+async fn retries_without_a_rejected_format() {
+    let audio = test_tone();
+    let server = MockServer::start(TTS_PATH, vec![api_error(422, "unsupported response_format"), speech(audio.clone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    assert_eq!(synthesize(&app, request("Định dạng")).await.unwrap(), audio);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[1].json().get("response_format").is_none());
+}
+
+#[tokio::test]
+async fn does_not_retry_other_invalid_requests() {
+    let server = MockServer::start(TTS_PATH, vec![api_error(400, "voice not found"), speech(test_tone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    let error = synthesize(&app, request("Giọng lạ")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Upstream { status: 400, .. }), "{}", error);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn keeps_text_from_being_read_as_an_option() {
+    let backend = |args: Value| json!({ "tts": { "backends": [
+        { "type": "command", "program": "true", "args": args, "default_voice": "vi" }
+    ] } });
+
+    let app = TestApp::start(backend(json!(["-w", "{output}", "{text}"])));
+    let error = synthesize(&app, request("--help")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::InvalidInput(reason) if reason.contains("add \"--\" before")), "{}", error);
+
+    // After `--` the text gets through to the program, which writes no audio here
+    let app = TestApp::start(backend(json!(["-w", "{output}", "--", "{text}"])));
+    let error = synthesize(&app, request("--help")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::Io { .. }), "{}", error);
+}
+
+#[tokio::test]
+async fn deletes_the_output_of_an_abandoned_command() {
+    // The program notes where it writes, starts writing and hangs
+    let scratch = TestApp::start(json!({}));
+    let marker = scratch.root.join("output-path");
+    let script = "echo \"$0\" > \"$1\"; printf RIFF > \"$0\"; sleep 5";
+    let config = |timeout_secs: u64| json!({ "tts": { "backends": [
+        { "type": "command", "program": "sh", "args": ["-c", script, "{output}", marker.to_string_lossy()], "default_voice": "vi" }
+    ], "timeout_secs": timeout_secs } });
+    let output_path = || std::path::PathBuf::from(std::fs::read_to_string(&marker).unwrap().trim());
+
+    // Cancelled by the caller
+    let app = TestApp::start(config(30));
+    let services = &app.services;
+    let cancel = CancellationToken::new();
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        canceller.cancel();
+    });
+    let result = pipeline::synthesize(request("Xin chào"), &services.tts_chain, &services.tts_cache, services.workflow_log.clone(), &RecordingEventSink::new(), &cancel).await;
+    assert!(matches!(result, Err(AssistantError::Cancelled)), "{:?}", result);
+    assert!(!output_path().exists());
+
+    // Dropped by the fallback chain on timeout
+    std::fs::remove_file(&marker).unwrap();
+    let app = TestApp::start(config(1));
+    assert!(synthesize(&app, request("Xin chào")).await.is_err());
+    assert!(!output_path().exists());
+}
+
+#[tokio::test]
+async fn reports_error_statuses() {
+    let server = MockServer::start(TTS_PATH, vec![api_error(500, "CUDA out of memory")]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    // With a single backend its own error is returned
+    let error = synthesize(&app, request("Lỗi")).await.unwrap_err();
+    assert!(matches!(&error, AssistantError::Upstream { service, status: 500, .. } if service == "VietTTS service"), "{}", error);
+}
+
+#[tokio::test]
+async fn times_out_a_slow_backend() {
+    let server = MockServer::start(TTS_PATH, vec![speech(test_tone()).delayed(Duration::from_secs(3))]).await;
+    let mut config = tts_config(&server.url());
+    config["tts"]["timeout_secs"] = json!(1);
+    let app = TestApp::start(config);
+
+    let error = synthesize(&app, request("Chậm")).await.unwrap_err();
+    assert!(matches!(error, AssistantError::Timeout { after, .. } if after == Duration::from_secs(1)), "{}", error);
+}
+
+#[tokio::test]
+async fn falls_back_to_the_next_backend_without_caching() {
+    let server = MockServer::start(TTS_PATH, vec![Reply::Hangup]).await;
+    let app = TestApp::start(json!({ "tts": { "backends": [
+        { "type": "viettts", "url": server.url(), "default_voice": "diep-chi" },
+        { "type": "mock" }
+    ] } }));
+
+    let audio = synthesize(&app, request("Dự phòng")).await.unwrap();
+    assert!(audio.starts_with(b"RIFF"));
+    // Fallback audio is not cached, so the primary backend is asked again next time
+    assert_eq!(pipeline::tts_cache_stats(&app.services.tts_cache).await.unwrap().entries, 0);
+}
+
+#[tokio::test]
+async fn validates_the_request_before_synthesizing() {
+    let server = MockServer::start(TTS_PATH, vec![speech(test_tone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    let error = synthesize(&app, SpeechRequest { speed: Some(5.0), ..request("x") }).await.unwrap_err();
+    assert_eq!(error.to_string(), "Speed must be between 0.25 and 4, got 5");
+    assert!(matches!(error, AssistantError::InvalidInput(_)));
+    let error = synthesize(&app, SpeechRequest { response_format: Some("flac".to_string()), ..request("x") }).await.unwrap_err();
+    assert!(error.to_string().starts_with("Unsupported response_format 'flac'"), "{}", error);
+    let error = synthesize(&app, SpeechRequest { sample_rate: Some(0), ..request("x") }).await.unwrap_err();
+    assert_eq!(error.to_string(), "Sample rate must be between 8000 and 48000 Hz, got 0");
+    // A huge rate would otherwise allocate the resampled audio without limit
+    let error = synthesize(&app, SpeechRequest { sample_rate: Some(4_000_000_000), ..request("x") }).await.unwrap_err();
+    assert!(matches!(error, AssistantError::InvalidInput(_)), "{}", error);
+
+    assert!(server.requests().is_empty());
+}
+
+#[tokio::test]
+async fn resamples_audio_to_the_requested_rate() {
+    let server = MockServer::start(TTS_PATH, vec![speech(test_tone())]).await;
+    let app = TestApp::start(tts_config(&server.url()));
+
+    let audio = synthesize(&app, SpeechRequest { sample_rate: Some(16_000), ..request("Tần số") }).await.unwrap();
+    // Sample rate field of the WAV header
+    assert_eq!(u32::from_le_bytes(audio[24..28].try_into().unwrap()), 16_000);
+}
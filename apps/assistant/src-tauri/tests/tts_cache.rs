@@ -0,0 +1,111 @@
+// Tests of the TTS audio cache: its keys, LRU eviction, statistics and index
+mod support;
+
+use std::path::PathBuf;
+
+use assistant_lib::pipeline;
+use assistant_lib::tts_cache::{TtsCache, TtsCacheKey};
+use serde_json::json;
+use support::*;
+
+fn digest(text: &str) -> String {
+    TtsCacheKey { text, voice: "diep-chi", speed: 1.0, format: "wav", sample_rate: None }.digest()
+}
+
+// A cache directory deleted with the app
+fn cache_dir(app: &TestApp) -> PathBuf {
+    app.root.join("tts-cache-test")
+}
+
+#[test]
+fn evicts_the_least_recently_used_entries_by_size() {
+    let app = TestApp::start(json!({}));
+    let mut cache = TtsCache::open(&cache_dir(&app), 10).unwrap();
+
+    cache.put(&digest("một"), b"1111").unwrap();
+    cache.put(&digest("hai"), b"2222").unwrap();
+    assert!(cache.get(&digest("một")).is_some());
+    // "hai" is now the least recently used entry
+    cache.put(&digest("ba"), b"3333").unwrap();
+    assert!(cache.get(&digest("hai")).is_none());
+
+    // A large entry evicts as many entries as it needs, oldest first
+    cache.put(&digest("bốn"), b"44444444").unwrap();
+    assert!(cache.get(&digest("một")).is_none());
+    assert!(cache.get(&digest("ba")).is_none());
+    assert_eq!(cache.get(&digest("bốn")).unwrap(), b"44444444");
+    assert_eq!(cache.stats().total_bytes, 8);
+
+    // Audio larger than the whole cache is not stored and evicts nothing
+    cache.put(&digest("năm"), b"55555555555").unwrap();
+    assert!(cache.get(&digest("năm")).is_none());
+    assert_eq!(cache.stats().entries, 1);
+}
+
+#[test]
+fn ignores_case_and_whitespace_in_the_text() {
+    assert_eq!(digest("Xin chào bạn"), digest("  XIN   chào\nBạn "));
+    assert_ne!(digest("Xin chào bạn"), digest("Xin chào"));
+
+    // The other settings change the audio, so they are part of the key
+    let key = |voice, speed, format, sample_rate| TtsCacheKey { text: "Xin chào", voice, speed, format, sample_rate }.digest();
+    let base = key("diep-chi", 1.0, "wav", None);
+    assert_eq!(base, key("diep-chi", 1.0, "WAV", None));
+    for other in [key("nu-nhe-nhang", 1.0, "wav", None), key("diep-chi", 1.5, "wav", None), key("diep-chi", 1.0, "mp3", None), key("diep-chi", 1.0, "wav", Some(16_000))] {
+        assert_ne!(base, other);
+    }
+}
+
+#[test]
+fn counts_hits_and_misses() {
+    let app = TestApp::start(json!({}));
+    let mut cache = TtsCache::open(&cache_dir(&app), 1024).unwrap();
+
+    assert!(cache.get(&digest("Chào")).is_none());
+    cache.put(&digest("Chào"), b"audio").unwrap();
+    assert_eq!(cache.get(&digest("chào")).unwrap(), b"audio");
+    assert_eq!(cache.get(&digest("Chào ")).unwrap(), b"audio");
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.entries, stats.total_bytes, stats.max_bytes), (2, 1, 1, 5, 1024));
+    assert!((stats.hit_rate - 2.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn keeps_the_recency_of_hits_across_restarts() {
+    let app = TestApp::start(json!({}));
+    let dir = cache_dir(&app);
+    let mut cache = TtsCache::open(&dir, 10).unwrap();
+    cache.put(&digest("một"), b"1111").unwrap();
+    cache.put(&digest("hai"), b"2222").unwrap();
+    cache.get(&digest("một")).unwrap();
+    // The hit is saved to the index when the cache is dropped
+    drop(cache);
+
+    let mut cache = TtsCache::open(&dir, 10).unwrap();
+    cache.put(&digest("ba"), b"3333").unwrap();
+    assert!(cache.get(&digest("một")).is_some());
+    assert!(cache.get(&digest("hai")).is_none());
+}
+
+#[tokio::test]
+async fn clearing_deletes_the_cached_audio() {
+    let app = TestApp::start(json!({}));
+    let tts_cache = &app.services.tts_cache;
+    {
+        let mut guard = tts_cache.lock().unwrap();
+        let cache = guard.as_mut().unwrap();
+        cache.put(&digest("một"), b"1111").unwrap();
+        cache.get(&digest("một")).unwrap();
+    }
+
+    pipeline::clear_tts_cache(tts_cache).await.unwrap();
+
+    let stats = pipeline::tts_cache_stats(tts_cache).await.unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.entries, stats.total_bytes), (0, 0, 0, 0));
+    assert!(tts_cache.lock().unwrap().as_mut().unwrap().get(&digest("một")).is_none());
+    let audio_files = std::fs::read_dir(app.root.join("cache/tts")).unwrap()
+        .filter(|entry| entry.as_ref().unwrap().path().extension().is_some_and(|ext| ext == "audio"))
+        .count();
+    assert_eq!(audio_files, 0);
+}
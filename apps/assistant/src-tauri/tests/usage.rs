@@ -0,0 +1,165 @@
+// Integration tests of the LLM usage ledger: token accounting, prices and budgets
+mod support;
+
+use assistant_lib::events::RecordingEventSink;
+use assistant_lib::pipeline;
+use assistant_lib::usage::{BudgetPeriod, BudgetState, TokenUsage, UsageLedger, USAGE_FILE_NAME};
+use assistant_lib::{AssistantError, CancellationToken, ChatRequest};
+use serde_json::json;
+use support::*;
+
+// The model reported by `chat_completion`, which answers with 12 prompt and 5 completion tokens
+const MOCK_MODEL: &str = "grok-3-mini-beta";
+
+// The user's greeting, counted against `conversation_id`
+fn request(conversation_id: Option<&str>) -> ChatRequest {
+    ChatRequest { conversation_id: conversation_id.map(str::to_string), ..user_request("Chào") }
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+}
+
+#[tokio::test]
+async fn records_tokens_per_conversation_and_model() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Một"), chat_completion("Hai"), chat_completion("Ba")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({}));
+    let events = RecordingEventSink::new();
+
+    app.chat_with(request(Some("conversation-a")), &events, &CancellationToken::new()).await.unwrap();
+    app.chat_with(request(Some("conversation-a")), &events, &CancellationToken::new()).await.unwrap();
+    app.chat_with(request(Some("conversation-b")), &events, &CancellationToken::new()).await.unwrap();
+
+    // Priced with the built-in xAI table: $0.30 and $0.50 per million tokens
+    let call_cost = (12.0 * 0.30 + 5.0 * 0.50) / 1_000_000.0;
+    let report = pipeline::usage_report(&app.services.usage, None).unwrap();
+    assert_eq!((report.total.calls, report.total.prompt_tokens, report.total.completion_tokens), (3, 36, 15));
+    assert_close(report.total.cost_usd, 3.0 * call_cost);
+    assert_eq!(report.by_model.len(), 1);
+    assert_eq!(report.by_model[0].model, MOCK_MODEL);
+
+    let mut conversations: Vec<(Option<String>, u64)> = report.by_conversation.iter()
+        .map(|conversation| (conversation.conversation_id.clone(), conversation.usage.calls))
+        .collect();
+    conversations.sort();
+    assert_eq!(conversations, vec![(Some("conversation-a".to_string()), 2), (Some("conversation-b".to_string()), 1)]);
+    assert!(report.budgets.is_empty());
+    assert!(events.budget_warnings().is_empty());
+
+    // The ledger is saved and read back after a restart
+    let reopened = UsageLedger::open(&app.root.join("data"), &app.services.config.usage).unwrap();
+    assert_eq!(reopened.report(None).total, report.total);
+}
+
+#[tokio::test]
+async fn warns_then_blocks_when_the_daily_budget_is_spent() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Một"), chat_completion("Hai"), chat_completion("Ba")]).await;
+    // Each call costs 12 * $500 + 5 * $800 per million tokens = $0.01
+    let app = TestApp::with_llm(&server.url(), json!({ "usage": {
+        "prices": { MOCK_MODEL: { "prompt_per_million_usd": 500.0, "completion_per_million_usd": 800.0 } },
+        "daily_budget_usd": 0.025,
+        "warn_at_percent": 50.0
+    } }));
+    let events = RecordingEventSink::new();
+
+    app.chat_with(request(None), &events, &CancellationToken::new()).await.unwrap();
+    assert!(events.budget_warnings().is_empty());
+
+    app.chat_with(request(None), &events, &CancellationToken::new()).await.unwrap();
+    let warnings = events.budget_warnings();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!((warnings[0].period, warnings[0].state), (BudgetPeriod::Daily, BudgetState::Warning));
+    assert_close(warnings[0].spent_usd, 0.02);
+
+    app.chat_with(request(None), &events, &CancellationToken::new()).await.unwrap();
+    assert_eq!(events.budget_warnings().last().unwrap().state, BudgetState::Exceeded);
+
+    // The next call is blocked without reaching the provider
+    let error = app.chat_with(request(None), &events, &CancellationToken::new()).await.unwrap_err();
+    assert!(matches!(error, AssistantError::BudgetExceeded { period: BudgetPeriod::Daily, .. }), "{}", error);
+    assert_eq!(serde_json::to_value(&error).unwrap()["kind"], "budget_exceeded");
+    assert_eq!(server.requests().len(), 3);
+    assert_eq!(events.budget_warnings().len(), 2);
+}
+
+#[test]
+fn concurrent_calls_warn_once_per_budget_state() {
+    // Each call costs $0.01 of a $0.10 budget, so sixteen calls pass the warning share and spend it
+    let app = TestApp::start(json!({ "usage": {
+        "prices": { MOCK_MODEL: { "prompt_per_million_usd": 500.0, "completion_per_million_usd": 800.0 } },
+        "daily_budget_usd": 0.1,
+        "warn_at_percent": 45.0
+    } }));
+    let data_dir = app.root.join("data");
+    let ledger = UsageLedger::open(&data_dir, &app.services.config.usage).unwrap();
+    let events = RecordingEventSink::new();
+
+    std::thread::scope(|scope| {
+        for _ in 0..16 {
+            scope.spawn(|| {
+                let tokens = TokenUsage { prompt_tokens: 12, completion_tokens: 5, reasoning_tokens: 0 };
+                ledger.record(None, MOCK_MODEL, tokens, &events);
+            });
+        }
+    });
+
+    // The calls that crossed each threshold may emit in either order
+    let warnings = events.budget_warnings();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    for state in [BudgetState::Warning, BudgetState::Exceeded] {
+        assert_eq!(warnings.iter().filter(|warning| warning.state == state).count(), 1, "{:?}", warnings);
+    }
+    let saved = std::fs::read_to_string(data_dir.join(USAGE_FILE_NAME)).unwrap();
+    assert_eq!(saved.lines().count(), 16);
+}
+
+#[tokio::test]
+async fn budgets_only_count_the_current_period() {
+    let app = TestApp::start(json!({ "usage": { "monthly_budget_usd": 1.0 } }));
+    let data_dir = app.root.join("data");
+
+    // A costly call 40 days ago, a cheap one today and a line cut off by a crash
+    let now = chrono::Local::now().fixed_offset();
+    let record = |timestamp: chrono::DateTime<chrono::FixedOffset>, cost: f64| {
+        json!({ "timestamp": timestamp, "model": MOCK_MODEL, "prompt_tokens": 10, "completion_tokens": 10, "reasoning_tokens": 0, "cost_usd": cost }).to_string()
+    };
+    let lines = [record(now - chrono::Duration::days(40), 100.0), record(now, 0.5), "{\"timestamp\":".to_string()];
+    std::fs::write(data_dir.join(USAGE_FILE_NAME), lines.join("\n")).unwrap();
+
+    let ledger = UsageLedger::open(&data_dir, &app.services.config.usage).unwrap();
+    ledger.check_budget().unwrap();
+    let budgets = ledger.budgets();
+    assert_eq!(budgets.len(), 1);
+    assert_eq!((budgets[0].period, budgets[0].state), (BudgetPeriod::Monthly, BudgetState::Ok));
+    assert_close(budgets[0].spent_usd, 0.5);
+
+    assert_close(ledger.report(None).total.cost_usd, 100.5);
+    let recent = pipeline::usage_report(&ledger, Some(24.0)).unwrap();
+    assert_eq!(recent.total.calls, 1);
+    assert!(pipeline::usage_report(&ledger, Some(0.0)).is_err());
+}
+
+#[tokio::test]
+async fn calls_of_unpriced_models_are_counted_without_cost() {
+    let server = MockServer::start(LLM_PATH, vec![chat_completion("Miễn phí")]).await;
+    let app = TestApp::with_llm(&server.url(), json!({ "usage": { "prices": {}, "daily_budget_usd": 0.000001 } }));
+
+    app.chat(request(None)).await.unwrap();
+
+    let report = pipeline::usage_report(&app.services.usage, None).unwrap();
+    assert_eq!((report.total.calls, report.total.unpriced_calls), (1, 1));
+    assert_eq!(report.total.cost_usd, 0.0);
+    assert_eq!(report.budgets[0].state, BudgetState::Ok);
+}
+
+#[test]
+fn counts_reasoning_tokens_as_completion_tokens() {
+    // xAI reports reasoning tokens outside of completion_tokens, OpenAI inside
+    let xai = json!({ "usage": { "prompt_tokens": 10, "completion_tokens": 4, "total_tokens": 30, "completion_tokens_details": { "reasoning_tokens": 16 } } });
+    let openai = json!({ "usage": { "prompt_tokens": 10, "completion_tokens": 20, "total_tokens": 30, "completion_tokens_details": { "reasoning_tokens": 16 } } });
+    let expected = TokenUsage { prompt_tokens: 10, completion_tokens: 20, reasoning_tokens: 16 };
+
+    assert_eq!(TokenUsage::from_completion(&xai), Some(expected.clone()));
+    assert_eq!(TokenUsage::from_completion(&openai), Some(expected));
+    assert_eq!(TokenUsage::from_completion(&json!({ "choices": [] })), None);
+}
@@ -0,0 +1,88 @@
+// Integration tests of the workflow log: the text format and the stages of tracing spans
+mod support;
+
+use std::time::Duration;
+
+use assistant_lib::workflow_logger::{RecordStatus, WorkflowEventType, WorkflowTimings};
+use serde_json::json;
+use support::*;
+
+#[test]
+fn reads_back_attributes_holding_the_separator() {
+    let app = TestApp::start(json!({ "logging": { "workflow_log_format": "text" } }));
+    let timings = WorkflowTimings::with_sink("chat", app.services.workflow_log.clone());
+    let mut stage = timings.stage("LLM");
+    stage.set_attribute("prompt", "Có | không?");
+    stage.set_attribute("tags", json!(["a|b", "c"]));
+    stage.set_attribute("attempts", 2);
+    stage.succeed();
+    drop(timings);
+
+    let records = app.workflow_records();
+    let stage = records.iter().find(|record| record.event_type == WorkflowEventType::Stage).unwrap();
+    assert_eq!(stage.stage.as_deref(), Some("LLM"));
+    assert_eq!(stage.status, Some(RecordStatus::Ok));
+    assert_eq!(stage.attributes["prompt"], "Có | không?");
+    assert_eq!(stage.attributes["tags"], json!(["a|b", "c"]));
+    assert_eq!(stage.attributes["attempts"], 2);
+}
+
+#[test]
+fn each_app_records_the_spans_of_its_own_workflows() {
+    for parser in ["first", "second"] {
+        let app = TestApp::start(json!({}));
+        let timings = WorkflowTimings::with_sink("chat", app.services.workflow_log.clone());
+        let stage = timings.stage("LLM");
+        stage.span().in_scope(|| tracing::info_span!("parse_reply", parser).in_scope(|| {}));
+        stage.succeed();
+        drop(timings);
+
+        let records = app.workflow_records();
+        let child = records.iter().find(|record| record.stage.as_deref() == Some("parse_reply")).expect("no child stage");
+        assert_eq!(child.attributes["parser"], parser);
+    }
+}
+
+#[tokio::test]
+async fn records_nested_stages_with_their_outcomes() {
+    let app = TestApp::start(json!({}));
+    let timings = WorkflowTimings::with_sink("chat", app.services.workflow_log.clone());
+
+    let llm = timings.stage("LLM");
+    let llm_span = llm.span_id();
+    let tool = llm.child("tool:web_search");
+    drop(llm.child("tool:weather")); // Dropped without an outcome
+    // Guards can move to another task and be held across `.await`
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        tool.succeed();
+    }).await.unwrap();
+    llm.fail("upstream said no".to_string());
+
+    // A stage of a future that is dropped before it finishes
+    let tts = async {
+        let _stage = timings.stage("TTS");
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    };
+    assert!(tokio::time::timeout(Duration::from_millis(20), tts).await.is_err());
+    timings.finalize_and_log("Completed.");
+
+    let records = app.workflow_records();
+    let stage = |name: &str| records.iter().find(|record| record.stage.as_deref() == Some(name)).unwrap_or_else(|| panic!("no {} stage", name));
+    assert!(records.iter().all(|record| record.workflow_id == records[0].workflow_id));
+
+    let tool = stage("tool:web_search");
+    assert_eq!((tool.parent_span_id, tool.status), (Some(llm_span), Some(RecordStatus::Ok)));
+    assert!(tool.duration_us.unwrap() >= 20_000, "{:?}", tool.duration_us);
+
+    let weather = stage("tool:weather");
+    assert_eq!((weather.parent_span_id, weather.status), (Some(llm_span), Some(RecordStatus::Error)));
+    assert_eq!(weather.error_kind.as_deref(), Some("cancelled"));
+
+    let llm = stage("LLM");
+    assert_eq!((llm.span_id, llm.parent_span_id, llm.status), (Some(llm_span), None, Some(RecordStatus::Error)));
+    assert_eq!(llm.error.as_deref(), Some("upstream said no"));
+
+    let tts = stage("TTS");
+    assert_eq!((tts.status, tts.error_kind.as_deref()), (Some(RecordStatus::Error), Some("cancelled")));
+}
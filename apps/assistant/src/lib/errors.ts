@@ -0,0 +1,37 @@
+// Errors returned by the Tauri commands (AssistantError in src-tauri/src/error.rs)
+
+export type AssistantErrorKind =
+  | 'service_unavailable'
+  | 'auth'
+  | 'rate_limited'
+  | 'invalid_input'
+  | 'timeout'
+  | 'cancelled'
+  | 'budget_exceeded'
+  | 'upstream'
+  | 'invalid_response'
+  | 'io'
+  | 'internal';
+
+export interface AssistantError {
+  kind: AssistantErrorKind;
+  message: string; // Vietnamese, shown to the user
+  detail: string; // Technical message, for the console
+  service?: string;
+  status?: number; // upstream
+  retry_after_secs?: number; // rate_limited
+  timeout_secs?: number; // timeout
+}
+
+export function isAssistantError(e: unknown): e is AssistantError {
+  return typeof e === 'object' && e !== null && 'kind' in e && 'message' in e;
+}
+
+// The text to show for an error thrown by invoke() or by the browser
+export function errorMessage(e: unknown): string {
+  if (isAssistantError(e)) {
+    console.error(`[${e.kind}] ${e.detail}`);
+    return e.message;
+  }
+  return e instanceof Error ? e.message : String(e);
+}